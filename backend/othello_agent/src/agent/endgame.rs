@@ -0,0 +1,267 @@
+use crate::gameplay::bitboard::{ indices_from_mask, Bitboard };
+use crate::gameplay::constants::NULL_MOVE_INDEX;
+use crate::gameplay::game::{ GameResult, IBoard, IPlayer };
+
+/// Number of empty squares below which agents that opt in hand over to the solver.
+pub const DEFAULT_ENDGAME_EMPTIES: u32 = 12;
+/// Below this many empties move ordering costs more than it saves.
+const ORDERING_MIN_EMPTIES: u32 = 6;
+const INFINITY: i32 = 65;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EndgameSolution {
+    /// Piece index of the best move, None if the side to move has to pass or the game is over.
+    pub best_move: Option<usize>,
+    /// Final score of the side to move minus the opponent's, assuming perfect play. Scores
+    /// are those of `GameResult`, with the empty squares given to the winner, so this is the
+    /// difference of the `IGame::result` scores.
+    pub disc_difference: i32,
+    pub nodes: u64,
+}
+
+///
+/// Solves the position exactly. Runtime grows exponentially with the number of empty squares,
+/// so callers should use `solve_within` unless they know the position is late enough.
+///
+/// # Arguments
+///
+/// * `board` - The position to solve.
+/// * `player` - The side to move.
+pub fn solve(board: IBoard, player: IPlayer) -> EndgameSolution {
+    solve_bitboard(&Bitboard::from_board(board), player)
+}
+
+///
+/// Solves the position if it has at most `max_empties` empty squares.
+pub fn solve_within(board: IBoard, player: IPlayer, max_empties: u32) -> Option<EndgameSolution> {
+    let bitboard = Bitboard::from_board(board);
+    if bitboard.count_empty() > max_empties {
+        return None;
+    }
+    Some(solve_bitboard(&bitboard, player))
+}
+
+///
+/// Difference between the final scores of `player` and the opponent on a finished board, with
+/// the empty squares given to the winner as `GameResult` does.
+pub fn final_disc_difference(board: &Bitboard, player: IPlayer) -> i32 {
+    let result = GameResult::from_disc_counts(board.count(0) as u8, board.count(1) as u8);
+    (result.discs_for_player(player) as i32) - (result.discs_for_player(1 - player) as i32)
+}
+
+pub fn solve_bitboard(board: &Bitboard, player: IPlayer) -> EndgameSolution {
+    let mut solver = Solver { nodes: 0 };
+    // moves are made and taken back on this board as the solver walks the tree
+    let mut board = *board;
+    let moves = board.legal_moves(player);
+    if moves == 0 {
+        let disc_difference = solver.negamax(&mut board, player, -INFINITY, INFINITY, false);
+        return EndgameSolution {
+            best_move: None,
+            disc_difference,
+            nodes: solver.nodes,
+        };
+    }
+    let mut alpha = -INFINITY;
+    let mut best_move = None;
+    for index in solver.ordered_moves(&board, player, moves) {
+        let flips = board.make_move(index, player);
+        let score = -solver.negamax(&mut board, 1 - player, -INFINITY, -alpha, false);
+        board.unmake_move(index, player, flips);
+        if best_move.is_none() || score > alpha {
+            alpha = score;
+            best_move = Some(index);
+        }
+    }
+    EndgameSolution {
+        best_move,
+        disc_difference: alpha,
+        nodes: solver.nodes,
+    }
+}
+
+///
+/// Action an agent should take if the endgame solver applies, for agents that opted in with a
+/// maximum number of empties. Returns `NULL_MOVE_INDEX` when the side to move has to pass.
+pub fn endgame_action(board: IBoard, player: IPlayer, max_empties: Option<u32>) -> Option<usize> {
+    let solution = solve_within(board, player, max_empties?)?;
+    Some(solution.best_move.unwrap_or(NULL_MOVE_INDEX))
+}
+
+struct Solver {
+    nodes: u64,
+}
+
+impl Solver {
+    fn negamax(
+        &mut self,
+        board: &mut Bitboard,
+        player: IPlayer,
+        mut alpha: i32,
+        beta: i32,
+        passed: bool
+    ) -> i32 {
+        self.nodes += 1;
+        let moves = board.legal_moves(player);
+        if moves == 0 {
+            if passed {
+                // neither player can move, the game is over
+                return final_disc_difference(board, player);
+            }
+            return -self.negamax(board, 1 - player, -beta, -alpha, true);
+        }
+        let mut best = -INFINITY;
+        for index in self.ordered_moves(board, player, moves) {
+            let flips = board.make_move(index, player);
+            let score = -self.negamax(board, 1 - player, -beta, -alpha, false);
+            board.unmake_move(index, player, flips);
+            if score > best {
+                best = score;
+            }
+            if best > alpha {
+                alpha = best;
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+        best
+    }
+
+    /// Fastest-first ordering: try moves that leave the opponent with the fewest replies.
+    fn ordered_moves(&self, board: &Bitboard, player: IPlayer, moves: u64) -> Vec<usize> {
+        let indices = indices_from_mask(moves);
+        if board.count_empty() < ORDERING_MIN_EMPTIES {
+            return indices;
+        }
+        let mut scored: Vec<(u32, usize)> = indices
+            .into_iter()
+            .map(|index| {
+                let next = board.play(index, player).expect("Expected a legal move");
+                (next.legal_moves(1 - player).count_ones(), index)
+            })
+            .collect();
+        scored.sort();
+        scored
+            .into_iter()
+            .map(|(_, index)| index)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{ rngs::StdRng, seq::SliceRandom, SeedableRng };
+    use rl_examples::agents::agent::Agent;
+
+    use crate::{
+        agent::rule_based::RuleAgent,
+        gameplay::{
+            bitboard::{ indices_from_mask, Bitboard },
+            constants::NULL_MOVE_INDEX,
+            game::{ IGame, IPlayer, Move },
+        },
+    };
+
+    use super::{ final_disc_difference, solve_bitboard, solve_within };
+
+    /// Exhaustive minimax over the remaining moves.
+    fn brute_force(board: &Bitboard, player: IPlayer) -> i32 {
+        let moves = indices_from_mask(board.legal_moves(player));
+        if moves.is_empty() {
+            if !board.has_move(1 - player) {
+                return final_disc_difference(board, player);
+            }
+            return -brute_force(board, 1 - player);
+        }
+        moves
+            .iter()
+            .map(|&index| -brute_force(&board.play(index, player).unwrap(), 1 - player))
+            .max()
+            .unwrap()
+    }
+
+    /// Plays random moves until at most `empties` squares are left.
+    fn random_late_position(rng: &mut StdRng, empties: u32) -> Option<(Bitboard, IPlayer)> {
+        let mut board = Bitboard::new();
+        let mut player: IPlayer = 0;
+        while board.count_empty() > empties {
+            let moves = indices_from_mask(board.legal_moves(player));
+            match moves.choose(rng) {
+                Some(&index) => {
+                    board = board.play(index, player).unwrap();
+                }
+                None if !board.has_move(1 - player) => {
+                    return None;
+                }
+                None => {}
+            }
+            player = 1 - player;
+        }
+        Some((board, player))
+    }
+
+    #[test]
+    fn agrees_with_brute_force() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut solved = 0;
+        while solved < 30 {
+            let Some((board, player)) = random_late_position(&mut rng, 7) else {
+                continue;
+            };
+            let solution = solve_bitboard(&board, player);
+            assert_eq!(solution.disc_difference, brute_force(&board, player));
+            if let Some(index) = solution.best_move {
+                let next = board.play(index, player).unwrap();
+                assert_eq!(-brute_force(&next, 1 - player), solution.disc_difference);
+            } else {
+                assert!(!board.has_move(player));
+            }
+            solved += 1;
+        }
+    }
+
+    #[test]
+    fn gives_the_empty_squares_to_the_winner() {
+        // black wipes out white with 51 squares still empty
+        let moves = [19, 18, 17, 11, 4, 43, 51, 20, 29];
+        let game = IGame::from_moves(&moves.map(|index| Move::from_index(index).unwrap())).unwrap();
+        let result = game.result().unwrap();
+        let board = Bitboard::from_board(game.board);
+        let solution = solve_bitboard(&board, game.turn);
+        assert_eq!(solution.best_move, None);
+        let difference = (result.white_discs as i32) - (result.black_discs as i32);
+        assert_eq!((game.turn, solution.disc_difference), (1, difference));
+        assert_eq!(final_disc_difference(&board, 0), 64);
+    }
+
+    #[test]
+    fn respects_empty_threshold() {
+        let board = Bitboard::new().to_board();
+        assert!(solve_within(board, 0, 12).is_none());
+        let mut rng = StdRng::seed_from_u64(9);
+        let (late, player) = loop {
+            if let Some(position) = random_late_position(&mut rng, 10) {
+                break position;
+            }
+        };
+        assert!(solve_within(late.to_board(), player, 12).is_some());
+    }
+
+    #[test]
+    fn rule_agent_switches_to_solver() {
+        let mut rng = StdRng::seed_from_u64(13);
+        let mut checked = 0;
+        while checked < 10 {
+            let Some((board, player)) = random_late_position(&mut rng, 8) else {
+                continue;
+            };
+            let mut agent = RuleAgent::new(player, board.to_board());
+            agent.set_endgame_solver(Some(8));
+            let action = agent.select_action();
+            let solution = solve_bitboard(&board, player);
+            assert_eq!(action, solution.best_move.unwrap_or(NULL_MOVE_INDEX));
+            checked += 1;
+        }
+    }
+}
@@ -0,0 +1,569 @@
+use burn::tensor::{ backend::Backend, Device, Float, Tensor };
+use rand::{ rngs::StdRng, seq::SliceRandom, SeedableRng };
+use rl_examples::agents::agent::Agent;
+
+use crate::agent::endgame::endgame_action;
+use crate::gameplay::bitboard::{ indices_from_mask, Bitboard };
+use crate::gameplay::constants::NULL_MOVE_INDEX;
+use crate::gameplay::game::{ board_to_ml_input, IBoard, IGame, IPlayer };
+use crate::gameplay::recommender::suggest_moves_rules_based;
+use crate::model::model::Model;
+
+///
+/// Estimates the value of a position for the side to move, in [-1, 1].
+pub trait RolloutPolicy {
+    fn evaluate(&mut self, board: &Bitboard, player: IPlayer) -> f32;
+}
+
+///
+/// Prior probabilities for the legal moves of a position, used for PUCT selection.
+/// The returned vector must have one entry per move, in the same order.
+pub trait PriorPolicy {
+    fn priors(&mut self, board: &Bitboard, player: IPlayer, moves: &[usize]) -> Vec<f32>;
+}
+
+/// Value of a finished game for `player`: 1 for a win, -1 for a loss and 0 for a draw.
+pub fn terminal_value(board: &Bitboard, player: IPlayer) -> f32 {
+    let own = board.count(player);
+    let opponent = board.count(1 - player);
+    if own > opponent {
+        1.0
+    } else if own < opponent {
+        -1.0
+    } else {
+        0.0
+    }
+}
+
+///
+/// Plays the game out with `choose` picking each move, then scores the final position.
+fn play_out<F>(board: &Bitboard, player: IPlayer, mut choose: F) -> f32
+    where F: FnMut(&Bitboard, IPlayer, Vec<usize>) -> usize
+{
+    let mut board = *board;
+    let mut current = player;
+    loop {
+        let moves = indices_from_mask(board.legal_moves(current));
+        if moves.is_empty() {
+            if !board.has_move(1 - current) {
+                return terminal_value(&board, player);
+            }
+        } else {
+            let index = choose(&board, current, moves);
+            board = board.play(index, current).expect("Expected a legal move");
+        }
+        current = 1 - current;
+    }
+}
+
+/// Plays uniformly random legal moves until the game ends.
+pub struct RandomRollout {
+    rng: StdRng,
+}
+
+impl RandomRollout {
+    /// Plays out with moves drawn from `rng`, seed it to get the same search again.
+    pub fn new(rng: StdRng) -> Self {
+        RandomRollout { rng }
+    }
+}
+
+impl Default for RandomRollout {
+    fn default() -> Self {
+        RandomRollout::new(StdRng::from_entropy())
+    }
+}
+
+impl RolloutPolicy for RandomRollout {
+    fn evaluate(&mut self, board: &Bitboard, player: IPlayer) -> f32 {
+        let rng = &mut self.rng;
+        play_out(board, player, |_, _, moves| *moves.choose(rng).unwrap())
+    }
+}
+
+/// Plays moves suggested by `suggest_moves_rules_based` until the game ends.
+pub struct RuleRollout {
+    rng: StdRng,
+}
+
+impl RuleRollout {
+    /// Picks between suggested moves with `rng`, seed it to get the same search again.
+    pub fn new(rng: StdRng) -> Self {
+        RuleRollout { rng }
+    }
+}
+
+impl Default for RuleRollout {
+    fn default() -> Self {
+        RuleRollout::new(StdRng::from_entropy())
+    }
+}
+
+impl RolloutPolicy for RuleRollout {
+    fn evaluate(&mut self, board: &Bitboard, player: IPlayer) -> f32 {
+        let rng = &mut self.rng;
+        play_out(board, player, |board, current, _| {
+            suggest_moves_rules_based(board.to_board(), current)
+                .choose(rng)
+                .expect("Expected a suggested move")
+                .to_piece_index()
+        })
+    }
+}
+
+///
+/// Plays `cutoff_depth` random moves and then asks the win/loss/draw model who is ahead.
+pub struct ValueNetworkRollout<B: Backend> {
+    model: Model<B>,
+    device: Device<B>,
+    cutoff_depth: usize,
+    rng: StdRng,
+}
+
+impl<B: Backend> ValueNetworkRollout<B> {
+    pub fn new(model: Model<B>, device: Device<B>, cutoff_depth: usize) -> Self {
+        ValueNetworkRollout {
+            model,
+            device,
+            cutoff_depth,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Replaces the source of the random moves, seed it to get the same search again.
+    pub fn set_rng(&mut self, rng: StdRng) {
+        self.rng = rng;
+    }
+}
+
+impl<B: Backend> RolloutPolicy for ValueNetworkRollout<B> {
+    fn evaluate(&mut self, board: &Bitboard, player: IPlayer) -> f32 {
+        let mut board = *board;
+        let mut current = player;
+        for _ in 0..self.cutoff_depth {
+            let moves = indices_from_mask(board.legal_moves(current));
+            if let Some(&index) = moves.choose(&mut self.rng) {
+                board = board.play(index, current).expect("Expected a legal move");
+            } else if !board.has_move(1 - current) {
+                return terminal_value(&board, player);
+            }
+            current = 1 - current;
+        }
+        if !board.has_move(0) && !board.has_move(1) {
+            return terminal_value(&board, player);
+        }
+        let input = Tensor::<B, 2, Float>
+            ::from_floats(board_to_ml_input(board.to_board()), &self.device)
+            .reshape([1, 8, 8]);
+        let output = burn::tensor::activation::softmax(self.model.forward(input), 1);
+        let output = output.into_data().convert::<f32>().value;
+        // output is (player 0 wins, player 1 wins, draw)
+        let (own, opponent) = if player == 0 {
+            (output[0], output[1])
+        } else {
+            (output[1], output[0])
+        };
+        own - opponent
+    }
+}
+
+///
+/// Gives every move suggested by the rule-based recommender the same share of `weight`, and
+/// spreads the rest evenly over all legal moves.
+pub struct RulePrior {
+    pub weight: f32,
+}
+
+impl PriorPolicy for RulePrior {
+    fn priors(&mut self, board: &Bitboard, player: IPlayer, moves: &[usize]) -> Vec<f32> {
+        let suggested: Vec<usize> = suggest_moves_rules_based(board.to_board(), player)
+            .iter()
+            .map(|position| position.to_piece_index())
+            .collect();
+        let uniform = (1.0 - self.weight) / (moves.len() as f32);
+        let bonus = self.weight / (suggested.len().max(1) as f32);
+        moves
+            .iter()
+            .map(|index| if suggested.contains(index) { uniform + bonus } else { uniform })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MctsConfig {
+    pub iterations: u32,
+    /// Exploration constant. Used as `c` in UCT, or `c_puct` when a prior policy is set.
+    pub exploration: f32,
+}
+
+impl Default for MctsConfig {
+    fn default() -> Self {
+        MctsConfig {
+            iterations: 1000,
+            exploration: std::f32::consts::SQRT_2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MoveVisits {
+    /// Piece index of the move, `NULL_MOVE_INDEX` for a pass.
+    pub move_index: usize,
+    pub visits: u32,
+    /// Mean value of the move for the side to move at the root, in [-1, 1].
+    pub mean_value: f32,
+    pub prior: f32,
+}
+
+struct Node {
+    board: Bitboard,
+    // side to move at this node
+    player: IPlayer,
+    parent: Option<usize>,
+    // move that led here from the parent
+    move_index: usize,
+    children: Vec<usize>,
+    expanded: bool,
+    prior: f32,
+    visits: u32,
+    // accumulated from the point of view of the parent's side to move
+    value_sum: f32,
+}
+
+impl Node {
+    fn new(board: Bitboard, player: IPlayer, parent: Option<usize>, move_index: usize, prior: f32) -> Node {
+        Node {
+            board,
+            player,
+            parent,
+            move_index,
+            children: Vec::new(),
+            expanded: false,
+            prior,
+            visits: 0,
+            value_sum: 0.0,
+        }
+    }
+
+    fn is_terminal(&self) -> bool {
+        !self.board.has_move(self.player) && !self.board.has_move(1 - self.player)
+    }
+
+    fn mean_value(&self) -> f32 {
+        if self.visits == 0 { 0.0 } else { self.value_sum / (self.visits as f32) }
+    }
+}
+
+///
+/// Runs UCT (or PUCT when `prior_policy` is given) from the position and returns visit
+/// statistics for every root move, most visited first.
+///
+/// # Arguments
+///
+/// * `board` - The position to search.
+/// * `player` - The side to move.
+/// * `config` - Iteration count and exploration constant.
+/// * `rollout_policy` - Evaluates leaf positions.
+/// * `prior_policy` - Optional move priors, switches selection to PUCT.
+pub fn run_mcts(
+    board: &Bitboard,
+    player: IPlayer,
+    config: &MctsConfig,
+    rollout_policy: &mut dyn RolloutPolicy,
+    mut prior_policy: Option<&mut dyn PriorPolicy>
+) -> Vec<MoveVisits> {
+    let use_priors = prior_policy.is_some();
+    let mut nodes = vec![Node::new(*board, player, None, NULL_MOVE_INDEX, 1.0)];
+    for _ in 0..config.iterations {
+        // selection
+        let mut current = 0;
+        while nodes[current].expanded && !nodes[current].children.is_empty() {
+            current = select_child(&nodes, current, config.exploration, use_priors);
+        }
+        // expansion
+        if !nodes[current].is_terminal() {
+            expand(&mut nodes, current, &mut prior_policy);
+            current = select_child(&nodes, current, config.exploration, use_priors);
+        }
+        // evaluation, from the point of view of the side to move at the leaf
+        let leaf = &nodes[current];
+        let leaf_player = leaf.player;
+        let value = if leaf.is_terminal() {
+            terminal_value(&leaf.board, leaf_player)
+        } else {
+            rollout_policy.evaluate(&leaf.board, leaf_player)
+        };
+        // backpropagation
+        let mut next = Some(current);
+        while let Some(index) = next {
+            let parent = nodes[index].parent;
+            if let Some(parent_index) = parent {
+                let mover = nodes[parent_index].player;
+                nodes[index].value_sum += if mover == leaf_player { value } else { -value };
+            }
+            nodes[index].visits += 1;
+            next = parent;
+        }
+    }
+    let mut visits: Vec<MoveVisits> = nodes[0].children
+        .iter()
+        .map(|&child| MoveVisits {
+            move_index: nodes[child].move_index,
+            visits: nodes[child].visits,
+            mean_value: nodes[child].mean_value(),
+            prior: nodes[child].prior,
+        })
+        .collect();
+    visits.sort_by_key(|visit| std::cmp::Reverse(visit.visits));
+    visits
+}
+
+fn expand(nodes: &mut Vec<Node>, index: usize, prior_policy: &mut Option<&mut dyn PriorPolicy>) {
+    let board = nodes[index].board;
+    let player = nodes[index].player;
+    let moves = indices_from_mask(board.legal_moves(player));
+    nodes[index].expanded = true;
+    if moves.is_empty() {
+        // forced pass, the position is unchanged and the opponent moves
+        let child = nodes.len();
+        nodes.push(Node::new(board, 1 - player, Some(index), NULL_MOVE_INDEX, 1.0));
+        nodes[index].children.push(child);
+        return;
+    }
+    let priors = match prior_policy {
+        Some(policy) => policy.priors(&board, player, &moves),
+        None => vec![1.0 / (moves.len() as f32); moves.len()],
+    };
+    for (&move_index, &prior) in moves.iter().zip(priors.iter()) {
+        let next = board.play(move_index, player).expect("Expected a legal move");
+        let child = nodes.len();
+        nodes.push(Node::new(next, 1 - player, Some(index), move_index, prior));
+        nodes[index].children.push(child);
+    }
+}
+
+fn select_child(nodes: &[Node], index: usize, exploration: f32, use_priors: bool) -> usize {
+    let parent_visits = nodes[index].visits.max(1) as f32;
+    let mut best_child = nodes[index].children[0];
+    let mut best_score = f32::NEG_INFINITY;
+    for &child in nodes[index].children.iter() {
+        let node = &nodes[child];
+        let score = if use_priors {
+            node.mean_value() +
+                (exploration * node.prior * parent_visits.sqrt()) / (1.0 + (node.visits as f32))
+        } else if node.visits == 0 {
+            f32::INFINITY
+        } else {
+            node.mean_value() +
+                exploration * (parent_visits.ln() / (node.visits as f32)).sqrt()
+        };
+        if score > best_score {
+            best_score = score;
+            best_child = child;
+        }
+    }
+    best_child
+}
+
+pub struct MctsAgent {
+    player: IPlayer,
+    last_board: IBoard,
+    config: MctsConfig,
+    rollout_policy: Box<dyn RolloutPolicy>,
+    prior_policy: Option<Box<dyn PriorPolicy>>,
+    last_visits: Vec<MoveVisits>,
+    // switch to the exact endgame solver at or below this many empty squares
+    endgame_empties: Option<u32>,
+}
+
+impl MctsAgent {
+    pub fn new(player: IPlayer, board: IBoard) -> MctsAgent {
+        let rollout_policy = Box::new(RandomRollout::default());
+        MctsAgent::with_config(player, board, MctsConfig::default(), rollout_policy)
+    }
+
+    pub fn with_config(
+        player: IPlayer,
+        board: IBoard,
+        config: MctsConfig,
+        rollout_policy: Box<dyn RolloutPolicy>
+    ) -> MctsAgent {
+        MctsAgent {
+            player,
+            last_board: board,
+            config,
+            rollout_policy,
+            prior_policy: None,
+            last_visits: Vec::new(),
+            endgame_empties: None,
+        }
+    }
+
+    pub fn update_board(&mut self, board: IBoard) {
+        self.last_board = board;
+    }
+
+    pub fn update_player(&mut self, player: IPlayer) {
+        self.player = player;
+    }
+
+    /// Takes both the board and the side to move from a game.
+    pub fn update_game(&mut self, game: &IGame) {
+        self.last_board = game.board;
+        self.player = game.turn;
+    }
+
+    pub fn get_player(&self) -> IPlayer {
+        self.player
+    }
+
+    ///
+    /// Plays rollouts with a `RandomRollout` drawing from `rng`, the rollout policy of `new`.
+    /// Seed it to replay the same searches. Replaces a rollout policy given to `with_config`.
+    pub fn set_rng(&mut self, rng: StdRng) {
+        self.rollout_policy = Box::new(RandomRollout::new(rng));
+    }
+
+    /// Switches selection from UCT to PUCT using the given priors. Pass None to go back to UCT.
+    pub fn set_prior_policy(&mut self, prior_policy: Option<Box<dyn PriorPolicy>>) {
+        self.prior_policy = prior_policy;
+    }
+
+    ///
+    /// Opts into the exact endgame solver once the board has at most `max_empties` empty squares.
+    /// Pass None to always search.
+    pub fn set_endgame_solver(&mut self, max_empties: Option<u32>) {
+        self.endgame_empties = max_empties;
+    }
+
+    ///
+    /// Visit statistics of the root moves from the most recent search, most visited first.
+    /// The share of visits a move received is a measure of the agent's confidence in it.
+    pub fn get_visit_counts(&self) -> &[MoveVisits] {
+        &self.last_visits
+    }
+}
+
+impl Agent for MctsAgent {
+    fn select_action(&mut self) -> usize {
+        self.last_visits.clear();
+        if let Some(action) = endgame_action(self.last_board, self.player, self.endgame_empties) {
+            return action;
+        }
+        let board = Bitboard::from_board(self.last_board);
+        if !board.has_move(self.player) {
+            return NULL_MOVE_INDEX;
+        }
+        let prior_policy: Option<&mut dyn PriorPolicy> = match self.prior_policy.as_mut() {
+            Some(policy) => Some(policy.as_mut()),
+            None => None,
+        };
+        self.last_visits = run_mcts(
+            &board,
+            self.player,
+            &self.config,
+            self.rollout_policy.as_mut(),
+            prior_policy
+        );
+        self.last_visits[0].move_index
+    }
+
+    fn take_action(&mut self, _action: usize) -> f64 {
+        0.0
+    }
+
+    fn update_estimate(&mut self, _state: String, _action: usize, _reward: f64, _is_terminal: bool) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{ rngs::StdRng, SeedableRng };
+    use rl_examples::agents::agent::Agent;
+
+    use crate::gameplay::{
+        bitboard::{ indices_from_mask, Bitboard },
+        constants::{ INITIAL_BOARD, NULL_MOVE_INDEX },
+    };
+
+    use super::{ run_mcts, MctsAgent, MctsConfig, RandomRollout, RulePrior, RuleRollout };
+
+    #[test]
+    fn visit_counts_cover_every_iteration() {
+        let config = MctsConfig {
+            iterations: 200,
+            ..MctsConfig::default()
+        };
+        let mut rollout = RandomRollout::default();
+        let visits = run_mcts(&Bitboard::new(), 0, &config, &mut rollout, None);
+        assert_eq!(visits.len(), 4);
+        assert_eq!(visits.iter().map(|v| v.visits).sum::<u32>(), 200);
+        assert!(visits.windows(2).all(|pair| pair[0].visits >= pair[1].visits));
+
+        let mut prior = RulePrior { weight: 0.5 };
+        let mut rollout = RuleRollout::default();
+        let visits = run_mcts(&Bitboard::new(), 0, &config, &mut rollout, Some(&mut prior));
+        assert_eq!(visits.iter().map(|v| v.visits).sum::<u32>(), 200);
+        assert!((visits.iter().map(|v| v.prior).sum::<f32>() - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn seeded_rollouts_repeat_the_search() {
+        let config = MctsConfig {
+            iterations: 100,
+            ..MctsConfig::default()
+        };
+        let mut first = RandomRollout::new(StdRng::seed_from_u64(25));
+        let mut second = RandomRollout::new(StdRng::seed_from_u64(25));
+        let board = Bitboard::new();
+        assert_eq!(
+            run_mcts(&board, 0, &config, &mut first, None),
+            run_mcts(&board, 0, &config, &mut second, None)
+        );
+    }
+
+    #[test]
+    fn seeded_agents_repeat_the_search() {
+        let visits: Vec<_> = (0..2)
+            .map(|_| {
+                let mut agent = MctsAgent::new(0, INITIAL_BOARD);
+                agent.set_rng(StdRng::seed_from_u64(4));
+                agent.select_action();
+                agent.get_visit_counts().to_vec()
+            })
+            .collect();
+        assert_eq!(visits[0], visits[1]);
+    }
+
+    #[test]
+    fn handles_passes() {
+        // black to move but only white can play: h1 white, g1 black, f1 empty
+        let mut board = [[2u8; 8]; 8];
+        board[0][7] = 1;
+        board[0][6] = 0;
+        let bitboard = Bitboard::from_board(board);
+        assert!(!bitboard.has_move(0));
+        assert!(bitboard.has_move(1));
+        let config = MctsConfig {
+            iterations: 50,
+            ..MctsConfig::default()
+        };
+        let visits = run_mcts(&bitboard, 0, &config, &mut RandomRollout::default(), None);
+        assert_eq!(visits.len(), 1);
+        assert_eq!(visits[0].move_index, NULL_MOVE_INDEX);
+        // white wins by capturing the last black piece
+        assert_eq!(visits[0].mean_value, -1.0);
+
+        let mut agent = MctsAgent::new(0, board);
+        assert_eq!(agent.select_action(), NULL_MOVE_INDEX);
+        agent.update_player(1);
+        assert_eq!(agent.select_action(), 5);
+    }
+
+    #[test]
+    fn agent_selects_legal_moves() {
+        let mut agent = MctsAgent::new(0, INITIAL_BOARD);
+        let action = agent.select_action();
+        assert!(indices_from_mask(Bitboard::new().legal_moves(0)).contains(&action));
+        assert_eq!(agent.get_visit_counts()[0].move_index, action);
+    }
+}
@@ -1,5 +1,10 @@
 pub mod rule_based;
+pub mod endgame;
 pub mod traits;
 pub mod value_estimator;
 pub mod utils;
 pub mod value_based;
+pub mod search_based;
+pub mod policy_based;
+pub mod mcts;
+pub mod transposition;
@@ -0,0 +1,131 @@
+use burn::config::Config;
+use burn::module::Module;
+use burn::record::{ FullPrecisionSettings, NamedMpkFileRecorder };
+use burn::tensor::backend::Backend;
+use burn::tensor::Device;
+use rl_examples::agents::agent::Agent;
+
+use crate::agent::endgame::endgame_action;
+use crate::gameplay::bitboard::Bitboard;
+use crate::gameplay::constants::NULL_MOVE_INDEX;
+use crate::gameplay::game::{ IBoard, IPlayer };
+use crate::model::policy::PolicyModel;
+use crate::model::train_policy::{ OthelloPolicyTrainingConfig, POLICY_ARTIFACT_DIR };
+
+///
+/// Plays the legal move the supervised policy model considers most likely.
+pub struct PolicyAgent<B: Backend> {
+    player: IPlayer,
+    current_board: IBoard,
+    model: PolicyModel<B>,
+    device: Device<B>,
+    // switch to the exact endgame solver at or below this many empty squares
+    endgame_empties: Option<u32>,
+}
+
+impl<B: Backend> PolicyAgent<B> {
+    /// Loads the model trained by the `train_policy` binary.
+    pub fn new(player: IPlayer, board: IBoard, device: Device<B>) -> PolicyAgent<B> {
+        let model = PolicyAgent::load_policy_model(&device);
+        PolicyAgent::with_model(player, board, model, device)
+    }
+
+    pub fn with_model(
+        player: IPlayer,
+        board: IBoard,
+        model: PolicyModel<B>,
+        device: Device<B>
+    ) -> PolicyAgent<B> {
+        PolicyAgent {
+            player,
+            current_board: board,
+            model,
+            device,
+            endgame_empties: None,
+        }
+    }
+
+    pub fn update_board(&mut self, board: IBoard) {
+        self.current_board = board;
+    }
+
+    pub fn update_player(&mut self, player: IPlayer) {
+        self.player = player;
+    }
+
+    pub fn get_player(&self) -> IPlayer {
+        self.player
+    }
+
+    ///
+    /// Opts into the exact endgame solver once the board has at most `max_empties` empty squares.
+    /// Pass None to always use the policy model.
+    pub fn set_endgame_solver(&mut self, max_empties: Option<u32>) {
+        self.endgame_empties = max_empties;
+    }
+
+    ///
+    /// Probability the model assigns to each legal move of the current board.
+    pub fn move_probabilities(&self) -> Vec<(usize, f32)> {
+        self.model.predict(&Bitboard::from_board(self.current_board), self.player, &self.device)
+    }
+
+    fn load_policy_model(device: &Device<B>) -> PolicyModel<B> {
+        let config = OthelloPolicyTrainingConfig::load(
+            format!("{POLICY_ARTIFACT_DIR}/config.json")
+        ).expect("Config should exist for the model");
+        let recorder = NamedMpkFileRecorder::<FullPrecisionSettings>::new();
+
+        config.model
+            .init::<B>(device)
+            .load_file(format!("{POLICY_ARTIFACT_DIR}/model"), &recorder, device)
+            .expect("Model should exist")
+    }
+}
+
+impl<B: Backend> Agent for PolicyAgent<B> {
+    fn select_action(&mut self) -> usize {
+        if let Some(action) = endgame_action(self.current_board, self.player, self.endgame_empties) {
+            return action;
+        }
+        self.move_probabilities()
+            .into_iter()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(index, _)| index)
+            .unwrap_or(NULL_MOVE_INDEX)
+    }
+
+    fn take_action(&mut self, _action: usize) -> f64 {
+        0.0
+    }
+
+    fn update_estimate(&mut self, _state: String, _action: usize, _reward: f64, _is_terminal: bool) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use burn::backend::{ ndarray::NdArrayDevice, NdArray };
+    use rl_examples::agents::agent::Agent;
+
+    use crate::{
+        gameplay::{ bitboard::Bitboard, constants::{ INITIAL_BOARD, NULL_MOVE_INDEX } },
+        model::policy::PolicyModelConfig,
+    };
+
+    use super::PolicyAgent;
+
+    #[test]
+    fn plays_legal_moves_and_passes() {
+        let device = NdArrayDevice::Cpu;
+        let model = PolicyModelConfig::new().with_channels(4).init::<NdArray>(&device);
+        let mut agent = PolicyAgent::with_model(0, INITIAL_BOARD, model, device);
+        let action = agent.select_action();
+        assert!(Bitboard::new().legal_moves(0) & (1 << action) != 0);
+
+        // black has no pieces left to flank with, so it has to pass
+        let mut board = [[2; 8]; 8];
+        board[0][0] = 1;
+        agent.update_board(board);
+        assert_eq!(agent.select_action(), NULL_MOVE_INDEX);
+    }
+}
@@ -1,7 +1,9 @@
+use crate::agent::endgame::endgame_action;
+use crate::agent::value_estimator::ValueEstimator;
 use crate::gameplay::constants::NULL_MOVE_INDEX;
 use crate::gameplay::game::{ IBoard, IPlayer };
 use crate::gameplay::position::IPosition;
-use rand::{ thread_rng, Rng };
+use rand::{ rngs::StdRng, Rng, SeedableRng };
 use rl_examples::agents::agent::Agent;
 use crate::gameplay::recommender::suggest_moves_rules_based;
 
@@ -31,6 +33,12 @@ use crate::gameplay::recommender::suggest_moves_rules_based;
 pub struct RuleAgent {
     player: IPlayer,
     last_board: IBoard,
+    // None never hands over to the endgame solver, see `set_endgame_solver`
+    endgame_empties: Option<u32>,
+    // learns from the rewards passed to update_estimate when set
+    value_estimator: Option<ValueEstimator>,
+    // picks between equally good suggested moves
+    rng: StdRng,
 }
 
 impl RuleAgent {
@@ -38,6 +46,9 @@ impl RuleAgent {
         RuleAgent {
             player,
             last_board: board,
+            endgame_empties: None,
+            value_estimator: None,
+            rng: StdRng::from_entropy(),
         }
     }
     fn suggest_moves(&self, board: IBoard) -> Vec<IPosition> {
@@ -57,18 +68,42 @@ impl RuleAgent {
         self.player
     }
 
+    ///
+    /// Opts into the exact endgame solver once the board has at most `max_empties` empty squares.
+    /// Pass None to always use the rules.
+    pub fn set_endgame_solver(&mut self, max_empties: Option<u32>) {
+        self.endgame_empties = max_empties;
+    }
+
+    ///
+    /// Attaches a TD(λ) value estimator that learns from every `update_estimate` call.
+    pub fn set_value_estimator(&mut self, value_estimator: Option<ValueEstimator>) {
+        self.value_estimator = value_estimator;
+    }
+
+    pub fn get_value_estimator(&self) -> Option<&ValueEstimator> {
+        self.value_estimator.as_ref()
+    }
+
+    /// Replaces the random choice between suggested moves, seed it to replay the same games.
+    pub fn set_rng(&mut self, rng: StdRng) {
+        self.rng = rng;
+    }
+
     fn choose_from_actions(&mut self, suggested_moves: Vec<IPosition>) -> Option<IPosition> {
         if suggested_moves.len() == 0 {
             return None;
         }
-        let mut rng = thread_rng();
-        let random_index = rng.gen_range(0..suggested_moves.len());
+        let random_index = self.rng.gen_range(0..suggested_moves.len());
         Some(suggested_moves[random_index].duplicate())
     }
 }
 
 impl Agent for RuleAgent {
     fn select_action(&mut self) -> usize {
+        if let Some(action) = endgame_action(self.last_board, self.player, self.endgame_empties) {
+            return action;
+        }
         let suggested_moves = self.suggest_moves(self.last_board);
         let res = self.choose_from_actions(suggested_moves);
         if res.is_none() {
@@ -81,5 +116,9 @@ impl Agent for RuleAgent {
         0.0
     }
 
-    fn update_estimate(&mut self, state: String, action: usize, reward: f64, _is_terminal: bool) {}
+    fn update_estimate(&mut self, state: String, _action: usize, reward: f64, is_terminal: bool) {
+        if let Some(value_estimator) = self.value_estimator.as_mut() {
+            value_estimator.update(&state, reward, is_terminal);
+        }
+    }
 }
@@ -0,0 +1,538 @@
+use std::{ sync::Arc, time::{ Duration, Instant } };
+
+use rl_examples::agents::agent::Agent;
+
+use crate::agent::endgame::{ endgame_action, final_disc_difference };
+use crate::agent::transposition::{ Bound, TableEntry, TranspositionTable };
+use crate::gameplay::bitboard::{ indices_from_mask, Bitboard, CORNER_MASK };
+use crate::gameplay::constants::{
+    DEFAULT_CORNER_SCORE,
+    DEFAULT_EDGE_SCORE,
+    DEFAULT_OTHER_SCORE,
+    NULL_MOVE_INDEX,
+};
+use crate::gameplay::game::{ IBoard, IPlayer };
+use crate::gameplay::zobrist::{ placement_delta, zobrist_hash, WHITE_TO_MOVE_KEY };
+
+/// Scores a position from the point of view of `player`. Higher is better.
+pub type Evaluator = fn(&Bitboard, IPlayer) -> i32;
+
+/// Added to the final disc differential so that any won endgame outranks any heuristic score.
+pub const TERMINAL_SCORE: i32 = 100_000;
+const INFINITY: i32 = i32::MAX - 1;
+/// How many nodes are searched between two clock reads.
+const TIME_CHECK_INTERVAL: u64 = 1024;
+
+///
+/// Default leaf evaluation: the difference between both players' `augmented_score_for_player`
+/// using the default corner, edge and other weights.
+pub fn augmented_score_evaluator(board: &Bitboard, player: IPlayer) -> i32 {
+    let own = board.augmented_score(
+        player,
+        DEFAULT_CORNER_SCORE,
+        DEFAULT_EDGE_SCORE,
+        DEFAULT_OTHER_SCORE
+    );
+    let opponent = board.augmented_score(
+        1 - player,
+        DEFAULT_CORNER_SCORE,
+        DEFAULT_EDGE_SCORE,
+        DEFAULT_OTHER_SCORE
+    );
+    (own as i32) - (opponent as i32)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SearchBudget {
+    /// Stop deepening once the wall clock budget is spent.
+    Time(Duration),
+    /// Stop deepening once this many nodes have been visited.
+    Nodes(u64),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SearchConfig {
+    pub max_depth: u8,
+    pub budget: SearchBudget,
+    pub evaluator: Evaluator,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            max_depth: 8,
+            budget: SearchBudget::Time(Duration::from_millis(500)),
+            evaluator: augmented_score_evaluator,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SearchResult {
+    /// Piece index of the best move, None if the side to move has to pass.
+    pub best_move: Option<usize>,
+    /// Score of the best move from the point of view of the side to move.
+    pub score: i32,
+    /// Deepest iteration that completed within the budget.
+    pub depth: u8,
+    pub nodes: u64,
+}
+
+///
+/// Runs an iterative deepening negamax search with alpha-beta pruning.
+///
+/// # Arguments
+///
+/// * `board` - The position to search.
+/// * `player` - The side to move.
+/// * `config` - Depth limit, budget and leaf evaluator.
+pub fn search(board: &Bitboard, player: IPlayer, config: &SearchConfig) -> SearchResult {
+    run_search(board, player, config, None)
+}
+
+///
+/// Same as `search`, but reuses and fills a transposition table, which may be shared with
+/// searches running on other threads.
+///
+/// # Arguments
+///
+/// * `board` - The position to search.
+/// * `player` - The side to move.
+/// * `config` - Depth limit, budget and leaf evaluator.
+/// * `table` - The table to probe and store searched positions in.
+pub fn search_with_table(
+    board: &Bitboard,
+    player: IPlayer,
+    config: &SearchConfig,
+    table: &TranspositionTable
+) -> SearchResult {
+    table.new_search();
+    run_search(board, player, config, Some(table))
+}
+
+fn run_search(
+    board: &Bitboard,
+    player: IPlayer,
+    config: &SearchConfig,
+    table: Option<&TranspositionTable>
+) -> SearchResult {
+    let mut searcher = Searcher {
+        evaluator: config.evaluator,
+        budget: config.budget,
+        started: Instant::now(),
+        nodes: 0,
+        aborted: false,
+        table,
+    };
+    let moves = indices_from_mask(board.legal_moves(player));
+    let mut result = SearchResult {
+        best_move: moves.first().copied(),
+        score: 0,
+        depth: 0,
+        nodes: 0,
+    };
+    if moves.len() <= 1 {
+        return result;
+    }
+    // moves are made and taken back on this board as the search walks the tree
+    let mut board = *board;
+    for depth in 1..=config.max_depth.max(1) {
+        let (best_move, score) = searcher.root(&mut board, player, depth, result.best_move);
+        if searcher.aborted {
+            break;
+        }
+        result.best_move = best_move;
+        result.score = score;
+        result.depth = depth;
+        // a proven result cannot change at a greater depth
+        if score.abs() >= TERMINAL_SCORE {
+            break;
+        }
+    }
+    result.nodes = searcher.nodes;
+    result
+}
+
+struct Searcher<'a> {
+    evaluator: Evaluator,
+    budget: SearchBudget,
+    started: Instant,
+    nodes: u64,
+    aborted: bool,
+    table: Option<&'a TranspositionTable>,
+}
+
+impl Searcher<'_> {
+    fn out_of_budget(&mut self) -> bool {
+        if self.aborted {
+            return true;
+        }
+        self.aborted = match self.budget {
+            SearchBudget::Nodes(limit) => self.nodes >= limit,
+            SearchBudget::Time(limit) =>
+                self.nodes.is_multiple_of(TIME_CHECK_INTERVAL) && self.started.elapsed() >= limit,
+        };
+        self.aborted
+    }
+
+    fn root(
+        &mut self,
+        board: &mut Bitboard,
+        player: IPlayer,
+        depth: u8,
+        previous_best: Option<usize>
+    ) -> (Option<usize>, i32) {
+        let mut alpha = -INFINITY;
+        let mut best_move = None;
+        let hash = zobrist_hash(board, player);
+        for index in order_moves(board, player, previous_best) {
+            let flips = board.make_move(index, player);
+            let next_hash = hash ^ placement_delta(index, flips, player);
+            let score = -self.negamax(board, 1 - player, next_hash, depth - 1, -INFINITY, -alpha);
+            board.unmake_move(index, player, flips);
+            if self.aborted {
+                break;
+            }
+            if best_move.is_none() || score > alpha {
+                alpha = score;
+                best_move = Some(index);
+            }
+        }
+        (best_move, alpha)
+    }
+
+    fn negamax(
+        &mut self,
+        board: &mut Bitboard,
+        player: IPlayer,
+        hash: u64,
+        depth: u8,
+        mut alpha: i32,
+        beta: i32
+    ) -> i32 {
+        self.nodes += 1;
+        if self.out_of_budget() {
+            return 0;
+        }
+        let moves = board.legal_moves(player);
+        if moves == 0 {
+            if !board.has_move(1 - player) {
+                return final_score(board, player);
+            }
+            // a pass does not use up depth
+            return -self.negamax(board, 1 - player, hash ^ WHITE_TO_MOVE_KEY, depth, -beta, -alpha);
+        }
+        if depth == 0 {
+            return (self.evaluator)(board, player);
+        }
+        let original_alpha = alpha;
+        let mut table_move = None;
+        if let Some(entry) = self.table.and_then(|table| table.probe(hash)) {
+            table_move = entry.best_move;
+            if entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact => {
+                        return entry.score;
+                    }
+                    Bound::Lower if entry.score >= beta => {
+                        return entry.score;
+                    }
+                    Bound::Upper if entry.score <= alpha => {
+                        return entry.score;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let mut best = -INFINITY;
+        let mut best_move = None;
+        for index in order_moves(board, player, table_move) {
+            let flips = board.make_move(index, player);
+            let next_hash = hash ^ placement_delta(index, flips, player);
+            let score = -self.negamax(board, 1 - player, next_hash, depth - 1, -beta, -alpha);
+            board.unmake_move(index, player, flips);
+            if self.aborted {
+                return 0;
+            }
+            if score > best {
+                best = score;
+                best_move = Some(index);
+            }
+            if best > alpha {
+                alpha = best;
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+        if let Some(table) = self.table {
+            let bound = if best <= original_alpha {
+                Bound::Upper
+            } else if best >= beta {
+                Bound::Lower
+            } else {
+                Bound::Exact
+            };
+            table.store(hash, TableEntry { depth, bound, score: best, best_move });
+        }
+        best
+    }
+}
+
+///
+/// Score of a finished game from the point of view of `player`, see `final_disc_difference`.
+pub fn final_score(board: &Bitboard, player: IPlayer) -> i32 {
+    match final_disc_difference(board, player) {
+        d if d > 0 => TERMINAL_SCORE + d,
+        d if d < 0 => -TERMINAL_SCORE + d,
+        _ => 0,
+    }
+}
+
+///
+/// Orders moves so that alpha-beta cuts early: the previous best move first, then
+/// corners, then moves that leave the opponent with the fewest replies.
+fn order_moves(board: &Bitboard, player: IPlayer, first: Option<usize>) -> Vec<usize> {
+    let mut scored: Vec<(i32, usize)> = indices_from_mask(board.legal_moves(player))
+        .into_iter()
+        .map(|index| {
+            let next = board.play(index, player).expect("Expected a legal move");
+            let mut key = next.legal_moves(1 - player).count_ones() as i32;
+            if CORNER_MASK & (1 << index) != 0 {
+                key -= 64;
+            }
+            if Some(index) == first {
+                key -= 128;
+            }
+            (key, index)
+        })
+        .collect();
+    scored.sort();
+    scored
+        .into_iter()
+        .map(|(_, index)| index)
+        .collect()
+}
+
+pub struct SearchAgent {
+    player: IPlayer,
+    last_board: IBoard,
+    config: SearchConfig,
+    last_result: Option<SearchResult>,
+    // empties at which the depth limited search hands the position to the exact solver
+    endgame_empties: Option<u32>,
+    table: Option<Arc<TranspositionTable>>,
+}
+
+impl SearchAgent {
+    pub fn new(player: IPlayer, board: IBoard) -> SearchAgent {
+        SearchAgent::with_config(player, board, SearchConfig::default())
+    }
+
+    pub fn with_config(player: IPlayer, board: IBoard, config: SearchConfig) -> SearchAgent {
+        SearchAgent {
+            player,
+            last_board: board,
+            config,
+            last_result: None,
+            endgame_empties: None,
+            table: None,
+        }
+    }
+
+    pub fn update_board(&mut self, board: IBoard) {
+        self.last_board = board;
+    }
+
+    pub fn update_player(&mut self, player: IPlayer) {
+        self.player = player;
+    }
+
+    pub fn get_player(&self) -> IPlayer {
+        self.player
+    }
+
+    ///
+    /// Opts into the exact endgame solver once the board has at most `max_empties` empty squares.
+    /// Pass None to always use the depth limited search.
+    pub fn set_endgame_solver(&mut self, max_empties: Option<u32>) {
+        self.endgame_empties = max_empties;
+    }
+
+    ///
+    /// Searches with a transposition table, which can be shared with other agents, e.g. both
+    /// sides of a self play game or agents on several threads. Pass None to search without one.
+    pub fn set_transposition_table(&mut self, table: Option<Arc<TranspositionTable>>) {
+        self.table = table;
+    }
+
+    /// Result of the most recent search, useful for reporting depth, score and node counts.
+    pub fn get_last_result(&self) -> Option<SearchResult> {
+        self.last_result
+    }
+}
+
+impl Agent for SearchAgent {
+    fn select_action(&mut self) -> usize {
+        if let Some(action) = endgame_action(self.last_board, self.player, self.endgame_empties) {
+            return action;
+        }
+        let board = Bitboard::from_board(self.last_board);
+        let result = match &self.table {
+            Some(table) => search_with_table(&board, self.player, &self.config, table),
+            None => search(&board, self.player, &self.config),
+        };
+        self.last_result = Some(result);
+        result.best_move.unwrap_or(NULL_MOVE_INDEX)
+    }
+
+    fn take_action(&mut self, _action: usize) -> f64 {
+        0.0
+    }
+
+    fn update_estimate(&mut self, _state: String, _action: usize, _reward: f64, _is_terminal: bool) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::{ rngs::StdRng, seq::SliceRandom, SeedableRng };
+    use rl_examples::agents::agent::Agent;
+
+    use crate::agent::transposition::TranspositionTable;
+    use crate::gameplay::{
+        bitboard::{ indices_from_mask, Bitboard },
+        constants::{ INITIAL_BOARD, NULL_MOVE_INDEX },
+        game::IPlayer,
+    };
+
+    use super::{
+        augmented_score_evaluator,
+        final_score,
+        search,
+        search_with_table,
+        SearchAgent,
+        SearchBudget,
+        SearchConfig,
+    };
+
+    /// Plain minimax without pruning, used as a reference.
+    fn minimax(board: &Bitboard, player: IPlayer, depth: u8) -> i32 {
+        let moves = indices_from_mask(board.legal_moves(player));
+        if moves.is_empty() {
+            if !board.has_move(1 - player) {
+                return final_score(board, player);
+            }
+            return -minimax(board, 1 - player, depth);
+        }
+        if depth == 0 {
+            return augmented_score_evaluator(board, player);
+        }
+        moves
+            .iter()
+            .map(|&index| -minimax(&board.play(index, player).unwrap(), 1 - player, depth - 1))
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn agrees_with_minimax() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let config = SearchConfig {
+            max_depth: 3,
+            budget: SearchBudget::Nodes(u64::MAX),
+            ..SearchConfig::default()
+        };
+        for _ in 0..20 {
+            let mut board = Bitboard::new();
+            let mut player: IPlayer = 0;
+            for _ in 0..20 {
+                let moves = indices_from_mask(board.legal_moves(player));
+                if moves.len() > 1 {
+                    let result = search(&board, player, &config);
+                    assert_eq!(result.depth, 3);
+                    assert_eq!(result.score, minimax(&board, player, 3));
+                    // a fresh table, entries of other roots may be searched deeper than minimax
+                    let table = TranspositionTable::new(1 << 12);
+                    let with_table = search_with_table(&board, player, &config, &table);
+                    assert_eq!(with_table.score, result.score);
+                }
+                if let Some(&index) = moves.choose(&mut rng) {
+                    board = board.play(index, player).unwrap();
+                }
+                player = 1 - player;
+            }
+        }
+    }
+
+    #[test]
+    fn respects_node_budget() {
+        let config = SearchConfig {
+            max_depth: 60,
+            budget: SearchBudget::Nodes(2000),
+            ..SearchConfig::default()
+        };
+        let result = search(&Bitboard::new(), 0, &config);
+        assert!(result.best_move.is_some());
+        assert!(result.nodes <= 2000);
+        assert!(result.depth < 60);
+    }
+
+    #[test]
+    fn table_saves_nodes_on_a_repeated_search() {
+        let config = SearchConfig {
+            max_depth: 5,
+            budget: SearchBudget::Nodes(u64::MAX),
+            ..SearchConfig::default()
+        };
+        let board = Bitboard::new().play(19, 0).unwrap();
+        let table = TranspositionTable::new(1 << 16);
+        let first = search_with_table(&board, 1, &config, &table);
+        let second = search_with_table(&board, 1, &config, &table);
+        assert_eq!(second.score, first.score);
+        assert!(second.nodes < first.nodes);
+        let stats = table.stats();
+        assert!(stats.hits > 0);
+        assert!(stats.hit_rate() > 0.0 && stats.hit_rate() <= 1.0);
+    }
+
+    #[test]
+    fn agents_can_share_a_table() {
+        let table = Arc::new(TranspositionTable::new(1 << 14));
+        let config = SearchConfig {
+            max_depth: 4,
+            budget: SearchBudget::Nodes(u64::MAX),
+            ..SearchConfig::default()
+        };
+        let mut black = SearchAgent::with_config(0, INITIAL_BOARD, config);
+        let mut white = SearchAgent::with_config(1, INITIAL_BOARD, config);
+        black.set_transposition_table(Some(Arc::clone(&table)));
+        white.set_transposition_table(Some(Arc::clone(&table)));
+        let action = black.select_action();
+        let board = Bitboard::new().play(action, 0).unwrap();
+        white.update_board(board.to_board());
+        let reply = white.select_action();
+        assert!(indices_from_mask(board.legal_moves(1)).contains(&reply));
+        assert!(table.stats().hits > 0);
+    }
+
+    #[test]
+    fn agent_selects_legal_moves_and_passes() {
+        let mut agent = SearchAgent::new(0, INITIAL_BOARD);
+        let action = agent.select_action();
+        assert!(indices_from_mask(Bitboard::new().legal_moves(0)).contains(&action));
+        let no_move_board = [
+            [0, 0, 0, 0, 0, 0, 0, 0],
+            [0, 2, 2, 2, 2, 2, 2, 0],
+            [0, 2, 2, 2, 2, 2, 2, 0],
+            [0, 2, 2, 2, 2, 2, 2, 0],
+            [0, 2, 2, 2, 2, 2, 2, 0],
+            [0, 2, 2, 2, 2, 2, 2, 0],
+            [0, 2, 2, 2, 2, 2, 2, 0],
+            [0, 0, 0, 0, 0, 0, 0, 0],
+        ];
+        agent.update_board(no_move_board);
+        assert_eq!(agent.select_action(), NULL_MOVE_INDEX);
+    }
+}
@@ -0,0 +1,270 @@
+use std::sync::atomic::{ AtomicU64, AtomicU8, Ordering };
+
+///
+/// How a stored score relates to the true score of the position, as in alpha-beta search.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    /// The score is exact.
+    Exact,
+    /// The search failed high, the true score is at least this.
+    Lower,
+    /// The search failed low, the true score is at most this.
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TableEntry {
+    /// Remaining depth the score was searched to.
+    pub depth: u8,
+    pub bound: Bound,
+    pub score: i32,
+    /// Piece index of the best move found, None if no move was searched.
+    pub best_move: Option<usize>,
+}
+
+/// Counters of a `TranspositionTable`, for benchmarking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TableStats {
+    pub probes: u64,
+    pub hits: u64,
+    pub stores: u64,
+    /// Stores that evicted an entry of another position.
+    pub replacements: u64,
+}
+
+impl TableStats {
+    /// Share of probes that found their position, 0 before the first probe.
+    pub fn hit_rate(&self) -> f64 {
+        if self.probes == 0 {
+            return 0.0;
+        }
+        (self.hits as f64) / (self.probes as f64)
+    }
+}
+
+/// Stands for "no best move" in a packed entry.
+const NO_MOVE: u64 = 0xff;
+
+// the data word of a slot: score in bits 0-31, depth 32-39, bound 40-41, best move 42-49,
+// generation 50-57. The bound is never 0, so a stored entry is never 0 either
+fn pack(entry: &TableEntry, generation: u8) -> u64 {
+    let bound = match entry.bound {
+        Bound::Exact => 1,
+        Bound::Lower => 2,
+        Bound::Upper => 3,
+    };
+    let best_move = entry.best_move.map_or(NO_MOVE, |index| index as u64);
+    (entry.score as u32 as u64) |
+        ((entry.depth as u64) << 32) |
+        (bound << 40) |
+        (best_move << 42) |
+        ((generation as u64) << 50)
+}
+
+fn unpack(data: u64) -> (TableEntry, u8) {
+    let bound = match (data >> 40) & 0b11 {
+        1 => Bound::Exact,
+        2 => Bound::Lower,
+        _ => Bound::Upper,
+    };
+    let best_move = match (data >> 42) & 0xff {
+        NO_MOVE => None,
+        index => Some(index as usize),
+    };
+    let entry = TableEntry {
+        depth: ((data >> 32) & 0xff) as u8,
+        bound,
+        score: data as u32 as i32,
+        best_move,
+    };
+    (entry, ((data >> 50) & 0xff) as u8)
+}
+
+///
+/// Fixed size table of searched positions keyed by their Zobrist hash, shared between threads
+/// without locks. Each slot keeps the key xored with the data next to the data, so a slot torn
+/// by two threads writing at once reads as a miss instead of a wrong entry.
+///
+/// A store replaces the entry in its slot if that entry is for the same position, was stored
+/// before the last `new_search`, or was searched less deeply.
+pub struct TranspositionTable {
+    // pairs of key ^ data and data, two words per slot
+    slots: Vec<AtomicU64>,
+    mask: usize,
+    generation: AtomicU8,
+    probes: AtomicU64,
+    hits: AtomicU64,
+    stores: AtomicU64,
+    replacements: AtomicU64,
+}
+
+impl TranspositionTable {
+    ///
+    /// Creates an empty table.
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - Number of slots, rounded up to a power of two.
+    pub fn new(entries: usize) -> TranspositionTable {
+        let entries = entries.max(1).next_power_of_two();
+        TranspositionTable {
+            slots: (0..entries * 2).map(|_| AtomicU64::new(0)).collect(),
+            mask: entries - 1,
+            generation: AtomicU8::new(0),
+            probes: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            stores: AtomicU64::new(0),
+            replacements: AtomicU64::new(0),
+        }
+    }
+
+    /// Creates a table using about `megabytes` of memory, 16 bytes per slot.
+    pub fn with_megabytes(megabytes: usize) -> TranspositionTable {
+        let entries = ((megabytes * 1024 * 1024) / 16).max(1);
+        // round down so the table stays within the budget
+        TranspositionTable::new(1 << entries.ilog2())
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.mask + 1
+    }
+
+    fn slot(&self, key: u64) -> (&AtomicU64, &AtomicU64) {
+        let index = ((key as usize) & self.mask) * 2;
+        (&self.slots[index], &self.slots[index + 1])
+    }
+
+    /// Looks up a position, None if it is not stored.
+    pub fn probe(&self, key: u64) -> Option<TableEntry> {
+        self.probes.fetch_add(1, Ordering::Relaxed);
+        let (checked, data) = self.slot(key);
+        let data = data.load(Ordering::Relaxed);
+        // an empty slot holds zeros
+        if data == 0 || checked.load(Ordering::Relaxed) ^ data != key {
+            return None;
+        }
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        Some(unpack(data).0)
+    }
+
+    /// Stores a searched position, following the replacement policy of the table.
+    pub fn store(&self, key: u64, entry: TableEntry) {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let (checked, data) = self.slot(key);
+        let stored = data.load(Ordering::Relaxed);
+        let same_position = checked.load(Ordering::Relaxed) ^ stored == key;
+        if stored != 0 && !same_position {
+            let (previous, previous_generation) = unpack(stored);
+            if previous_generation == generation && previous.depth > entry.depth {
+                return;
+            }
+            self.replacements.fetch_add(1, Ordering::Relaxed);
+        }
+        let packed = pack(&entry, generation);
+        data.store(packed, Ordering::Relaxed);
+        checked.store(key ^ packed, Ordering::Relaxed);
+        self.stores.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Marks every stored entry as old, so the next search can replace them first.
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Empties the table and resets the statistics.
+    pub fn clear(&self) {
+        for slot in self.slots.iter() {
+            slot.store(0, Ordering::Relaxed);
+        }
+        self.reset_stats();
+    }
+
+    pub fn stats(&self) -> TableStats {
+        TableStats {
+            probes: self.probes.load(Ordering::Relaxed),
+            hits: self.hits.load(Ordering::Relaxed),
+            stores: self.stores.load(Ordering::Relaxed),
+            replacements: self.replacements.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn reset_stats(&self) {
+        self.probes.store(0, Ordering::Relaxed);
+        self.hits.store(0, Ordering::Relaxed);
+        self.stores.store(0, Ordering::Relaxed);
+        self.replacements.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ sync::Arc, thread };
+
+    use super::{ Bound, TableEntry, TableStats, TranspositionTable };
+
+    fn entry(depth: u8, score: i32) -> TableEntry {
+        TableEntry { depth, bound: Bound::Lower, score, best_move: Some(63) }
+    }
+
+    #[test]
+    fn stores_and_probes_entries() {
+        let table = TranspositionTable::new(1000);
+        assert_eq!(table.capacity(), 1024);
+        assert_eq!(table.probe(42), None);
+        table.store(42, entry(5, -100_064));
+        assert_eq!(table.probe(42), Some(entry(5, -100_064)));
+        let no_move = TableEntry { depth: 0, bound: Bound::Upper, score: 7, best_move: None };
+        table.store(7, no_move);
+        assert_eq!(table.probe(7), Some(no_move));
+        // same slot, other position
+        assert_eq!(table.probe(42 + 1024), None);
+
+        let stats = table.stats();
+        assert_eq!(stats, TableStats { probes: 4, hits: 2, stores: 2, replacements: 0 });
+        assert_eq!(stats.hit_rate(), 0.5);
+        table.clear();
+        assert_eq!(table.probe(42), None);
+        assert_eq!(table.stats().probes, 1);
+    }
+
+    #[test]
+    fn prefers_deeper_entries_of_the_same_search() {
+        let table = TranspositionTable::new(16);
+        table.store(3, entry(6, 1));
+        table.store(3 + 16, entry(2, 2));
+        assert_eq!(table.probe(3), Some(entry(6, 1)));
+        // the same position is always updated
+        table.store(3, entry(1, 3));
+        assert_eq!(table.probe(3), Some(entry(1, 3)));
+
+        table.store(3, entry(6, 1));
+        table.new_search();
+        table.store(3 + 16, entry(2, 2));
+        assert_eq!(table.probe(3), None);
+        assert_eq!(table.probe(3 + 16), Some(entry(2, 2)));
+        assert_eq!(table.stats().replacements, 1);
+    }
+
+    #[test]
+    fn can_be_shared_between_threads() {
+        let table = Arc::new(TranspositionTable::with_megabytes(1));
+        assert_eq!(table.capacity(), 1 << 16);
+        let handles: Vec<_> = (0..4u64)
+            .map(|thread_index| {
+                let table = Arc::clone(&table);
+                thread::spawn(move || {
+                    for key in 1..=1000u64 {
+                        let key = key * 4 + thread_index;
+                        table.store(key, entry(1, key as i32));
+                        let found = table.probe(key).expect("Expected the stored entry");
+                        assert_eq!(found.score, key as i32);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(table.stats().hits, 4000);
+    }
+}
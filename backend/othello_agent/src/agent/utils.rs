@@ -2,7 +2,7 @@ use rl_examples::environment::Environment;
 use serde::Deserialize;
 
 use crate::{
-    gameplay::position::IPosition,
+    gameplay::{ constants::NULL_MOVE_INDEX, transcript::parse_transcript },
     simulate::{ environment::OthelloEnvironment, history::{ GameHistory, GameHistoryStore } },
 };
 
@@ -33,17 +33,14 @@ pub fn fetch_data() -> Option<GameHistoryStore> {
 }
 
 pub fn raw_record_to_game_history(record: RawRecord) -> GameHistory {
-    // chunk game_moves into 2 character strings... separate original every 2 characters
-    // note that game_moves is a continous string with no spaces
+    // game_moves is a continous string of squares with no spaces, e.g. f5d6c3
+    let moves = parse_transcript(&record.game_moves).unwrap_or_else(|error| {
+        panic!("Unable to parse game moves: {}", error)
+    });
     let mut env = OthelloEnvironment::new();
-    for i in 0..record.game_moves.len() / 2 {
-        let move_string = &record.game_moves[i * 2..(i + 1) * 2];
-        let position = IPosition::position_from_string_position(move_string);
-        if position.is_none() {
-            panic!("Unable to convert string to position.");
-        }
-        let position = position.unwrap();
-        env.step(position.to_piece_index());
+    // the environment passes on its own, so passes are not stepped
+    for move_index in moves.into_iter().filter(|&move_index| move_index != NULL_MOVE_INDEX) {
+        env.step(move_index);
     }
     env.get_game_history()
 }
@@ -1,6 +1,9 @@
+use crate::agent::endgame::endgame_action;
+use crate::agent::value_estimator::ValueEstimator;
 use crate::gameplay::constants::NULL_MOVE_INDEX;
 use crate::gameplay::game::{ board_to_ml_input, IBoard, IPlayer };
 use crate::gameplay::position::IPosition;
+use crate::gameplay::symmetry::Symmetry;
 use crate::gameplay::utils::board_by_playing_piece_at_index;
 use crate::model::batch::OthelloMoveBatcher;
 use crate::model::dataset::OthelloMovesDataset;
@@ -28,9 +31,12 @@ use burn::train::{
     MetricEarlyStoppingStrategy,
     StoppingCondition,
 };
-use rand::{ thread_rng, Rng };
+use rand::{ rngs::StdRng, Rng, SeedableRng };
 use rl_examples::agents::agent::Agent;
 
+/// Where `train_win` saves the value model.
+pub const VALUE_ARTIFACT_DIR: &str = "tmp/othello_win_again_slim_training_artifacts";
+
 pub struct ValueAgent<B: Backend> {
     player: IPlayer,
     current_board: IBoard,
@@ -38,24 +44,60 @@ pub struct ValueAgent<B: Backend> {
     model: Model<B>,
     device: Device<B>,
     current_prob_of_win: (f32, f32, f32),
+    // the network is only asked while more squares than this are empty
+    endgame_empties: Option<u32>,
+    // learns from the rewards passed to update_estimate when set
+    value_estimator: Option<ValueEstimator>,
+    // average predictions over the eight board symmetries
+    test_time_augmentation: bool,
+    // picks between equally good moves
+    rng: StdRng,
 }
 
-impl<B: AutodiffBackend> ValueAgent<B> {
+impl<B: Backend> ValueAgent<B> {
     pub fn new(player: IPlayer, board: IBoard, device: Device<B>) -> ValueAgent<B> {
-        let model = ValueAgent::load_value_model(&device);
+        let model = ValueAgent::load_value_model(&device).expect("Model should exist");
+        ValueAgent::with_model(player, board, model, device)
+    }
+
+    ///
+    /// Creates an agent around an already loaded model, so that one model can be shared by
+    /// many agents.
+    pub fn with_model(
+        player: IPlayer,
+        board: IBoard,
+        model: Model<B>,
+        device: Device<B>
+    ) -> ValueAgent<B> {
         ValueAgent {
             player,
             current_board: board,
             possible_moves: Vec::new(),
-            model: model,
-            device: device,
+            model,
+            device,
             current_prob_of_win: (0.0, 0.0, 0.0),
+            endgame_empties: None,
+            value_estimator: None,
+            test_time_augmentation: false,
+            rng: StdRng::from_entropy(),
         }
     }
     pub fn get_win_probability(&self) -> f32 {
         if self.player == 0 { self.current_prob_of_win.0 } else { self.current_prob_of_win.1 }
     }
 
+    ///
+    /// Outcome probabilities predicted for the last move the agent chose.
+    ///
+    /// # Returns
+    ///
+    /// * `(f32, f32, f32)` - Probability that player 0 wins, that player 1 wins and of a draw.
+    pub fn get_outcome_probabilities(&self) -> (f32, f32, f32) {
+        self.current_prob_of_win
+    }
+}
+
+impl<B: AutodiffBackend> ValueAgent<B> {
     pub fn train(
         &mut self,
         observations: Vec<ObservationMove>,
@@ -86,7 +128,7 @@ impl<B: AutodiffBackend> ValueAgent<B> {
             .shuffle(SEED)
             .num_workers(NUM_WORKERS)
             .build(OthelloMovesDataset::from_raw_observations(observartions_for_validation));
-        let formatted_name = "tmp/othello_win_again_slim_training_artifacts";
+        let formatted_name = VALUE_ARTIFACT_DIR;
         // Model
         let learner = LearnerBuilder::new(&formatted_name)
             .metric_train_numeric(AccuracyMetric::new())
@@ -115,11 +157,20 @@ impl<B: AutodiffBackend> ValueAgent<B> {
 
         let model_trained = learner.fit(dataloader_train, dataloader_test);
     }
+}
+
+impl<B: Backend> ValueAgent<B> {
     fn suggest_moves(&mut self, board: IBoard) -> Vec<IPosition> {
         let mut suggested_moves: Vec<IPosition> = Vec::new();
+        // with test-time augmentation every candidate board is evaluated under all symmetries
+        let symmetries: &[Symmetry] = if self.test_time_augmentation {
+            &Symmetry::ALL
+        } else {
+            &[Symmetry::Identity]
+        };
         let input: Vec<Tensor<B, 3>> = self.possible_moves
             .iter()
-            .map(|&index| {
+            .flat_map(|&index| {
                 let position_formatted_raw = IPosition::position_from_piece_index(index as i8);
                 let position_formatted = position_formatted_raw.unwrap();
                 let new_board_raw = board_by_playing_piece_at_index(
@@ -128,7 +179,11 @@ impl<B: AutodiffBackend> ValueAgent<B> {
                     self.player
                 );
                 let new_board = new_board_raw.unwrap();
-                let ml_input = board_to_ml_input(new_board);
+                symmetries
+                    .iter()
+                    .map(move |symmetry| board_to_ml_input(symmetry.transform_board(new_board)))
+            })
+            .map(|ml_input| {
                 let tensor: Tensor<B, 2> = Tensor::<B, 2, Float>::from_floats(
                     ml_input,
                     &self.device
@@ -143,6 +198,19 @@ impl<B: AutodiffBackend> ValueAgent<B> {
         // Convert the model output into probability distribution using softmax formula
         let output = burn::tensor::activation::softmax(output, 1);
         let output = output.into_data().convert::<f32>().value;
+        // average the probabilities of each candidate over its symmetries
+        let output: Vec<f32> = output
+            .chunks(3 * symmetries.len())
+            .flat_map(|chunk| {
+                (0..3).map(move |outcome| {
+                    chunk
+                        .iter()
+                        .skip(outcome)
+                        .step_by(3)
+                        .sum::<f32>() / (symmetries.len() as f32)
+                })
+            })
+            .collect();
         let mut prob_prediction: (f32, f32, f32) = (0.0, 0.0, 0.0);
         // step through output in chunks of three
         let mut best_move_index = 0;
@@ -183,6 +251,35 @@ impl<B: AutodiffBackend> ValueAgent<B> {
         self.player
     }
 
+    ///
+    /// Opts into the exact endgame solver once the board has at most `max_empties` empty squares.
+    /// Pass None to always use the value network.
+    pub fn set_endgame_solver(&mut self, max_empties: Option<u32>) {
+        self.endgame_empties = max_empties;
+    }
+
+    ///
+    /// Attaches a TD(λ) value estimator that learns from every `update_estimate` call.
+    pub fn set_value_estimator(&mut self, value_estimator: Option<ValueEstimator>) {
+        self.value_estimator = value_estimator;
+    }
+
+    pub fn get_value_estimator(&self) -> Option<&ValueEstimator> {
+        self.value_estimator.as_ref()
+    }
+
+    ///
+    /// When enabled every candidate board is evaluated under the eight board symmetries and the
+    /// predictions are averaged. This is eight times slower but less sensitive to orientation.
+    pub fn set_test_time_augmentation(&mut self, enabled: bool) {
+        self.test_time_augmentation = enabled;
+    }
+
+    /// Replaces the random choice between equally good moves, seed it to replay the same games.
+    pub fn set_rng(&mut self, rng: StdRng) {
+        self.rng = rng;
+    }
+
     pub fn update_possible_move_indices(&mut self, new_possible_moves: Vec<usize>) {
         self.possible_moves = new_possible_moves;
     }
@@ -191,31 +288,38 @@ impl<B: AutodiffBackend> ValueAgent<B> {
         if suggested_moves.len() == 0 {
             return None;
         }
-        let mut rng = thread_rng();
-        let random_index = rng.gen_range(0..suggested_moves.len());
+        let random_index = self.rng.gen_range(0..suggested_moves.len());
         Some(suggested_moves[random_index].duplicate())
     }
 
-    fn load_value_model(device: &Device<B>) -> Model<B> {
-        const ARTIFACT_DIR: &str = "tmp/othello_win_again_slim_training_artifacts";
-        let config = OthelloMovesTrainingConfig::load(format!("{ARTIFACT_DIR}/config.json")).expect(
-            "Config should exist for the model"
-        );
+    ///
+    /// Loads the model trained by the `train_win` binary from `VALUE_ARTIFACT_DIR`.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Model<B>>` - None if the config or the weights can not be read.
+    pub fn load_value_model(device: &Device<B>) -> Option<Model<B>> {
+        let config = OthelloMovesTrainingConfig::load(
+            format!("{VALUE_ARTIFACT_DIR}/config.json")
+        ).ok()?;
         println!("Config loaded successfully");
         // Include the model file as a reference to a byte array
         let recorder = NamedMpkFileRecorder::<FullPrecisionSettings>::new();
 
         let model = config.model
-            .init::<B>(&device)
-            .load_file(format!("{ARTIFACT_DIR}/model"), &recorder, &device)
-            .expect("Model should exist");
+            .init::<B>(device)
+            .load_file(format!("{VALUE_ARTIFACT_DIR}/model"), &recorder, device)
+            .ok()?;
         println!("Model loaded successfully");
-        model
+        Some(model)
     }
 }
 
-impl<B: AutodiffBackend> Agent for ValueAgent<B> {
+impl<B: Backend> Agent for ValueAgent<B> {
     fn select_action(&mut self) -> usize {
+        if let Some(action) = endgame_action(self.current_board, self.player, self.endgame_empties) {
+            return action;
+        }
         let suggested_moves = self.suggest_moves(self.current_board);
         let res = self.choose_from_actions(suggested_moves);
         if res.is_none() {
@@ -228,5 +332,9 @@ impl<B: AutodiffBackend> Agent for ValueAgent<B> {
         0.0
     }
 
-    fn update_estimate(&mut self, state: String, action: usize, reward: f64, _is_terminal: bool) {}
+    fn update_estimate(&mut self, state: String, _action: usize, reward: f64, is_terminal: bool) {
+        if let Some(value_estimator) = self.value_estimator.as_mut() {
+            value_estimator.update(&state, reward, is_terminal);
+        }
+    }
 }
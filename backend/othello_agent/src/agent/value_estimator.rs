@@ -0,0 +1,375 @@
+use rand::{ rngs::StdRng, seq::SliceRandom, Rng, SeedableRng };
+use rl_examples::environment::Environment;
+use serde::{ Deserialize, Serialize };
+
+use crate::gameplay::bitboard::{ indices_from_mask, Bitboard, CORNER_MASK };
+use crate::gameplay::constants::CODE_CHARS;
+use crate::gameplay::encoding::create_code_char_hash;
+use crate::gameplay::packed::{ PackedPosition, StateEncoding };
+use crate::gameplay::game::IPlayer;
+use crate::simulate::environment::OthelloEnvironment;
+
+/// X-squares: the squares diagonally adjacent to a corner, in the same order as the corners.
+const X_SQUARES: [usize; 4] = [9, 14, 49, 54];
+const CORNERS: [usize; 4] = [0, 7, 56, 63];
+/// C-squares: the squares orthogonally adjacent to a corner, two per corner.
+const C_SQUARES: [[usize; 2]; 4] = [
+    [1, 8],
+    [6, 15],
+    [48, 57],
+    [55, 62],
+];
+
+const BIAS_FEATURE: usize = 0;
+const DISC_FEATURE: usize = 1;
+const MOBILITY_FEATURE: usize = 2;
+const CORNER_FEATURE: usize = 3;
+const X_SQUARE_FEATURE: usize = 4;
+const C_SQUARE_FEATURE: usize = 5;
+const EDGE_PATTERN_OFFSET: usize = 6;
+/// Each edge is read as an 8 digit base 3 number. The four edges share weights.
+const EDGE_PATTERNS: usize = 6561;
+const CORNER_PATTERN_OFFSET: usize = EDGE_PATTERN_OFFSET + EDGE_PATTERNS;
+/// Each corner's 3x3 region is read as a 9 digit base 3 number. The four corners share weights.
+const CORNER_PATTERNS: usize = 19683;
+pub const NUM_FEATURES: usize = CORNER_PATTERN_OFFSET + CORNER_PATTERNS;
+
+/// Sparse feature vector: (feature index, feature value) pairs.
+pub type Features = Vec<(usize, f32)>;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TdConfig {
+    /// Step size, divided by the number of active features on each update.
+    pub alpha: f32,
+    /// Trace decay. 0 is one-step TD, 1 is Monte Carlo.
+    pub lambda: f32,
+    pub gamma: f32,
+}
+
+impl Default for TdConfig {
+    fn default() -> Self {
+        TdConfig {
+            alpha: 0.05,
+            lambda: 0.7,
+            gamma: 1.0,
+        }
+    }
+}
+
+///
+/// Linear TD(λ) value estimator over hand-crafted Othello features. Values are in terms of
+/// player 0, matching the rewards returned by `OthelloEnvironment::step`: 1 for a player 0 win,
+/// -1 for a loss and 0 for a draw.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValueEstimator {
+    pub config: TdConfig,
+    weights: Vec<f32>,
+    #[serde(skip)]
+    trace: Vec<f32>,
+    // features of the last state seen and the reward received for leaving it
+    #[serde(skip)]
+    pending: Option<(Features, f32)>,
+}
+
+impl ValueEstimator {
+    pub fn new(config: TdConfig) -> ValueEstimator {
+        ValueEstimator {
+            config,
+            weights: vec![0.0; NUM_FEATURES],
+            trace: vec![0.0; NUM_FEATURES],
+            pending: None,
+        }
+    }
+
+    ///
+    /// Extracts features from the point of view of player 0.
+    pub fn features(board: &Bitboard) -> Features {
+        let black = board.pieces[0];
+        let white = board.pieces[1];
+        let empty = board.empty();
+        let mut features: Features = vec![(BIAS_FEATURE, 1.0)];
+
+        let discs = (board.count(0) as f32) - (board.count(1) as f32);
+        features.push((DISC_FEATURE, discs / 64.0));
+        let mobility =
+            (board.legal_moves(0).count_ones() as f32) - (board.legal_moves(1).count_ones() as f32);
+        features.push((MOBILITY_FEATURE, mobility / 16.0));
+        let corners =
+            ((black & CORNER_MASK).count_ones() as f32) -
+            ((white & CORNER_MASK).count_ones() as f32);
+        features.push((CORNER_FEATURE, corners / 4.0));
+
+        // X and C squares are only a liability while their corner is still empty
+        let mut x_squares = 0.0;
+        let mut c_squares = 0.0;
+        for (corner, (x_square, c_pair)) in CORNERS.iter().zip(X_SQUARES.iter().zip(C_SQUARES.iter())) {
+            if empty & (1 << corner) == 0 {
+                continue;
+            }
+            x_squares += square_sign(board, *x_square);
+            c_squares += c_pair
+                .iter()
+                .map(|&square| square_sign(board, square))
+                .sum::<f32>();
+        }
+        features.push((X_SQUARE_FEATURE, x_squares / 4.0));
+        features.push((C_SQUARE_FEATURE, c_squares / 8.0));
+
+        for edge in edges() {
+            features.push((EDGE_PATTERN_OFFSET + pattern_index(board, &edge), 1.0));
+        }
+        for region in corner_regions() {
+            features.push((CORNER_PATTERN_OFFSET + pattern_index(board, &region), 1.0));
+        }
+        features
+    }
+
+    /// Estimated outcome for player 0.
+    pub fn value(&self, board: &Bitboard) -> f32 {
+        self.value_of_features(&ValueEstimator::features(board))
+    }
+
+    /// Estimated outcome for the given player.
+    pub fn value_for_player(&self, board: &Bitboard, player: IPlayer) -> f32 {
+        let value = self.value(board);
+        if player == 0 { value } else { -value }
+    }
+
+    fn value_of_features(&self, features: &Features) -> f32 {
+        features
+            .iter()
+            .map(|&(index, value)| self.weights[index] * value)
+            .sum()
+    }
+
+    ///
+    /// Feeds one transition to the learner, following the `Agent::update_estimate` convention.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - Encoded board the action was taken from, as returned by `Environment::get_state`
+    ///   in either `StateEncoding`.
+    /// * `reward` - Reward received for the action.
+    /// * `is_terminal` - Whether the action ended the game.
+    pub fn update(&mut self, state: &str, reward: f64, is_terminal: bool) {
+        let position = PackedPosition::try_from_state(state, &create_code_char_hash(CODE_CHARS))
+            .expect("Invalid state");
+        self.update_board(&position.to_bitboard(), reward, is_terminal);
+    }
+
+    pub fn update_board(&mut self, board: &Bitboard, reward: f64, is_terminal: bool) {
+        let features = ValueEstimator::features(board);
+        if let Some((previous, previous_reward)) = self.pending.take() {
+            let target = previous_reward + self.config.gamma * self.value_of_features(&features);
+            self.td_step(&previous, target);
+        }
+        if is_terminal {
+            self.td_step(&features, reward as f32);
+            self.reset_episode();
+        } else {
+            self.pending = Some((features, reward as f32));
+        }
+    }
+
+    /// Clears the eligibility trace and any unfinished transition.
+    pub fn reset_episode(&mut self) {
+        self.trace = vec![0.0; NUM_FEATURES];
+        self.pending = None;
+    }
+
+    fn td_step(&mut self, features: &Features, target: f32) {
+        if self.trace.len() != NUM_FEATURES {
+            // estimators loaded from disk start without a trace
+            self.trace = vec![0.0; NUM_FEATURES];
+        }
+        let error = target - self.value_of_features(features);
+        let decay = self.config.gamma * self.config.lambda;
+        for trace in self.trace.iter_mut() {
+            *trace *= decay;
+        }
+        for &(index, value) in features.iter() {
+            self.trace[index] += value;
+        }
+        let step = (self.config.alpha / (features.len() as f32)) * error;
+        for (weight, trace) in self.weights.iter_mut().zip(self.trace.iter()) {
+            *weight += step * trace;
+        }
+    }
+
+    ///
+    /// Picks the move leading to the position with the highest estimated value for `player`.
+    /// With probability `epsilon` a random legal move is played instead, drawn from `rng`.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<usize>` - The piece index of the move, None if the player has to pass.
+    pub fn select_move<R: Rng>(
+        &self,
+        board: &Bitboard,
+        player: IPlayer,
+        epsilon: f64,
+        rng: &mut R
+    ) -> Option<usize> {
+        let moves = indices_from_mask(board.legal_moves(player));
+        if rng.gen::<f64>() < epsilon {
+            return moves.choose(rng).copied();
+        }
+        moves
+            .into_iter()
+            .map(|index| {
+                let next = board.play(index, player).expect("Expected a legal move");
+                (self.value_for_player(&next, player), index)
+            })
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, index)| index)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer(std::io::BufWriter::new(file), self)?;
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<ValueEstimator, Box<dyn std::error::Error>> {
+        let file = std::fs::File::open(path)?;
+        let mut estimator: ValueEstimator = serde_json::from_reader(std::io::BufReader::new(file))?;
+        if estimator.weights.len() != NUM_FEATURES {
+            return Err("Saved estimator has a different number of features".into());
+        }
+        estimator.reset_episode();
+        Ok(estimator)
+    }
+}
+
+impl Default for ValueEstimator {
+    fn default() -> Self {
+        ValueEstimator::new(TdConfig::default())
+    }
+}
+
+///
+/// Trains the estimator by letting it play `episodes` games against itself in `OthelloEnvironment`,
+/// picking ε-greedy moves for both sides. The same `rng` seed trains the same estimator.
+pub fn train_self_play<R: Rng>(
+    estimator: &mut ValueEstimator,
+    episodes: usize,
+    epsilon: f64,
+    rng: &mut R
+) {
+    for _ in 0..episodes {
+        let mut env = OthelloEnvironment::with_rng(StdRng::seed_from_u64(rng.gen()));
+        // packed states are quicker to write and read than code chars
+        env.update_state_encoding(StateEncoding::Packed);
+        estimator.reset_episode();
+        while !env.is_terminal() {
+            let state = env.get_state();
+            let board = PackedPosition::try_from_hex(&state).expect("Invalid state").to_bitboard();
+            let action = estimator
+                .select_move(&board, env.get_current_turn_id(), epsilon, rng)
+                .expect("Environment should only hand over turns with a legal move");
+            let reward = env.step(action);
+            estimator.update_board(&board, reward, env.is_terminal());
+        }
+    }
+}
+
+/// 1 for a player 0 piece, -1 for a player 1 piece, 0 for an empty square.
+fn square_sign(board: &Bitboard, square: usize) -> f32 {
+    if board.pieces[0] & (1 << square) != 0 {
+        1.0
+    } else if board.pieces[1] & (1 << square) != 0 {
+        -1.0
+    } else {
+        0.0
+    }
+}
+
+/// Base 3 index of the pattern formed by the given squares: 0 empty, 1 player 0, 2 player 1.
+fn pattern_index(board: &Bitboard, squares: &[usize]) -> usize {
+    squares.iter().fold(0, |index, &square| {
+        let digit = if board.pieces[0] & (1 << square) != 0 {
+            1
+        } else if board.pieces[1] & (1 << square) != 0 {
+            2
+        } else {
+            0
+        };
+        index * 3 + digit
+    })
+}
+
+/// The four edges, each read from one corner to the next.
+fn edges() -> [[usize; 8]; 4] {
+    [
+        std::array::from_fn(|i| i),
+        std::array::from_fn(|i| 56 + i),
+        std::array::from_fn(|i| i * 8),
+        std::array::from_fn(|i| i * 8 + 7),
+    ]
+}
+
+/// The 3x3 region of each corner, read row by row starting at the corner.
+fn corner_regions() -> [[usize; 9]; 4] {
+    let origins: [(i32, i32, i32, i32); 4] = [
+        (0, 0, 1, 1),
+        (0, 7, 1, -1),
+        (7, 0, -1, 1),
+        (7, 7, -1, -1),
+    ];
+    let mut regions = [[0; 9]; 4];
+    for (region, &(row, col, row_step, col_step)) in regions.iter_mut().zip(origins.iter()) {
+        for i in 0..3 {
+            for j in 0..3 {
+                region[(i * 3 + j) as usize] = ((row + row_step * i) * 8 + col + col_step * j) as usize;
+            }
+        }
+    }
+    regions
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{ rngs::StdRng, SeedableRng };
+
+    use crate::gameplay::{ bitboard::Bitboard, constants::CODE_CHARS, encoding::string_from_board };
+
+    use super::{ train_self_play, TdConfig, ValueEstimator, NUM_FEATURES };
+
+    #[test]
+    fn initial_features_are_balanced() {
+        let features = ValueEstimator::features(&Bitboard::new());
+        assert!(features.iter().all(|&(index, _)| index < NUM_FEATURES));
+        // disc, mobility, corner, x and c square differences
+        assert!(features[1..6].iter().all(|&(_, value)| value == 0.0));
+    }
+
+    #[test]
+    fn learns_terminal_reward() {
+        let mut estimator = ValueEstimator::new(TdConfig {
+            alpha: 0.5,
+            ..TdConfig::default()
+        });
+        let state = string_from_board(Bitboard::new().to_board(), CODE_CHARS);
+        for _ in 0..50 {
+            estimator.update(&state, 1.0, true);
+        }
+        assert!((estimator.value(&Bitboard::new()) - 1.0).abs() < 0.05);
+        assert!(estimator.value_for_player(&Bitboard::new(), 1) < -0.95);
+    }
+
+    #[test]
+    fn self_play_and_save_round_trip() {
+        let mut estimator = ValueEstimator::default();
+        train_self_play(&mut estimator, 3, 0.1, &mut StdRng::seed_from_u64(5));
+        let path = std::env::temp_dir().join("othello_value_estimator_test.json");
+        let path = path.to_str().unwrap();
+        estimator.save(path).unwrap();
+        let loaded = ValueEstimator::load(path).unwrap();
+        std::fs::remove_file(path).ok();
+        assert_eq!(loaded.value(&Bitboard::new()), estimator.value(&Bitboard::new()));
+
+        let mut repeated = ValueEstimator::default();
+        train_self_play(&mut repeated, 3, 0.1, &mut StdRng::seed_from_u64(5));
+        assert_eq!(repeated.value(&Bitboard::new()), estimator.value(&Bitboard::new()));
+    }
+}
@@ -0,0 +1,30 @@
+//!
+//! Burn backend used by the binaries. NdArray runs on the CPU and is the default, build with
+//! `--features wgpu` to run on the GPU instead.
+
+#[cfg(not(feature = "wgpu"))]
+use burn::backend::{ ndarray::NdArrayDevice, NdArray };
+#[cfg(feature = "wgpu")]
+use burn::backend::{ wgpu::WgpuDevice, Wgpu };
+use burn::{ backend::Autodiff, tensor::Device };
+
+#[cfg(not(feature = "wgpu"))]
+pub type DefaultBackend = NdArray;
+#[cfg(feature = "wgpu")]
+pub type DefaultBackend = Wgpu;
+
+/// Backend for training, the default backend with automatic differentiation.
+pub type DefaultAutodiffBackend = Autodiff<DefaultBackend>;
+
+pub fn default_device() -> Device<DefaultBackend> {
+    #[cfg(not(feature = "wgpu"))]
+    return NdArrayDevice::Cpu;
+    #[cfg(feature = "wgpu")]
+    return WgpuDevice::default();
+}
+
+///
+/// Name of the backend the crate was built with, for logging.
+pub fn backend_name() -> &'static str {
+    if cfg!(feature = "wgpu") { "wgpu" } else { "ndarray" }
+}
@@ -1,29 +1,41 @@
 use core::panic;
 
-use burn::backend::{ wgpu::WgpuDevice, Autodiff, Wgpu };
 use othello_agent::{
     agent::{ rule_based::RuleAgent, value_based::ValueAgent },
+    backend::{ default_device, DefaultBackend },
     gameplay::{
         constants::{ CODE_CHARS, INITIAL_BOARD },
         encoding::{ board_from_string, create_code_char_hash },
     },
     simulate::{ environment::{ OthelloEnvironment, OthelloPlayer }, history::GameHistoryStore },
 };
+use rand::{ rngs::StdRng, Rng, SeedableRng };
 use rl_examples::environment::Environment;
 use rl_examples::agents::agent::Agent;
 
 // TODO: use unique id for each player that is constant throughout the game
 // TODO: start each game in random, valid state
 pub fn main() {
-    let mut env: OthelloEnvironment = OthelloEnvironment::new();
+    // pass the printed seed to replay the same games
+    let seed = std::env::args()
+        .nth(1)
+        .map(|arg| arg.parse::<u64>().expect("Seed should be an integer"))
+        .unwrap_or_else(rand::random);
+    println!("Seed: {}", seed);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut env: OthelloEnvironment = OthelloEnvironment::with_rng(
+        StdRng::seed_from_u64(rng.gen())
+    );
     let player_b: OthelloPlayer = env.get_player_b();
     let player_a = env.get_player_a();
-    let mut value_agent: ValueAgent<Autodiff<Wgpu>> = ValueAgent::new(
+    let mut value_agent: ValueAgent<DefaultBackend> = ValueAgent::new(
         player_a.turn_id as u8,
         INITIAL_BOARD,
-        WgpuDevice::default()
+        default_device()
     );
     let mut rule_agent = RuleAgent::new(player_b.turn_id as u8, INITIAL_BOARD);
+    value_agent.set_rng(StdRng::seed_from_u64(rng.gen()));
+    rule_agent.set_rng(StdRng::seed_from_u64(rng.gen()));
 
     let mut store = GameHistoryStore::new();
     let hash_map = create_code_char_hash(CODE_CHARS);
@@ -1,8 +1,27 @@
-use othello_agent::agent::utils::fetch_data;
+use othello_agent::{
+    agent::utils::fetch_data,
+    simulate::{ history::GameHistoryStore, import::{ load_ggf_file, load_wthor_file } },
+};
 
+///
+/// Writes the moves dataset used for training. Reads the eOthello csv by default, or
+/// `data_generate ggf <file>` / `data_generate wthor <file.wtb> [WTHOR.JOU]` to import other
+/// game databases.
 pub fn main() {
-    let game_history_store: Option<othello_agent::simulate::history::GameHistoryStore> =
-        fetch_data();
+    let args = std::env::args().collect::<Vec<String>>();
+    let game_history_store: Option<GameHistoryStore> = match args.get(1).map(String::as_str) {
+        None => fetch_data(),
+        Some("ggf") => {
+            let path = args.get(2).expect("Expected the path of a GGF file");
+            Some(load_ggf_file(path).expect("Failed to read GGF file"))
+        }
+        Some("wthor") => {
+            let path = args.get(2).expect("Expected the path of a WTHOR .wtb file");
+            let players_path = args.get(3).map(String::as_str);
+            Some(load_wthor_file(path, players_path).expect("Failed to read WTHOR file"))
+        }
+        Some(format) => panic!("Unknown format {}, expected ggf or wthor", format),
+    };
     if game_history_store.is_none() {
         panic!("Failed to fetch data");
     }
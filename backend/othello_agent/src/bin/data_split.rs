@@ -1,13 +1,21 @@
 use othello_agent::simulate::utils::split_csv_dataset;
+use rand::{ rngs::StdRng, SeedableRng };
 
 pub fn main() {
     let path = "data/othello_moves_dataset.csv";
     // get train ratio from command line argument
     let args = std::env::args().collect::<Vec<String>>();
     let train_ratio = args[1].parse::<f64>().unwrap();
+    // pass the printed seed to split the same way again
+    let seed = args
+        .get(2)
+        .map(|arg| arg.parse::<u64>().expect("Seed should be an integer"))
+        .unwrap_or_else(rand::random);
+    println!("Seed: {}", seed);
+    let mut rng = StdRng::seed_from_u64(seed);
     let train_path = "data/othello_moves_train_dataset.csv";
     let test_path = "data/othello_moves_test_dataset.csv";
-    let res = split_csv_dataset(path, train_path, test_path, train_ratio);
+    let res = split_csv_dataset(path, train_path, test_path, train_ratio, &mut rng);
     if res.is_err() {
         panic!("Failed to split dataset");
     }
@@ -1,11 +1,11 @@
 use burn::{
-    backend::{ wgpu::WgpuDevice, Wgpu },
     config::Config,
     data::dataloader::DataLoaderBuilder,
     module::Module,
     optim::{ decay::WeightDecayConfig, AdamConfig },
     record::{ CompactRecorder, FullPrecisionSettings, NamedMpkFileRecorder, Recorder },
 };
+use othello_agent::backend::{ backend_name, default_device, DefaultBackend };
 use othello_agent::model::{
     batch::OthelloMoveBatcher,
     dataset::OthelloMovesDataset,
@@ -13,7 +13,8 @@ use othello_agent::model::{
 };
 
 pub fn main() {
-    let device = WgpuDevice::default();
+    let device = default_device();
+    println!("Running on the {} backend", backend_name());
     const ARTIFACT_DIR: &str = "tmp/othello_win_again_slim_training_artifacts";
     let config = OthelloMovesTrainingConfig::load(format!("{ARTIFACT_DIR}/config.json")).expect(
         "Config should exist for the model"
@@ -24,12 +25,12 @@ pub fn main() {
     let recorder = NamedMpkFileRecorder::<FullPrecisionSettings>::new();
 
     let model = config.model
-        .init::<Wgpu>(&device)
+        .init::<DefaultBackend>(&device)
         .load_file(format!("{ARTIFACT_DIR}/model"), &recorder, &device)
         .expect("Model should exist");
     println!("Model loaded successfully");
     // Data
-    let batcher_valid = OthelloMoveBatcher::<Wgpu>::new(device.clone());
+    let batcher_valid = OthelloMoveBatcher::<DefaultBackend>::new(default_device());
     let dataloader_test = DataLoaderBuilder::new(batcher_valid)
         .batch_size(config.batch_size)
         .shuffle(config.seed)
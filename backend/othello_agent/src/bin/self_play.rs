@@ -0,0 +1,99 @@
+use othello_agent::{
+    agent::value_based::ValueAgent,
+    backend::{ default_device, DefaultBackend },
+    gameplay::random_position::MAX_RANDOM_PLIES,
+    simulate::self_play::{ read_run_record, run_self_play, AgentKind, SelfPlayConfig },
+};
+
+/// Where the games are written unless `--output` is given.
+const DEFAULT_OUTPUT_PATH: &str = "data/self_play_dataset.csv";
+
+fn parse_agent(name: Option<&String>) -> AgentKind {
+    let name = name.map(String::as_str).unwrap_or("rule_based");
+    AgentKind::from_name(name).unwrap_or_else(|| {
+        panic!("Unknown agent {}, expected one of {}", name, AgentKind::NAMES.join(", "))
+    })
+}
+
+/// Value of `--flag <value>` in the arguments.
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    let position = args.iter().position(|arg| arg == flag)?;
+    let value = args.get(position + 1).map(String::as_str);
+    Some(value.unwrap_or_else(|| panic!("Expected a value after {}", flag)))
+}
+
+fn parse_count(args: &[String], flag: &str) -> Option<usize> {
+    flag_value(args, flag).map(|value| {
+        value.parse::<usize>().unwrap_or_else(|_| panic!("{} should be an integer", flag))
+    })
+}
+
+/// Value of `--opening-plies`, which cannot reach past the last random placement.
+fn parse_opening_plies(args: &[String]) -> Option<usize> {
+    let opening_plies = parse_count(args, "--opening-plies")?;
+    if opening_plies > MAX_RANDOM_PLIES {
+        panic!("--opening-plies should be at most {}", MAX_RANDOM_PLIES);
+    }
+    Some(opening_plies)
+}
+
+///
+/// Plays games between two agents on several threads and appends them to a csv file in the
+/// format of the moves dataset. Running the same command again resumes an interrupted run, and
+/// the same seed plays the same games whatever the number of threads. Without `--seed` a resumed
+/// run keeps the seed of the file it resumes.
+///
+/// `self_play <games> <black agent> <white agent> [--threads N] [--opening-plies N]
+/// [--report-every N] [--seed N] [--output file.csv]`
+pub fn main() {
+    let args = std::env::args().collect::<Vec<String>>();
+    let defaults = SelfPlayConfig::default();
+    let output_path = flag_value(&args, "--output").unwrap_or(DEFAULT_OUTPUT_PATH);
+    let recorded_seed = read_run_record(output_path)
+        .unwrap_or_else(|error| panic!("Failed to read the run record: {}", error))
+        .map(|record| record.settings.seed);
+    let config = SelfPlayConfig {
+        games: args
+            .get(1)
+            .map(|arg| arg.parse::<usize>().expect("Number of games should be an integer"))
+            .unwrap_or(defaults.games),
+        threads: parse_count(&args, "--threads").unwrap_or(defaults.threads),
+        black: parse_agent(args.get(2)),
+        white: parse_agent(args.get(3)),
+        opening_plies: parse_opening_plies(&args).unwrap_or(defaults.opening_plies),
+        report_every: parse_count(&args, "--report-every").unwrap_or(defaults.report_every),
+        table_megabytes: defaults.table_megabytes,
+        search_nodes: defaults.search_nodes,
+        seed: flag_value(&args, "--seed")
+            .map(|value| value.parse::<u64>().expect("--seed should be an integer"))
+            .or(recorded_seed)
+            .unwrap_or(defaults.seed),
+    };
+    let needs_model = [config.black, config.white].contains(&AgentKind::ValueBased);
+    let value_model = if needs_model {
+        let model = ValueAgent::<DefaultBackend>::load_value_model(&default_device());
+        Some(model.expect("The value based agent needs a trained model, run train_win first"))
+    } else {
+        None
+    };
+    println!(
+        "Playing {} games of {} (black) against {} (white) on {} threads into {}",
+        config.games,
+        config.black.name(),
+        config.white.name(),
+        config.threads,
+        output_path
+    );
+    println!("Seed: {}", config.seed);
+    let summary = run_self_play(&config, output_path, value_model.as_ref()).unwrap_or_else(
+        |error| panic!("Failed to play the self play games: {}", error)
+    );
+    println!("Games complete");
+    println!(
+        "Black wins {}, white wins {}, draws {} of {} games played",
+        summary.black_wins,
+        summary.white_wins,
+        summary.draws,
+        summary.played
+    );
+}
@@ -0,0 +1,12 @@
+use burn::optim::AdamConfig;
+use othello_agent::{
+    backend::{ backend_name, default_device, DefaultAutodiffBackend },
+    model::{ alpha_zero::{ run, AlphaZeroConfig }, policy_value::PolicyValueModelConfig },
+};
+
+fn main() {
+    let device = default_device();
+    println!("Training on the {} backend", backend_name());
+    let config = AlphaZeroConfig::new(PolicyValueModelConfig::new(), AdamConfig::new());
+    run::<DefaultAutodiffBackend>(device, "othello_alpha_zero_artifacts", config);
+}
@@ -0,0 +1,8 @@
+use othello_agent::backend::{ backend_name, default_device, DefaultAutodiffBackend };
+use othello_agent::model::train_policy::run;
+
+fn main() {
+    let device = default_device();
+    println!("Training on the {} backend", backend_name());
+    run::<DefaultAutodiffBackend>(device, "othello_policy_training_artifacts");
+}
@@ -0,0 +1,32 @@
+use othello_agent::agent::value_estimator::{ train_self_play, ValueEstimator };
+use rand::{ rngs::StdRng, SeedableRng };
+
+pub fn main() {
+    const ESTIMATOR_PATH: &str = "tmp/td_value_estimator.json";
+    const EPSILON: f64 = 0.1;
+    const EPISODES_PER_REPORT: usize = 100;
+    // get number of self-play games from command line argument
+    let args = std::env::args().collect::<Vec<String>>();
+    let episodes = args
+        .get(1)
+        .map(|arg| arg.parse::<usize>().expect("Number of episodes should be an integer"))
+        .unwrap_or(1000);
+    // pass the printed seed to train the same way again
+    let seed = args
+        .get(2)
+        .map(|arg| arg.parse::<u64>().expect("Seed should be an integer"))
+        .unwrap_or_else(rand::random);
+    println!("Seed: {}", seed);
+    let mut rng = StdRng::seed_from_u64(seed);
+    // continue training from the last saved estimator if there is one
+    let mut estimator = ValueEstimator::load(ESTIMATOR_PATH).unwrap_or_default();
+    let mut completed = 0;
+    while completed < episodes {
+        let batch = EPISODES_PER_REPORT.min(episodes - completed);
+        train_self_play(&mut estimator, batch, EPSILON, &mut rng);
+        completed += batch;
+        println!("Self-play games: {}", completed);
+        estimator.save(ESTIMATOR_PATH).expect("Failed to save value estimator");
+    }
+    println!("Saved value estimator to {}", ESTIMATOR_PATH);
+}
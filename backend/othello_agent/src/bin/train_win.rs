@@ -1,7 +1,8 @@
-use burn::backend::{ wgpu::WgpuDevice, Autodiff, Wgpu };
+use othello_agent::backend::{ backend_name, default_device, DefaultAutodiffBackend };
 use othello_agent::model::train::run;
 
 fn main() {
-    let device = WgpuDevice::default();
-    run::<Autodiff<Wgpu>>(device, "othello_win_again_slim_training_artifacts");
+    let device = default_device();
+    println!("Training on the {} backend", backend_name());
+    run::<DefaultAutodiffBackend>(device, "othello_win_again_slim_training_artifacts");
 }
@@ -0,0 +1,90 @@
+//!
+//! Errors of the fallible gameplay api. The panicking functions are kept for code that has
+//! already checked its input, anything handling outside input should use the `try_` variants.
+
+use std::fmt;
+
+use crate::gameplay::game::IPlayer;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OthelloError {
+    /// A square that is not on the board, given as a row and column.
+    InvalidPosition {
+        downwards: i64,
+        rightwards: i64,
+    },
+    /// A player other than 0 (black) or 1 (white).
+    InvalidPlayer(IPlayer),
+    /// A move the player cannot play, the square is taken or nothing would be flipped.
+    IllegalMove {
+        move_index: usize,
+        player: IPlayer,
+    },
+    /// A pass by a player who has a legal placement.
+    IllegalPass {
+        player: IPlayer,
+    },
+    /// Neither player can move.
+    GameOver,
+    /// An encoded board with a character that is not in the code characters.
+    InvalidBoardCharacter(char),
+    /// An encoded board of the wrong length.
+    InvalidBoardLength(usize),
+    /// A packed position with pieces of both players on the squares of the mask.
+    OverlappingPieces(u64),
+    /// A board whose width or height is odd or outside 4 to 14.
+    InvalidBoardSize {
+        width: usize,
+        height: usize,
+    },
+    /// A range of random placements that is empty or reaches past the last one, 59.
+    InvalidPlyRange {
+        min: usize,
+        max: usize,
+    },
+}
+
+impl fmt::Display for OthelloError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OthelloError::InvalidPosition { downwards, rightwards } => {
+                write!(f, "row {} column {} is not on the board", downwards, rightwards)
+            }
+            OthelloError::InvalidPlayer(player) => {
+                write!(f, "player {} is not 0 (black) or 1 (white)", player)
+            }
+            OthelloError::IllegalMove { move_index, player } => {
+                write!(f, "move {} is not legal for player {}", move_index, player)
+            }
+            OthelloError::IllegalPass { player } => {
+                write!(f, "player {} cannot pass while they have a legal move", player)
+            }
+            OthelloError::GameOver => write!(f, "the game is over"),
+            OthelloError::InvalidBoardCharacter(c) => {
+                write!(f, "encoded board contains unknown character {:?}", c)
+            }
+            OthelloError::InvalidBoardLength(length) => {
+                write!(f, "encoded board has {} characters", length)
+            }
+            OthelloError::OverlappingPieces(mask) => {
+                write!(f, "packed position has both colours on squares {:#018x}", mask)
+            }
+            OthelloError::InvalidBoardSize { width, height } => {
+                write!(f, "a {}x{} board is not supported", width, height)
+            }
+            OthelloError::InvalidPlyRange { min, max } => {
+                write!(f, "cannot play between {} and {} random placements", min, max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for OthelloError {}
+
+/// Checks that `player` is black or white.
+pub fn check_player(player: IPlayer) -> Result<IPlayer, OthelloError> {
+    if player > 1 {
+        return Err(OthelloError::InvalidPlayer(player));
+    }
+    Ok(player)
+}
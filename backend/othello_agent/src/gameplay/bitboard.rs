@@ -0,0 +1,390 @@
+use super::{
+    constants::INITIAL_BOARD,
+    game::{ IBoard, IPlayer },
+    position::IPosition,
+    utils::is_piece_placeholder,
+};
+
+/// Every square in the a-file (leftmost column).
+const FILE_A: u64 = 0x0101_0101_0101_0101;
+/// Every square in the h-file (rightmost column).
+const FILE_H: u64 = 0x8080_8080_8080_8080;
+/// Every square in the top and bottom rows.
+const OUTER_ROWS: u64 = 0xff00_0000_0000_00ff;
+pub const CORNER_MASK: u64 = 0x8100_0000_0000_0081;
+pub const EDGE_MASK: u64 = (OUTER_ROWS | FILE_A | FILE_H) & !CORNER_MASK;
+
+/// Shift amounts and wrap-around masks for the eight directions. A positive shift moves a bit
+/// towards higher piece indices (rightwards/downwards), a negative shift towards lower ones.
+const SHIFTS: [(i8, u64); 8] = [
+    (8, !0),
+    (9, !FILE_A),
+    (1, !FILE_A),
+    (-7, !FILE_A),
+    (-8, !0),
+    (-9, !FILE_H),
+    (-1, !FILE_H),
+    (7, !FILE_H),
+];
+
+fn shift(mask: u64, (amount, wrap_mask): (i8, u64)) -> u64 {
+    if amount > 0 {
+        (mask << amount) & wrap_mask
+    } else {
+        (mask >> -amount) & wrap_mask
+    }
+}
+
+///
+/// Compact board representation holding one 64 bit mask per player. Bit `i` corresponds to the
+/// piece index `i` used by `IPosition::to_piece_index`, so a1 is bit 0 and h8 is bit 63.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Bitboard {
+    pub pieces: [u64; 2],
+}
+
+impl Bitboard {
+    pub fn new() -> Bitboard {
+        Bitboard::from_board(INITIAL_BOARD)
+    }
+
+    pub fn from_board(board: IBoard) -> Bitboard {
+        let mut pieces = [0u64; 2];
+        for (row_index, row) in board.iter().enumerate() {
+            for (col_index, &piece) in row.iter().enumerate() {
+                if is_piece_placeholder(piece) {
+                    continue;
+                }
+                pieces[piece as usize] |= 1 << (row_index * 8 + col_index);
+            }
+        }
+        Bitboard { pieces }
+    }
+
+    pub fn to_board(&self) -> IBoard {
+        let mut board = INITIAL_BOARD;
+        for (index, piece) in board.iter_mut().flatten().enumerate() {
+            let bit = 1u64 << index;
+            *piece = if self.pieces[0] & bit != 0 {
+                0
+            } else if self.pieces[1] & bit != 0 {
+                1
+            } else {
+                2
+            };
+        }
+        board
+    }
+
+    pub fn occupied(&self) -> u64 {
+        self.pieces[0] | self.pieces[1]
+    }
+
+    pub fn empty(&self) -> u64 {
+        !self.occupied()
+    }
+
+    /// Number of pieces the given player has on the board.
+    pub fn count(&self, player: IPlayer) -> u32 {
+        self.pieces[player as usize].count_ones()
+    }
+
+    pub fn count_empty(&self) -> u32 {
+        self.empty().count_ones()
+    }
+
+    ///
+    /// Returns a mask with a bit set for every square the given player can legally play.
+    pub fn legal_moves(&self, player: IPlayer) -> u64 {
+        let own = self.pieces[player as usize];
+        let opponent = self.pieces[1 - (player as usize)];
+        let empty = self.empty();
+        let mut moves = 0;
+        for direction in SHIFTS {
+            // a run of opponent pieces is at most six long on an 8x8 board
+            let mut run = shift(own, direction) & opponent;
+            for _ in 0..5 {
+                run |= shift(run, direction) & opponent;
+            }
+            moves |= shift(run, direction) & empty;
+        }
+        moves
+    }
+
+    pub fn has_move(&self, player: IPlayer) -> bool {
+        self.legal_moves(player) != 0
+    }
+
+    ///
+    /// Returns a mask of the opponent pieces flipped by the given player playing at `index`.
+    /// Does not check whether the square is empty.
+    pub fn flips(&self, index: usize, player: IPlayer) -> u64 {
+        let own = self.pieces[player as usize];
+        let opponent = self.pieces[1 - (player as usize)];
+        let origin = 1u64 << index;
+        let mut flips = 0;
+        for direction in SHIFTS {
+            let mut line = 0;
+            let mut current = shift(origin, direction);
+            while current & opponent != 0 {
+                line |= current;
+                current = shift(current, direction);
+            }
+            if current & own != 0 {
+                flips |= line;
+            }
+        }
+        flips
+    }
+
+    ///
+    /// Plays a piece for the given player at `index` and returns the resulting board.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Bitboard>` - None if the index is off the board, the square is taken or no piece is flipped.
+    pub fn play(&self, index: usize, player: IPlayer) -> Option<Bitboard> {
+        if index >= 64 || self.occupied() & (1 << index) != 0 {
+            return None;
+        }
+        let flips = self.flips(index, player);
+        if flips == 0 {
+            return None;
+        }
+        let mut next = *self;
+        next.pieces[player as usize] |= flips | (1 << index);
+        next.pieces[1 - (player as usize)] &= !flips;
+        Some(next)
+    }
+
+    ///
+    /// Plays a legal move in place, so searches can walk the tree on a single board.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Piece index of the move, which must be legal for `player`.
+    /// * `player` - The side to move.
+    ///
+    /// # Returns
+    ///
+    /// * `u64` - The flipped squares, to pass to `unmake_move`.
+    pub fn make_move(&mut self, index: usize, player: IPlayer) -> u64 {
+        let flips = self.flips(index, player);
+        debug_assert!(flips != 0 && self.occupied() & (1 << index) == 0, "Expected a legal move");
+        self.pieces[player as usize] ^= flips | (1 << index);
+        self.pieces[1 - (player as usize)] ^= flips;
+        flips
+    }
+
+    ///
+    /// Takes back a move played with `make_move`.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Piece index of the move.
+    /// * `player` - The side that played it.
+    /// * `flips` - The mask returned by `make_move`.
+    pub fn unmake_move(&mut self, index: usize, player: IPlayer, flips: u64) {
+        self.pieces[player as usize] ^= flips | (1 << index);
+        self.pieces[1 - (player as usize)] ^= flips;
+    }
+
+    ///
+    /// Bitboard equivalent of `augmented_score_for_player`.
+    pub fn augmented_score(
+        &self,
+        player: IPlayer,
+        corner_score: i16,
+        edge_score: i16,
+        other_score: i16
+    ) -> i16 {
+        let own = self.pieces[player as usize];
+        let corners = (own & CORNER_MASK).count_ones() as i16;
+        let edges = (own & EDGE_MASK).count_ones() as i16;
+        let others = (own & !(CORNER_MASK | EDGE_MASK)).count_ones() as i16;
+        corners * corner_score + edges * edge_score + others * other_score
+    }
+}
+
+impl Default for Bitboard {
+    fn default() -> Self {
+        Bitboard::new()
+    }
+}
+
+/// Converts a mask into the piece indices of its set bits, lowest index first.
+pub fn indices_from_mask(mut mask: u64) -> Vec<usize> {
+    let mut indices = Vec::with_capacity(mask.count_ones() as usize);
+    while mask != 0 {
+        indices.push(mask.trailing_zeros() as usize);
+        mask &= mask - 1;
+    }
+    indices
+}
+
+/// Converts a mask into positions, in the same row-major order `IGame::get_valid_moves` uses.
+pub fn positions_from_mask(mask: u64) -> Vec<IPosition> {
+    indices_from_mask(mask)
+        .into_iter()
+        .filter_map(|index| IPosition::position_from_piece_index(index as i8))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{ rngs::StdRng, seq::SliceRandom, SeedableRng };
+
+    use crate::gameplay::{
+        constants::INITIAL_BOARD,
+        game::{ IBoard, IPlayer },
+        position::IPosition,
+        recommender::suggest_moves_rules_based,
+        utils::{
+            augmented_score_for_player,
+            board_by_playing_piece_at_index,
+            flippable_pieces,
+            is_piece_placeholder,
+            player_has_move,
+            worst_score_by_playing_piece_at_index,
+        },
+    };
+
+    use super::{ indices_from_mask, Bitboard };
+
+    /// Legal move indices computed with the array implementation.
+    fn array_legal_moves(board: IBoard, player: IPlayer) -> Vec<usize> {
+        let mut moves = Vec::new();
+        for index in 0..64 {
+            let position = IPosition::position_from_piece_index(index as i8).unwrap();
+            let piece = board[position.downwards as usize][position.rightwards as usize];
+            if is_piece_placeholder(piece) && !flippable_pieces(board, &position, player).is_empty() {
+                moves.push(index);
+            }
+        }
+        moves
+    }
+
+    #[test]
+    fn initial_board_round_trips() {
+        let bitboard = Bitboard::from_board(INITIAL_BOARD);
+        assert_eq!(bitboard, Bitboard::new());
+        assert_eq!(bitboard.to_board(), INITIAL_BOARD);
+        assert_eq!(bitboard.count(0), 2);
+        assert_eq!(bitboard.count(1), 2);
+        assert_eq!(bitboard.count_empty(), 60);
+        assert_eq!(indices_from_mask(bitboard.legal_moves(0)).len(), 4);
+    }
+
+    #[test]
+    fn rejects_illegal_plays() {
+        let bitboard = Bitboard::new();
+        // occupied square
+        assert!(bitboard.play(27, 0).is_none());
+        // empty square that flips nothing
+        assert!(bitboard.play(0, 0).is_none());
+        // off the board
+        assert!(bitboard.play(64, 0).is_none());
+    }
+
+    #[test]
+    fn matches_array_implementation_on_random_playouts() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..200 {
+            let mut board = INITIAL_BOARD;
+            let mut player: IPlayer = 0;
+            loop {
+                let bitboard = Bitboard::from_board(board);
+                assert_eq!(bitboard.to_board(), board);
+                for p in 0..2 {
+                    assert_eq!(indices_from_mask(bitboard.legal_moves(p)), array_legal_moves(board, p));
+                    assert_eq!(bitboard.has_move(p), player_has_move(board, p));
+                    assert_eq!(
+                        bitboard.augmented_score(p, 12, 4, 1),
+                        augmented_score_for_player(board, p, 12, 4, 1)
+                    );
+                    assert_eq!(
+                        bitboard.count(p) as i16,
+                        augmented_score_for_player(board, p, 1, 1, 1)
+                    );
+                }
+                let moves = array_legal_moves(board, player);
+                let chosen = match moves.choose(&mut rng) {
+                    Some(&index) => index,
+                    None => {
+                        if !player_has_move(board, 1 - player) {
+                            break;
+                        }
+                        player = 1 - player;
+                        continue;
+                    }
+                };
+                let position = IPosition::position_from_piece_index(chosen as i8).unwrap();
+                let array_board = board_by_playing_piece_at_index(board, &position, player).unwrap();
+                let played = bitboard.play(chosen, player).unwrap();
+                assert_eq!(played.to_board(), array_board);
+                let mut in_place = bitboard;
+                let flips = in_place.make_move(chosen, player);
+                assert_eq!(in_place, played);
+                in_place.unmake_move(chosen, player, flips);
+                assert_eq!(in_place, bitboard);
+                assert_eq!(
+                    bitboard.flips(chosen, player).count_ones() as usize,
+                    flippable_pieces(board, &position, player).len()
+                );
+                board = array_board;
+                player = 1 - player;
+            }
+        }
+    }
+
+    #[test]
+    fn recommender_matches_array_scoring() {
+        let mut rng = StdRng::seed_from_u64(11);
+        for _ in 0..50 {
+            let mut board = INITIAL_BOARD;
+            let mut player: IPlayer = 0;
+            for _ in 0..30 {
+                let moves = array_legal_moves(board, player);
+                if moves.is_empty() {
+                    break;
+                }
+                // expected suggestions using the array implementation
+                let scores: Vec<(usize, i16)> = moves
+                    .iter()
+                    .map(|&index| {
+                        let position = IPosition::position_from_piece_index(index as i8).unwrap();
+                        let score = worst_score_by_playing_piece_at_index(
+                            board,
+                            position,
+                            player,
+                            12,
+                            4,
+                            1
+                        ).unwrap();
+                        (index, score)
+                    })
+                    .collect();
+                let best = scores
+                    .iter()
+                    .map(|&(_, score)| score)
+                    .min()
+                    .unwrap();
+                let expected: Vec<usize> = scores
+                    .iter()
+                    .filter(|&&(_, score)| score == best)
+                    .map(|&(index, _)| index)
+                    .collect();
+                let suggested: Vec<usize> = suggest_moves_rules_based(board, player)
+                    .iter()
+                    .map(|position| position.to_piece_index())
+                    .collect();
+                assert_eq!(suggested, expected);
+
+                let chosen = *moves.choose(&mut rng).unwrap();
+                let position = IPosition::position_from_piece_index(chosen as i8).unwrap();
+                board = board_by_playing_piece_at_index(board, &position, player).unwrap();
+                player = 1 - player;
+            }
+        }
+    }
+}
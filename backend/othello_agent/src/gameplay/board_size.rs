@@ -0,0 +1,142 @@
+use crate::error::OthelloError;
+
+use super::{
+    constants::{ DEFAULT_BOARD_HEIGHT, DEFAULT_BOARD_WIDTH, PLACEHOLDER },
+    encoding::encoded_length,
+    game::IPiece,
+    position::IPosition,
+};
+
+///
+/// Dimensions of a board. `IGame` and `Bitboard` only play on the standard 8x8 board, other
+/// sizes are played with `SizedGame`. The fields are private so that every size goes through
+/// the checks of `new`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BoardSize {
+    width: usize,
+    height: usize,
+}
+
+impl Default for BoardSize {
+    fn default() -> Self {
+        BoardSize { width: DEFAULT_BOARD_WIDTH, height: DEFAULT_BOARD_HEIGHT }
+    }
+}
+
+impl BoardSize {
+    /// 6x6 Othello, small enough for fast experiments.
+    pub const SMALL: BoardSize = BoardSize { width: 6, height: 6 };
+    pub const STANDARD: BoardSize = BoardSize { width: 8, height: 8 };
+    pub const LARGE: BoardSize = BoardSize { width: 10, height: 10 };
+
+    ///
+    /// Creates a board size. Both sides need to be even so the four starting pieces sit in the
+    /// centre, and at most 14 long so every square can be counted in a u8.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<BoardSize, OthelloError>` - `InvalidBoardSize` for an unsupported size.
+    pub fn new(width: usize, height: usize) -> Result<BoardSize, OthelloError> {
+        let supported = |side: usize| side.is_multiple_of(2) && (4..=14).contains(&side);
+        if !supported(width) || !supported(height) {
+            return Err(OthelloError::InvalidBoardSize { width, height });
+        }
+        Ok(BoardSize { width, height })
+    }
+
+    /// Number of columns.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Number of rows.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Number of squares on the board.
+    pub fn squares(&self) -> usize {
+        self.width * self.height
+    }
+
+    /// Length of a board of this size encoded with `string_from_pieces`.
+    pub fn encoded_length(&self) -> usize {
+        encoded_length(self.squares())
+    }
+
+    pub fn contains(&self, position: &IPosition) -> bool {
+        (0..self.height as i8).contains(&position.downwards) &&
+            (0..self.width as i8).contains(&position.rightwards)
+    }
+
+    /// Piece index of a position on this board, counted row by row.
+    pub fn index_of(&self, position: &IPosition) -> usize {
+        (position.downwards as usize) * self.width + (position.rightwards as usize)
+    }
+
+    /// Position of a piece index, None if it is not on the board.
+    pub fn position_of(&self, index: usize) -> Option<IPosition> {
+        if index >= self.squares() {
+            return None;
+        }
+        Some(IPosition {
+            downwards: (index / self.width) as i8,
+            rightwards: (index % self.width) as i8,
+        })
+    }
+
+    /// Pieces of the starting position, with white on the top left of the four centre squares.
+    pub fn initial_pieces(&self) -> Vec<IPiece> {
+        let mut pieces = vec![PLACEHOLDER; self.squares()];
+        let (row, col) = (self.height / 2 - 1, self.width / 2 - 1);
+        pieces[row * self.width + col] = 1;
+        pieces[row * self.width + col + 1] = 0;
+        pieces[(row + 1) * self.width + col] = 0;
+        pieces[(row + 1) * self.width + col + 1] = 1;
+        pieces
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::OthelloError,
+        gameplay::{ constants::INITIAL_BOARD, position::IPosition },
+    };
+
+    use super::BoardSize;
+
+    #[test]
+    fn checks_sizes() {
+        assert_eq!(BoardSize::new(6, 6), Ok(BoardSize::SMALL));
+        let size = BoardSize::new(10, 8).unwrap();
+        assert_eq!((size.width(), size.height(), size.squares()), (10, 8, 80));
+        let error = OthelloError::InvalidBoardSize { width: 7, height: 8 };
+        assert_eq!(BoardSize::new(7, 8), Err(error));
+        assert!(BoardSize::new(2, 2).is_err());
+        assert!(BoardSize::new(0, 8).is_err());
+        assert!(BoardSize::new(16, 16).is_err());
+        assert_eq!(BoardSize::default(), BoardSize::STANDARD);
+    }
+
+    #[test]
+    fn starts_like_the_standard_board() {
+        let pieces: Vec<u8> = INITIAL_BOARD.iter().flatten().copied().collect();
+        assert_eq!(BoardSize::STANDARD.initial_pieces(), pieces);
+        let small = BoardSize::SMALL.initial_pieces();
+        assert_eq!((small[14], small[15], small[20], small[21]), (1, 0, 0, 1));
+        assert_eq!(small.iter().filter(|&&piece| piece != 2).count(), 4);
+    }
+
+    #[test]
+    fn converts_indices_and_positions() {
+        let size = BoardSize::LARGE;
+        let position = IPosition { downwards: 9, rightwards: 3 };
+        assert_eq!(size.index_of(&position), 93);
+        assert_eq!(size.position_of(93), Some(position));
+        assert_eq!(size.position_of(100), None);
+        assert!(size.contains(&position));
+        assert!(!BoardSize::SMALL.contains(&position));
+        assert_eq!(size.encoded_length(), 34);
+    }
+}
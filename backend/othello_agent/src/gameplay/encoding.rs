@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use crate::gameplay::constants::INITIAL_BOARD;
+use crate::{ error::OthelloError, gameplay::constants::INITIAL_BOARD };
 
 use super::game::{ IBoard, IBoardForML };
 
@@ -23,12 +23,30 @@ pub fn string_from_board(board: IBoard, code_chars: &str) -> String {
         .flatten()
         .map(|&i| i)
         .collect();
-    // convert board to string and append the string 22 to the end
+    string_from_pieces(&board, code_chars)
+}
+
+///
+/// Encodes the pieces of a board of any size, row by row. Every three pieces are read as a base
+/// 3 number and written as one code character.
+///
+/// # Arguments
+///
+/// * `pieces` - piece values, 0 to 2, of every square
+/// * `code_chars` - string of code characters
+///
+/// # Returns
+///
+/// * `String` - `encoded_length(pieces.len())` characters, the last group padded with empty
+///   squares.
+pub fn string_from_pieces(pieces: &[u8], code_chars: &str) -> String {
+    // convert board to string and pad it with empty squares to a multiple of 3
+    let padding = (3 - (pieces.len() % 3)) % 3;
     let joined_board =
-        board
+        pieces
             .iter()
             .map(|&i| i.to_string())
-            .collect::<String>() + "22";
+            .collect::<String>() + &"2".repeat(padding);
     let mut chunks = Vec::new();
     let mut i = 0;
     // split the string into chunks of 3
@@ -47,6 +65,51 @@ pub fn string_from_board(board: IBoard, code_chars: &str) -> String {
         .collect()
 }
 
+/// Length of a board encoded with `string_from_board`, 64 pieces and 2 padding digits in base 27.
+pub const ENCODED_BOARD_LENGTH: usize = 22;
+
+/// Length of an encoded board with `squares` squares, see `string_from_pieces`.
+pub fn encoded_length(squares: usize) -> usize {
+    squares.div_ceil(3)
+}
+
+///
+/// Decodes the piece values, 0 to 2, of an encoded board of any size.
+///
+/// # Arguments
+///
+/// * `s` - string to decode
+/// * `squares` - number of squares on the board
+/// * `code_char_hash` - hashmap of code characters
+///
+/// # Returns
+///
+/// * `Result<Vec<u8>, OthelloError>` - The pieces row by row, or why the string is not an
+///   encoded board of that size.
+pub fn try_pieces_from_string(
+    s: &str,
+    squares: usize,
+    code_char_hash: &HashMap<char, u8>
+) -> Result<Vec<u8>, OthelloError> {
+    let length = s.chars().count();
+    if length != encoded_length(squares) {
+        return Err(OthelloError::InvalidBoardLength(length));
+    }
+    let mut pieces = Vec::with_capacity(length * 3);
+    for x in s.chars() {
+        let code = code_char_hash.get(&x).ok_or(OthelloError::InvalidBoardCharacter(x))?;
+        let sum = 27 + *code;
+        let mut base_3_string = format_radix(sum as u32, 3);
+        // clear whitespace
+        base_3_string.retain(|c| !c.is_whitespace());
+        // slice the last 3 characters
+        base_3_string = base_3_string[base_3_string.len() - 3..].to_string();
+        pieces.extend(base_3_string.chars().map(|c| c.to_digit(10).unwrap() as u8));
+    }
+    pieces.truncate(squares);
+    Ok(pieces)
+}
+
 ///
 /// Decodes string into board
 ///
@@ -55,55 +118,68 @@ pub fn string_from_board(board: IBoard, code_chars: &str) -> String {
 /// * `s` - string to decode
 /// * `code_char_hash` - hashmap of code characters
 ///
-/// # Note
+/// # Panics
 ///
-/// Relies on there being 64 characters in the board
+/// Panics if the string is not an encoded board, see `try_board_from_string`.
 pub fn board_from_string(s: &str, code_char_hash: &HashMap<char, u8>) -> IBoard {
-    let mut board: Vec<u8> = s
-        .chars()
-        .flat_map(|x| {
-            let sum = 27 + *code_char_hash.get(&x).unwrap();
-            let mut base_3_string = format_radix(sum as u32, 3);
-            // clear whitespace
-            base_3_string.retain(|c| !c.is_whitespace());
-            // slice the last 3 characters
-            base_3_string = base_3_string[base_3_string.len() - 3..].to_string();
-            base_3_string
-                .chars()
-                .map(|c| c.to_digit(10).unwrap() as u8)
-                .collect::<Vec<_>>()
-        })
-        .collect();
-    board.truncate(64);
+    try_board_from_string(s, code_char_hash).expect("Invalid encoded board")
+}
+
+///
+/// Decodes string into board
+///
+/// # Arguments
+///
+/// * `s` - string to decode
+/// * `code_char_hash` - hashmap of code characters
+///
+/// # Returns
+///
+/// * `Result<IBoard, OthelloError>` - The board, or why the string is not an encoded board.
+pub fn try_board_from_string(
+    s: &str,
+    code_char_hash: &HashMap<char, u8>
+) -> Result<IBoard, OthelloError> {
+    let pieces = try_pieces_from_string(s, 64, code_char_hash)?;
     // convert to 2d array
     let mut board_formatted: IBoard = INITIAL_BOARD;
-    for (i, &x) in board.iter().enumerate() {
+    for (i, &x) in pieces.iter().enumerate() {
         board_formatted[i / 8][i % 8] = x;
     }
-    board_formatted
+    Ok(board_formatted)
 }
 
+///
+/// Decodes string into the raw piece values as floats.
+///
+/// # Panics
+///
+/// Panics if the string is not an encoded board, see `try_board_floats_from_string`.
 pub fn board_floats_from_string(s: &str, code_char_hash: &HashMap<char, u8>) -> IBoardForML {
-    let mut board: Vec<f32> = s
-        .chars()
-        .flat_map(|x| {
-            let sum = 27 + *code_char_hash.get(&x).unwrap();
-            let mut base_3_string = format_radix(sum as u32, 3);
-            // clear whitespace
-            base_3_string.retain(|c| !c.is_whitespace());
-            // slice the last 3 characters
-            base_3_string = base_3_string[base_3_string.len() - 3..].to_string();
-            base_3_string
-                .chars()
-                .map(|c| c.to_digit(10).unwrap() as f32)
-                .collect::<Vec<_>>()
-        })
-        .collect();
-    board.truncate(64);
+    try_board_floats_from_string(s, code_char_hash).expect("Invalid encoded board")
+}
+
+pub fn try_board_floats_from_string(
+    s: &str,
+    code_char_hash: &HashMap<char, u8>
+) -> Result<IBoardForML, OthelloError> {
+    let pieces = try_pieces_from_string(s, 64, code_char_hash)?;
     // convert to 2d array
     let mut board_formatted: IBoardForML = [[0.0; 8]; 8];
-    for (i, &x) in board.iter().enumerate() {
-        board_formatted[i / 8][i % 8] = x;
+    for (i, &x) in pieces.iter().enumerate() {
+        board_formatted[i / 8][i % 8] = x as f32;
+    }
+    Ok(board_formatted)
+}
+
+///
+/// Inverse of `board_floats_from_string`: converts the float piece values back into a board.
+pub fn board_from_floats(board: IBoardForML) -> IBoard {
+    let mut board_formatted: IBoard = INITIAL_BOARD;
+    for (row_index, row) in board.iter().enumerate() {
+        for (col_index, &piece) in row.iter().enumerate() {
+            board_formatted[row_index][col_index] = piece as u8;
+        }
     }
     board_formatted
 }
@@ -134,9 +210,20 @@ fn format_radix(mut x: u32, radix: u32) -> String {
 
 #[cfg(test)]
 mod tests {
-    use crate::gameplay::{
-        constants::{ INITIAL_BOARD, CODE_CHARS },
-        encoding::{ create_code_char_hash, board_from_string },
+    use crate::{
+        error::OthelloError,
+        gameplay::{
+            constants::{ INITIAL_BOARD, CODE_CHARS },
+            encoding::{
+                create_code_char_hash,
+                board_from_string,
+                encoded_length,
+                string_from_pieces,
+                try_board_floats_from_string,
+                try_board_from_string,
+                try_pieces_from_string,
+            },
+        },
     };
 
     use super::string_from_board;
@@ -153,4 +240,31 @@ mod tests {
         let board_decoded = board_from_string(&board_str, &hashmap_chars);
         assert_eq!(INITIAL_BOARD, board_decoded);
     }
+
+    #[test]
+    fn rejects_invalid_encodings() {
+        let hashmap_chars = create_code_char_hash(CODE_CHARS);
+        let error = try_board_from_string("---------h-yq--------!", &hashmap_chars);
+        assert_eq!(error, Err(OthelloError::InvalidBoardCharacter('!')));
+        let error = try_board_floats_from_string("---------h-yq", &hashmap_chars);
+        assert_eq!(error, Err(OthelloError::InvalidBoardLength(13)));
+        let board = try_board_floats_from_string("---------h-yq---------", &hashmap_chars).unwrap();
+        assert_eq!(board[3][3], 1.0);
+        assert_eq!(board[3][4], 0.0);
+        assert_eq!(board[0][0], 2.0);
+    }
+
+    #[test]
+    fn encodes_boards_of_any_size() {
+        let hashmap_chars = create_code_char_hash(CODE_CHARS);
+        assert_eq!(encoded_length(64), 22);
+        for squares in [36, 64, 100] {
+            let pieces: Vec<u8> = (0..squares).map(|i| ((i * 7) % 3) as u8).collect();
+            let encoded = string_from_pieces(&pieces, CODE_CHARS);
+            assert_eq!(encoded.len(), encoded_length(squares));
+            assert_eq!(try_pieces_from_string(&encoded, squares, &hashmap_chars), Ok(pieces));
+        }
+        let error = try_pieces_from_string("---------h-yq", 36, &hashmap_chars);
+        assert_eq!(error, Err(OthelloError::InvalidBoardLength(13)));
+    }
 }
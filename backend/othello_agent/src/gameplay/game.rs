@@ -1,14 +1,17 @@
+use rand::Rng;
+
+use crate::error::OthelloError;
+
 use super::{
-    constants::{ CODE_CHARS, INITIAL_BOARD },
+    bitboard::{ indices_from_mask, positions_from_mask, Bitboard },
+    constants::{ CODE_CHARS, INITIAL_BOARD, NULL_MOVE_INDEX },
     encoding::string_from_board,
+    packed::PackedPosition,
     position::IPosition,
-    utils::{
-        augmented_score_for_player,
-        board_by_playing_piece_at_index,
-        flippable_pieces,
-        is_piece_placeholder,
-        random_board,
-    },
+    random_position::{ random_position, DEFAULT_RANDOM_PLIES },
+    rules::Rules,
+    utils::augmented_score_for_player,
+    zobrist::{ placement_delta, zobrist_hash, WHITE_TO_MOVE_KEY },
 };
 
 // this type can be 0,1,2
@@ -36,27 +39,123 @@ pub fn board_to_ml_input(board: IBoard) -> IBoardForML {
 
 pub type IBoardForML = [[f32; 8]; 8];
 
+///
+/// A move in a game, placing a piece or passing. A player may only pass when they have no legal
+/// placement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Move {
+    Place(IPosition),
+    Pass,
+}
+
+impl Move {
+    /// Piece index of the move, `NULL_MOVE_INDEX` for a pass.
+    pub fn to_index(&self) -> usize {
+        match self {
+            Move::Place(position) => position.to_piece_index(),
+            Move::Pass => NULL_MOVE_INDEX,
+        }
+    }
+
+    /// Inverse of `to_index`. None for an index that is neither on the board nor a pass.
+    pub fn from_index(index: usize) -> Option<Move> {
+        if index == NULL_MOVE_INDEX {
+            return Some(Move::Pass);
+        }
+        if index >= 64 {
+            return None;
+        }
+        IPosition::position_from_piece_index(index as i8).map(Move::Place)
+    }
+}
+
+///
+/// Final outcome of a game. Empty squares are counted for the winner, and shared in a draw, as
+/// in tournament play.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameResult {
+    /// None for a draw.
+    pub winner: Option<IPlayer>,
+    pub black_discs: u8,
+    pub white_discs: u8,
+}
+
+impl GameResult {
+    ///
+    /// Scores a finished 8x8 board.
+    ///
+    /// # Arguments
+    ///
+    /// * `black_discs` - Black discs on the board.
+    /// * `white_discs` - White discs on the board.
+    ///
+    /// # Returns
+    ///
+    /// * `GameResult` - The winner, with the empty squares added to their count.
+    pub fn from_disc_counts(black_discs: u8, white_discs: u8) -> GameResult {
+        GameResult::from_disc_counts_on(64, black_discs, white_discs)
+    }
+
+    /// Scores a finished board with `squares` squares, see `from_disc_counts`.
+    pub fn from_disc_counts_on(squares: u8, black_discs: u8, white_discs: u8) -> GameResult {
+        let empties = squares - black_discs - white_discs;
+        if black_discs > white_discs {
+            GameResult { winner: Some(0), black_discs: black_discs + empties, white_discs }
+        } else if white_discs > black_discs {
+            GameResult { winner: Some(1), black_discs, white_discs: white_discs + empties }
+        } else {
+            GameResult {
+                winner: None,
+                black_discs: black_discs + empties / 2,
+                white_discs: white_discs + empties / 2,
+            }
+        }
+    }
+
+    /// Final disc count of a player.
+    pub fn discs_for_player(&self, player: IPlayer) -> u8 {
+        if player == 0 { self.black_discs } else { self.white_discs }
+    }
+}
+
+///
+/// A move as it was played, with what is needed to take it back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ply {
+    pub player: IPlayer,
+    pub played: Move,
+    /// Mask of the squares turned over by the move, by piece index. Zero for a pass.
+    pub flipped: u64,
+    // last_piece before the move
+    previous_last_piece: IPiece,
+}
+
 pub struct IGame {
     pub board: IBoard,
     pub last_piece: IPiece,
     pub turn: IPlayer,
+    // plies played since the game was created, passes included
+    plies: Vec<Ply>,
+    // moves taken back by undo, the next one to redo last
+    undone: Vec<Move>,
+    // zobrist hash of board and turn, updated by every move
+    hash: u64,
 }
 
 impl IGame {
     pub fn new() -> IGame {
-        IGame {
-            board: INITIAL_BOARD,
-            last_piece: 0,
-            turn: 0,
-        }
+        IGame::from_board(INITIAL_BOARD, 0, 0)
     }
+    /// A random position that can come up in a game and is not over, see `random_position`.
     pub fn random_starting_state() -> IGame {
-        let (board, last_piece, turn) = random_board();
-        IGame {
-            board,
-            last_piece,
-            turn,
-        }
+        IGame::random_starting_state_with_rng(&mut rand::thread_rng())
+    }
+
+    /// Same as `random_starting_state`, drawing the moves from `rng`.
+    pub fn random_starting_state_with_rng<R: Rng>(rng: &mut R) -> IGame {
+        random_position(DEFAULT_RANDOM_PLIES, rng).expect(
+            "Expected a position within the default number of placements"
+        )
     }
 
     pub fn from_board(board: IBoard, last_piece: IPiece, turn: IPlayer) -> IGame {
@@ -64,28 +163,30 @@ impl IGame {
             board,
             last_piece,
             turn,
+            plies: Vec::new(),
+            undone: Vec::new(),
+            hash: zobrist_hash(&Bitboard::from_board(board), turn),
         }
     }
+
+    /// Zobrist hash of the board and the player to move, kept up to date move by move. Call
+    /// `refresh_hash` after changing `board` or `turn` directly.
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Recomputes `hash` from scratch.
+    pub fn refresh_hash(&mut self) {
+        self.hash = zobrist_hash(&Bitboard::from_board(self.board), self.turn);
+    }
+
+    /// The board in 16 bytes, see `PackedPosition`.
+    pub fn packed(&self) -> PackedPosition {
+        PackedPosition::from_board(self.board)
+    }
     pub fn get_valid_moves(&self, player: IPlayer) -> Vec<IPosition> {
-        let mut valid_moves: Vec<IPosition> = Vec::new();
-        for row_index in 0..8 {
-            for col_index in 0..8 {
-                let position = IPosition {
-                    downwards: row_index as i8,
-                    rightwards: col_index as i8,
-                };
-                // if already a piece at position, skip
-                let is_placeholder = is_piece_placeholder(self.board[row_index][col_index]);
-                if !is_placeholder {
-                    continue;
-                }
-                let flippable_positions = flippable_pieces(self.board, &position, player);
-                if flippable_positions.len() > 0 {
-                    valid_moves.push(position);
-                }
-            }
-        }
-        valid_moves
+        let moves = Bitboard::from_board(self.board).legal_moves(player);
+        positions_from_mask(moves)
     }
 
     ///
@@ -97,24 +198,182 @@ impl IGame {
     ///
     /// # Panics
     ///
-    /// Panics if the move is invalid.
+    /// Panics if the move is invalid, see `try_make_move_at_position`.
     pub fn make_move_at_position(&mut self, position: &IPosition) {
-        let flippeable_positions = flippable_pieces(self.board, &position, self.turn);
-        if flippeable_positions.len() == 0 {
-            panic!("Invalid move");
+        if let Err(error) = self.try_make_move_at_position(position) {
+            panic!("Invalid move: {}", error);
         }
-        let board = board_by_playing_piece_at_index(self.board, &position, self.turn);
-        if board.is_none() {
-            panic!("Invalid move");
+    }
+
+    ///
+    /// Makes a move at a given position for the player to move.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - The position to make the move at.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), OthelloError>` - `InvalidPosition` if the position is off the board,
+    ///   `IllegalMove` if the move is not legal. The game is unchanged on error.
+    pub fn try_make_move_at_position(&mut self, position: &IPosition) -> Result<(), OthelloError> {
+        let (downwards, rightwards) = (position.downwards, position.rightwards);
+        if !(0..8).contains(&downwards) || !(0..8).contains(&rightwards) {
+            return Err(OthelloError::InvalidPosition {
+                downwards: downwards as i64,
+                rightwards: rightwards as i64,
+            });
         }
-        self.board = board.unwrap();
-        self.last_piece = position.to_piece_index() as u8;
+        let move_index = position.to_piece_index();
+        let bitboard = Bitboard::from_board(self.board);
+        let board = bitboard
+            .play(move_index, self.turn)
+            .ok_or(OthelloError::IllegalMove { move_index, player: self.turn })?;
+        let flips = bitboard.flips(move_index, self.turn);
+        self.plies.push(Ply {
+            player: self.turn,
+            played: Move::Place(*position),
+            flipped: flips,
+            previous_last_piece: self.last_piece,
+        });
+        self.hash ^= placement_delta(move_index, flips, self.turn);
+        self.undone.clear();
+        self.board = board.to_board();
+        self.last_piece = move_index as u8;
         self.turn = 1 - self.turn;
+        Ok(())
+    }
+
+    ///
+    /// Makes a move at a piece index for the player to move.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), OthelloError>` - See `try_make_move_at_position`.
+    pub fn try_make_move_at_index(&mut self, move_index: usize) -> Result<(), OthelloError> {
+        if move_index >= 64 {
+            return Err(OthelloError::InvalidPosition {
+                downwards: (move_index / 8) as i64,
+                rightwards: (move_index % 8) as i64,
+            });
+        }
+        let downwards = (move_index / 8) as i8;
+        let rightwards = (move_index % 8) as i8;
+        self.try_make_move_at_position(&IPosition { downwards, rightwards })
+    }
+
+    ///
+    /// Replays moves from the starting position.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<IGame, OthelloError>` - The game after the moves, or the error of the first move
+    ///   that could not be played.
+    pub fn from_moves(moves: &[Move]) -> Result<IGame, OthelloError> {
+        let mut game = IGame::new();
+        for &next_move in moves {
+            game.try_make_move(next_move)?;
+        }
+        Ok(game)
+    }
+
+    /// Legal moves of the player to move, see `Rules::get_legal_moves`.
+    pub fn get_legal_moves(&self) -> Vec<Move> {
+        Rules::get_legal_moves(self)
+    }
+
+    /// The game is over once neither player can move, or after two passes in a row.
+    pub fn is_over(&self) -> bool {
+        Rules::is_over(self)
+    }
+
+    /// Discs a player has on the board.
+    pub fn disc_count(&self, player: IPlayer) -> u8 {
+        Bitboard::from_board(self.board).count(player) as u8
+    }
+
+    /// Outcome of the game, None while the game goes on, see `Rules::result`.
+    pub fn result(&self) -> Option<GameResult> {
+        Rules::result(self)
+    }
+
+    /// Scores of black and white: the disc counts, or the final scores once the game is over.
+    pub fn scores(&self) -> [i16; 2] {
+        Rules::scores(self)
+    }
+
+    ///
+    /// Plays a move for the player to move.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the move is not legal, see `try_make_move`.
+    pub fn make_move(&mut self, next_move: Move) {
+        Rules::make_move(self, next_move)
+    }
+
+    ///
+    /// Plays a move for the player to move and records it in `moves`. Moves that were undone can
+    /// no longer be redone afterwards.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), OthelloError>` - The errors of `Rules::try_make_move`.
+    pub fn try_make_move(&mut self, next_move: Move) -> Result<(), OthelloError> {
+        Rules::try_make_move(self, next_move)
+    }
+
+    /// Moves played since the game was created, passes included.
+    pub fn moves(&self) -> Vec<Move> {
+        self.plies.iter().map(|ply| ply.played).collect()
+    }
+
+    /// Moves played with the squares each of them flipped, oldest first.
+    pub fn plies(&self) -> &[Ply] {
+        &self.plies
+    }
+
+    ///
+    /// Takes back the last move in place, turning its flipped squares over again.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Move>` - The move taken back, None if no move was played since the game was
+    ///   created.
+    pub fn undo(&mut self) -> Option<Move> {
+        let ply = self.plies.pop()?;
+        self.hash ^= WHITE_TO_MOVE_KEY;
+        if let Move::Place(position) = ply.played {
+            // the delta toggles the turn again, so it is xored back out
+            let delta = placement_delta(position.to_piece_index(), ply.flipped, ply.player);
+            self.hash ^= delta ^ WHITE_TO_MOVE_KEY;
+            self.board[position.downwards as usize][position.rightwards as usize] = 2;
+            for index in indices_from_mask(ply.flipped) {
+                self.board[index / 8][index % 8] = 1 - ply.player;
+            }
+        }
+        self.last_piece = ply.previous_last_piece;
+        self.turn = ply.player;
+        self.undone.push(ply.played);
+        Some(ply.played)
+    }
+
+    ///
+    /// Plays the last move taken back by `undo` again.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Move>` - The move played, None if there is nothing to redo.
+    pub fn redo(&mut self) -> Option<Move> {
+        let next_move = self.undone.pop()?;
+        let undone = std::mem::take(&mut self.undone);
+        self.make_move(next_move);
+        self.undone = undone;
+        Some(next_move)
     }
 
     pub fn player_has_move(&self, player: IPlayer) -> bool {
-        let valid_moves = self.get_valid_moves(player);
-        valid_moves.len() > 0
+        Bitboard::from_board(self.board).has_move(player)
     }
 
     pub fn get_board_string(&self) -> String {
@@ -123,6 +382,7 @@ impl IGame {
 
     pub fn toggle_turn(&mut self) {
         self.turn = 1 - self.turn;
+        self.hash ^= WHITE_TO_MOVE_KEY;
     }
 
     pub fn score_for_player(&self, player: IPlayer) -> i16 {
@@ -130,3 +390,240 @@ impl IGame {
         score
     }
 }
+
+impl Rules for IGame {
+    fn turn(&self) -> IPlayer {
+        self.turn
+    }
+
+    fn squares(&self) -> usize {
+        64
+    }
+
+    fn previous_move(&self, back: usize) -> Option<Move> {
+        self.plies
+            .iter()
+            .rev()
+            .nth(back)
+            .map(|ply| ply.played)
+    }
+
+    fn placements(&self, player: IPlayer) -> Vec<IPosition> {
+        self.get_valid_moves(player)
+    }
+
+    fn player_has_move(&self, player: IPlayer) -> bool {
+        IGame::player_has_move(self, player)
+    }
+
+    fn disc_count(&self, player: IPlayer) -> u8 {
+        IGame::disc_count(self, player)
+    }
+
+    fn place(&mut self, position: &IPosition) -> Result<(), OthelloError> {
+        self.try_make_move_at_position(position)
+    }
+
+    fn record_pass(&mut self) {
+        self.plies.push(Ply {
+            player: self.turn,
+            played: Move::Pass,
+            flipped: 0,
+            previous_last_piece: self.last_piece,
+        });
+        self.undone.clear();
+        self.turn = 1 - self.turn;
+        self.hash ^= WHITE_TO_MOVE_KEY;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{ rngs::StdRng, SeedableRng };
+
+    use crate::{
+        error::OthelloError,
+        gameplay::{ constants::{ INITIAL_BOARD, NULL_MOVE_INDEX }, position::IPosition },
+    };
+
+    use super::{ GameResult, IGame, Move, Ply };
+
+    fn place(index: usize) -> Move {
+        Move::from_index(index).unwrap()
+    }
+
+    #[test]
+    fn rejects_moves_without_changing_the_game() {
+        let mut game = IGame::new();
+        let illegal = OthelloError::IllegalMove { move_index: 0, player: 0 };
+        assert_eq!(game.try_make_move_at_index(0), Err(illegal));
+        let off_board = OthelloError::InvalidPosition { downwards: 8, rightwards: 0 };
+        assert_eq!(game.try_make_move_at_index(64), Err(off_board));
+        let off_board = IPosition { rightwards: -1, downwards: 0 };
+        let error = OthelloError::InvalidPosition { downwards: 0, rightwards: -1 };
+        assert_eq!(game.try_make_move_at_position(&off_board), Err(error));
+        assert_eq!(game.board, INITIAL_BOARD);
+        assert_eq!(game.turn, 0);
+
+        assert_eq!(game.try_make_move_at_index(19), Ok(()));
+        assert_eq!(game.turn, 1);
+        assert_eq!(game.score_for_player(0), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid move")]
+    fn panics_on_illegal_moves() {
+        IGame::new().make_move_at_position(&IPosition { rightwards: 0, downwards: 0 });
+    }
+
+    #[test]
+    fn converts_moves_to_indices() {
+        assert_eq!(Move::from_index(NULL_MOVE_INDEX), Some(Move::Pass));
+        assert_eq!(Move::Pass.to_index(), NULL_MOVE_INDEX);
+        assert_eq!(place(37), Move::Place(IPosition { rightwards: 5, downwards: 4 }));
+        assert_eq!(place(37).to_index(), 37);
+        assert_eq!(Move::from_index(64), None);
+    }
+
+    #[test]
+    fn passes_are_moves() {
+        // black has no move after c1
+        let moves: Vec<Move> = [19, 18, 44, 11, 3, 4, 9, 2].into_iter().map(place).collect();
+        let mut game = IGame::from_moves(&moves).unwrap();
+        assert_eq!(game.turn, 0);
+        assert_eq!(game.get_legal_moves(), vec![Move::Pass]);
+        assert_eq!(game.try_make_move(place(16)), Err(OthelloError::IllegalMove {
+            move_index: 16,
+            player: 0,
+        }));
+        game.make_move(Move::Pass);
+        assert_eq!(game.turn, 1);
+        assert!(game.get_legal_moves().contains(&place(16)));
+        assert_eq!(game.try_make_move(Move::Pass), Err(OthelloError::IllegalPass { player: 1 }));
+        game.make_move(place(16));
+
+        // the recorded moves replay to the same game
+        let moves = game.moves();
+        assert_eq!(moves.len(), 10);
+        assert_eq!(moves[8], Move::Pass);
+        let replayed = IGame::from_moves(&moves).unwrap();
+        assert_eq!(replayed.board, game.board);
+        assert_eq!(replayed.turn, game.turn);
+    }
+
+    #[test]
+    fn detects_the_end_of_the_game() {
+        // black wipes out white in nine moves
+        let moves: Vec<Move> = [19, 18, 17, 11, 4, 43, 51, 20, 29].into_iter().map(place).collect();
+        let mut game = IGame::from_moves(&moves).unwrap();
+        assert!(game.is_over());
+        let result = GameResult { winner: Some(0), black_discs: 64, white_discs: 0 };
+        assert_eq!(game.result(), Some(result));
+        assert_eq!(game.disc_count(0), 13);
+        assert_eq!(game.scores(), [64, 0]);
+        assert!(game.get_legal_moves().is_empty());
+        assert_eq!(game.try_make_move(Move::Pass), Err(OthelloError::GameOver));
+
+        // two passes in a row end a game even when it was set up by hand
+        let mut game = IGame::new();
+        let pass = Ply {
+            player: 0,
+            played: Move::Pass,
+            flipped: 0,
+            previous_last_piece: 0,
+        };
+        game.plies = vec![pass.clone(), Ply { player: 1, ..pass }];
+        assert!(game.is_over());
+        assert!(!IGame::new().is_over());
+        assert_eq!(IGame::new().result(), None);
+        assert_eq!(IGame::new().scores(), [2, 2]);
+    }
+
+    #[test]
+    fn gives_empty_squares_to_the_winner() {
+        let result = GameResult::from_disc_counts(30, 28);
+        assert_eq!(result, GameResult { winner: Some(0), black_discs: 36, white_discs: 28 });
+        let result = GameResult::from_disc_counts(20, 40);
+        assert_eq!(result, GameResult { winner: Some(1), black_discs: 20, white_discs: 44 });
+        assert_eq!(result.discs_for_player(1), 44);
+        let result = GameResult::from_disc_counts(29, 29);
+        assert_eq!(result, GameResult { winner: None, black_discs: 32, white_discs: 32 });
+    }
+
+    #[test]
+    fn seeded_starting_states_repeat() {
+        let first = IGame::random_starting_state_with_rng(&mut StdRng::seed_from_u64(12));
+        let second = IGame::random_starting_state_with_rng(&mut StdRng::seed_from_u64(12));
+        assert_eq!(first.moves(), second.moves());
+        assert!(!first.is_over());
+    }
+
+    #[test]
+    fn undoes_and_redoes_moves() {
+        let mut game = IGame::new();
+        assert_eq!(game.undo(), None);
+        game.make_move(place(19));
+        game.make_move(place(18));
+        assert_eq!(game.plies()[1].flipped, 1 << 27);
+        let board = game.board;
+
+        assert_eq!(game.undo(), Some(place(18)));
+        assert_eq!(game.undo(), Some(place(19)));
+        assert_eq!(game.board, INITIAL_BOARD);
+        assert_eq!((game.turn, game.last_piece), (0, 0));
+        assert!(game.moves().is_empty());
+
+        assert_eq!(game.redo(), Some(place(19)));
+        assert_eq!(game.redo(), Some(place(18)));
+        assert_eq!(game.redo(), None);
+        assert_eq!(game.board, board);
+        assert_eq!(game.last_piece, 18);
+        assert_eq!(game.moves(), vec![place(19), place(18)]);
+
+        // a new move drops the moves that could be redone
+        game.undo();
+        game.make_move(place(20));
+        assert_eq!(game.redo(), None);
+    }
+
+    #[test]
+    fn undoes_passes() {
+        let moves: Vec<Move> = [19, 18, 44, 11, 3, 4, 9, 2].into_iter().map(place).collect();
+        let mut game = IGame::from_moves(&moves).unwrap();
+        let board = game.board;
+        game.make_move(Move::Pass);
+        game.make_move(place(16));
+        assert_eq!(game.undo(), Some(place(16)));
+        assert_eq!(game.undo(), Some(Move::Pass));
+        assert_eq!(game.board, board);
+        assert_eq!(game.turn, 0);
+        assert_eq!(game.get_legal_moves(), vec![Move::Pass]);
+    }
+
+    #[test]
+    fn keeps_the_hash_up_to_date() {
+        let moves: Vec<Move> = [19, 18, 44, 11, 3, 4, 9, 2].into_iter().map(place).collect();
+        let mut game = IGame::from_moves(&moves).unwrap();
+        game.make_move(Move::Pass);
+        game.make_move(place(16));
+        let hash = game.hash();
+        let mut fresh = IGame::from_board(game.board, 16, game.turn);
+        assert_eq!(fresh.hash(), hash);
+        fresh.toggle_turn();
+        assert_ne!(fresh.hash(), hash);
+
+        let mut hashes = vec![hash];
+        while game.undo().is_some() {
+            let mut expected = IGame::from_board(game.board, 0, game.turn);
+            expected.refresh_hash();
+            assert_eq!(game.hash(), expected.hash());
+            hashes.push(game.hash());
+        }
+        assert_eq!(game.hash(), IGame::new().hash());
+        while game.redo().is_some() {
+            hashes.pop();
+            assert_eq!(Some(&game.hash()), hashes.last());
+        }
+        assert_eq!(game.packed().to_board(), game.board);
+    }
+}
@@ -1,5 +1,7 @@
 pub mod utils;
 
+pub mod bitboard;
+
 pub mod encoding;
 
 pub mod constants;
@@ -9,3 +11,19 @@ pub mod recommender;
 pub mod position;
 
 pub mod game;
+
+pub mod rules;
+
+pub mod board_size;
+
+pub mod sized_game;
+
+pub mod zobrist;
+
+pub mod packed;
+
+pub mod random_position;
+
+pub mod symmetry;
+
+pub mod transcript;
@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+
+use serde::{ Deserialize, Serialize };
+
+use crate::error::OthelloError;
+
+use super::{
+    bitboard::Bitboard,
+    constants::CODE_CHARS,
+    encoding::{ string_from_board, try_board_from_string, ENCODED_BOARD_LENGTH },
+    game::{ IBoard, IPlayer },
+    zobrist::zobrist_hash,
+};
+
+/// Length of a position written with `PackedPosition::to_hex`.
+pub const PACKED_HEX_LENGTH: usize = 32;
+
+///
+/// How a board is written as a string, for states of the environment and boards of a history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StateEncoding {
+    /// The base 27 string of `string_from_board`, used by the csv datasets.
+    #[default]
+    CodeChars,
+    /// The 32 hex digits of `PackedPosition::to_hex`.
+    Packed,
+}
+
+///
+/// A board in 16 bytes: black's pieces as a little endian 64 bit mask, then white's. Bit `i` is
+/// the piece index `i`, as in `Bitboard`. The player to move is not included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct PackedPosition(pub [u8; 16]);
+
+impl PackedPosition {
+    pub fn from_bitboard(board: &Bitboard) -> PackedPosition {
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(&board.pieces[0].to_le_bytes());
+        bytes[8..].copy_from_slice(&board.pieces[1].to_le_bytes());
+        PackedPosition(bytes)
+    }
+
+    pub fn to_bitboard(&self) -> Bitboard {
+        let black = u64::from_le_bytes(self.0[..8].try_into().unwrap());
+        let white = u64::from_le_bytes(self.0[8..].try_into().unwrap());
+        Bitboard { pieces: [black, white] }
+    }
+
+    pub fn from_board(board: IBoard) -> PackedPosition {
+        PackedPosition::from_bitboard(&Bitboard::from_board(board))
+    }
+
+    pub fn to_board(&self) -> IBoard {
+        self.to_bitboard().to_board()
+    }
+
+    ///
+    /// Reads packed bytes, checking that no square holds both colours.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<PackedPosition, OthelloError>` - `OverlappingPieces` with the squares taken by
+    ///   both players.
+    pub fn try_from_bytes(bytes: [u8; 16]) -> Result<PackedPosition, OthelloError> {
+        let position = PackedPosition(bytes);
+        let board = position.to_bitboard();
+        let overlap = board.pieces[0] & board.pieces[1];
+        if overlap != 0 {
+            return Err(OthelloError::OverlappingPieces(overlap));
+        }
+        Ok(position)
+    }
+
+    /// Writes the bytes as 32 lowercase hex digits.
+    pub fn to_hex(&self) -> String {
+        self.0
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Reads a position written with `to_hex`.
+    pub fn try_from_hex(hex: &str) -> Result<PackedPosition, OthelloError> {
+        let length = hex.chars().count();
+        if length != PACKED_HEX_LENGTH {
+            return Err(OthelloError::InvalidBoardLength(length));
+        }
+        let mut bytes = [0; 16];
+        let digits: Vec<char> = hex.chars().collect();
+        for (byte, pair) in bytes.iter_mut().zip(digits.chunks(2)) {
+            let mut value = 0;
+            for &digit in pair {
+                let digit_value = digit
+                    .to_digit(16)
+                    .ok_or(OthelloError::InvalidBoardCharacter(digit))?;
+                value = value * 16 + digit_value;
+            }
+            *byte = value as u8;
+        }
+        PackedPosition::try_from_bytes(bytes)
+    }
+
+    /// Writes the position as a code char string, see `string_from_board`.
+    pub fn to_code_string(&self) -> String {
+        string_from_board(self.to_board(), CODE_CHARS)
+    }
+
+    /// Reads a code char string, as found in the csv datasets.
+    pub fn try_from_code_string(
+        s: &str,
+        code_char_hash: &HashMap<char, u8>
+    ) -> Result<PackedPosition, OthelloError> {
+        Ok(PackedPosition::from_board(try_board_from_string(s, code_char_hash)?))
+    }
+
+    ///
+    /// Reads a state in either `StateEncoding`, told apart by length.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - A code char string or 32 hex digits.
+    /// * `code_char_hash` - hashmap of code characters
+    ///
+    /// # Returns
+    ///
+    /// * `Result<PackedPosition, OthelloError>` - The position, or why the state is not one.
+    pub fn try_from_state(
+        state: &str,
+        code_char_hash: &HashMap<char, u8>
+    ) -> Result<PackedPosition, OthelloError> {
+        match state.chars().count() {
+            ENCODED_BOARD_LENGTH => PackedPosition::try_from_code_string(state, code_char_hash),
+            _ => PackedPosition::try_from_hex(state),
+        }
+    }
+
+    /// Writes the position in the given encoding.
+    pub fn to_state(&self, encoding: StateEncoding) -> String {
+        match encoding {
+            StateEncoding::CodeChars => self.to_code_string(),
+            StateEncoding::Packed => self.to_hex(),
+        }
+    }
+
+    /// Zobrist hash of the position with `turn` to move, see `zobrist_hash`.
+    pub fn zobrist_hash(&self, turn: IPlayer) -> u64 {
+        zobrist_hash(&self.to_bitboard(), turn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::OthelloError,
+        gameplay::{
+            constants::{ CODE_CHARS, INITIAL_BOARD },
+            encoding::{ create_code_char_hash, string_from_board },
+        },
+    };
+
+    use super::{ PackedPosition, StateEncoding };
+
+    #[test]
+    fn round_trips_boards() {
+        let position = PackedPosition::from_board(INITIAL_BOARD);
+        assert_eq!(position.to_board(), INITIAL_BOARD);
+        // d5 and e4 are black, bits 28 and 35
+        assert_eq!(position.0[3], 0x10);
+        assert_eq!(position.0[4], 0x08);
+        let hex = position.to_hex();
+        assert_eq!(hex, "00000010080000000000000810000000");
+        assert_eq!(PackedPosition::try_from_hex(&hex), Ok(position));
+        assert_eq!(PackedPosition::try_from_hex(&hex.to_uppercase()), Ok(position));
+    }
+
+    #[test]
+    fn converts_code_strings() {
+        let hash = create_code_char_hash(CODE_CHARS);
+        let code_string = string_from_board(INITIAL_BOARD, CODE_CHARS);
+        let position = PackedPosition::try_from_code_string(&code_string, &hash).unwrap();
+        assert_eq!(position.to_code_string(), code_string);
+        for encoding in [StateEncoding::CodeChars, StateEncoding::Packed] {
+            let state = position.to_state(encoding);
+            assert_eq!(PackedPosition::try_from_state(&state, &hash), Ok(position));
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_positions() {
+        let mut bytes = [0; 16];
+        bytes[0] = 0b11;
+        bytes[8] = 0b10;
+        let error = PackedPosition::try_from_bytes(bytes);
+        assert_eq!(error, Err(OthelloError::OverlappingPieces(0b10)));
+        let error = PackedPosition::try_from_hex("0000001008000000000000081000000g");
+        assert_eq!(error, Err(OthelloError::InvalidBoardCharacter('g')));
+        let hash = create_code_char_hash(CODE_CHARS);
+        let error = PackedPosition::try_from_state("00", &hash);
+        assert_eq!(error, Err(OthelloError::InvalidBoardLength(2)));
+    }
+}
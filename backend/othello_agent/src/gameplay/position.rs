@@ -3,7 +3,7 @@ use serde::Deserialize;
 use super::constants::{ DEFAULT_BOARD_HEIGHT, DEFAULT_BOARD_WIDTH };
 
 // derive debug
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
 pub struct IPosition {
     // can also represent a vector movement
     pub rightwards: i8,
@@ -32,7 +32,7 @@ impl IPosition {
     /// * `piece_index` - The index of the piece.
     ///
     pub fn to_piece_index(&self) -> usize {
-        (self.downwards as usize) * DEFAULT_BOARD_HEIGHT + (self.rightwards as usize)
+        (self.downwards as usize) * DEFAULT_BOARD_WIDTH + (self.rightwards as usize)
     }
 
     // convert a string position to a piece index
@@ -60,6 +60,14 @@ impl IPosition {
         })
     }
 
+    ///
+    /// Converts this position to its square name, the reverse of `position_from_string_position`.
+    /// e.g. rightwards 5, downwards 4 -> "f5"
+    pub fn to_string_position(&self) -> String {
+        let column = ((b'a' as i8) + self.rightwards) as u8 as char;
+        format!("{}{}", column, self.downwards + 1)
+    }
+
     ///
     /// Converts a 1d piece index to a 2d position characterized by rightward and downward movement.
     ///
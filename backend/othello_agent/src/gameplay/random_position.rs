@@ -0,0 +1,191 @@
+//!
+//! Random positions, reached by playing random legal moves from the initial board. Every
+//! position is one that can come up in a game, and none of them is over.
+
+use std::ops::RangeInclusive;
+
+use rand::{ seq::SliceRandom, Rng };
+
+use crate::error::OthelloError;
+
+use super::game::{ IGame, Move };
+
+/// Most placements a position can have without being over, the 60th fills the board.
+pub const MAX_RANDOM_PLIES: usize = 59;
+
+/// Placements played by `IGame::random_starting_state`, from the opening into the midgame.
+pub const DEFAULT_RANDOM_PLIES: RangeInclusive<usize> = 4..=30;
+
+// playouts that cannot reach the chosen ply without the game ending are started again
+const MAX_PLAYOUTS: usize = 1000;
+
+///
+/// Plays uniformly random legal moves from the initial board.
+///
+/// # Arguments
+///
+/// * `plies` - Range of the number of placements to play, passes are not counted.
+/// * `rng` - Source of randomness, seed it to get the same position again.
+///
+/// # Returns
+///
+/// * `Result<IGame, OthelloError>` - The game, see `random_position_with_policy`.
+pub fn random_position<R: Rng>(
+    plies: RangeInclusive<usize>,
+    rng: &mut R
+) -> Result<IGame, OthelloError> {
+    random_position_with_policy(plies, rng, |_, placements, rng| {
+        *placements.choose(rng).expect("Expected a placement to choose from")
+    })
+}
+
+///
+/// Plays moves chosen by a policy from the initial board. The number of placements is drawn
+/// from `plies` first. Moves that would end the game are not offered to the policy, and a
+/// player without a placement passes. The side to move of the returned game has a placement.
+///
+/// # Arguments
+///
+/// * `plies` - Range of the number of placements to play, within 0 to `MAX_RANDOM_PLIES`.
+/// * `rng` - Source of randomness, passed on to the policy.
+/// * `policy` - Picks one of the given piece indices to play in the given game.
+///
+/// # Returns
+///
+/// * `Result<IGame, OthelloError>` - The game with every move played, `InvalidPlyRange` for an
+///   empty or too long range, `IllegalMove` if the policy picks an index it was not given, or
+///   `GameOver` if no playout reached the chosen number of placements.
+pub fn random_position_with_policy<R, P>(
+    plies: RangeInclusive<usize>,
+    rng: &mut R,
+    mut policy: P
+) -> Result<IGame, OthelloError>
+    where R: Rng, P: FnMut(&IGame, &[usize], &mut R) -> usize
+{
+    if plies.is_empty() || *plies.end() > MAX_RANDOM_PLIES {
+        return Err(OthelloError::InvalidPlyRange { min: *plies.start(), max: *plies.end() });
+    }
+    let target = rng.gen_range(plies);
+    for _ in 0..MAX_PLAYOUTS {
+        if let Some(game) = playout(target, rng, &mut policy)? {
+            return Ok(game);
+        }
+    }
+    Err(OthelloError::GameOver)
+}
+
+/// Placements of the side to move, empty if they have to pass or the game is over.
+fn placement_indices(game: &IGame) -> Vec<usize> {
+    game.get_legal_moves()
+        .iter()
+        .filter(|&&legal_move| legal_move != Move::Pass)
+        .map(Move::to_index)
+        .collect()
+}
+
+// None if every move at some point would have ended the game
+fn playout<R, P>(
+    target: usize,
+    rng: &mut R,
+    policy: &mut P
+) -> Result<Option<IGame>, OthelloError>
+    where R: Rng, P: FnMut(&IGame, &[usize], &mut R) -> usize
+{
+    let mut game = IGame::new();
+    let mut placed = 0;
+    loop {
+        if game.get_legal_moves() == [Move::Pass] {
+            game.make_move(Move::Pass);
+        }
+        if placed == target {
+            return Ok(Some(game));
+        }
+        let mut candidates = placement_indices(&game);
+        loop {
+            if candidates.is_empty() {
+                return Ok(None);
+            }
+            let index = policy(&game, &candidates, rng);
+            if !candidates.contains(&index) {
+                return Err(OthelloError::IllegalMove { move_index: index, player: game.turn });
+            }
+            game.try_make_move_at_index(index)?;
+            if !game.is_over() {
+                break;
+            }
+            game.undo();
+            candidates.retain(|&candidate| candidate != index);
+        }
+        placed += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{ rngs::StdRng, SeedableRng };
+
+    use crate::{ error::OthelloError, gameplay::game::{ IGame, Move } };
+
+    use super::{ random_position, random_position_with_policy, MAX_RANDOM_PLIES };
+
+    #[test]
+    fn positions_are_reachable_and_not_over() {
+        let mut rng = StdRng::seed_from_u64(24);
+        for _ in 0..200 {
+            let game = random_position(0..=MAX_RANDOM_PLIES, &mut rng).unwrap();
+            assert!(!game.is_over());
+            assert!(game.get_legal_moves().iter().any(|&legal_move| legal_move != Move::Pass));
+            let moves = game.moves();
+            let placements = moves.iter().filter(|&&played| played != Move::Pass).count();
+            assert!(placements <= MAX_RANDOM_PLIES);
+            let replayed = IGame::from_moves(&moves).unwrap();
+            assert_eq!((replayed.board, replayed.turn), (game.board, game.turn));
+        }
+    }
+
+    #[test]
+    fn plays_the_given_number_of_placements() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let game = random_position(0..=0, &mut rng).unwrap();
+        assert_eq!(game.board, IGame::new().board);
+        for _ in 0..20 {
+            let game = random_position(12..=14, &mut rng).unwrap();
+            let placements = game.plies().iter().filter(|ply| ply.played != Move::Pass);
+            assert!((12..=14).contains(&placements.count()));
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_position() {
+        let first = random_position(20..=40, &mut StdRng::seed_from_u64(7)).unwrap();
+        let second = random_position(20..=40, &mut StdRng::seed_from_u64(7)).unwrap();
+        assert_eq!(first.moves(), second.moves());
+    }
+
+    #[test]
+    fn follows_the_policy() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let game = random_position_with_policy(8..=8, &mut rng, |_, placements, _| {
+            placements[0]
+        }).unwrap();
+        let mut expected = IGame::new();
+        for _ in 0..8 {
+            let first = expected.get_legal_moves()[0];
+            expected.make_move(first);
+        }
+        assert_eq!(game.moves(), expected.moves());
+
+        let error = random_position_with_policy(8..=8, &mut rng, |_, _, _| 0);
+        assert_eq!(error.err(), Some(OthelloError::IllegalMove { move_index: 0, player: 0 }));
+    }
+
+    #[test]
+    fn rejects_invalid_ranges() {
+        let mut rng = StdRng::seed_from_u64(0);
+        #[allow(clippy::reversed_empty_ranges)]
+        let error = random_position(10..=5, &mut rng);
+        assert_eq!(error.err(), Some(OthelloError::InvalidPlyRange { min: 10, max: 5 }));
+        let error = random_position(0..=60, &mut rng);
+        assert_eq!(error.err(), Some(OthelloError::InvalidPlyRange { min: 0, max: 60 }));
+    }
+}
@@ -1,59 +1,71 @@
 use crate::gameplay::constants::{ DEFAULT_CORNER_SCORE, DEFAULT_EDGE_SCORE, DEFAULT_OTHER_SCORE };
-use crate::gameplay::utils::{ is_piece_placeholder, worst_score_by_playing_piece_at_index };
 
+use super::bitboard::{ indices_from_mask, Bitboard };
 use super::constants::DEFAULT_BEST_WORST_CASE_SCORE;
 use super::game::{ IBoard, IPlayer };
 use super::position::IPosition;
 
 pub fn suggest_moves_rules_based(board: IBoard, player: IPlayer) -> Vec<IPosition> {
+    let bitboard = Bitboard::from_board(board);
     let mut best_moves: Vec<IPosition> = Vec::new();
-    let mut row_index: i8 = 0;
-    let mut col_index: i8 = 0;
     let mut best_worst_case_score: i16 = DEFAULT_BEST_WORST_CASE_SCORE;
-    for row in board.iter() {
-        for piece in row.iter() {
-            if !is_piece_placeholder(*piece) {
-                col_index += 1;
-                continue;
-            }
-            // make move and get score
-            let worst_case_score = worst_score_by_playing_piece_at_index(
-                board,
-                IPosition {
-                    downwards: row_index,
-                    rightwards: col_index,
-                },
-                player,
-                DEFAULT_CORNER_SCORE,
-                DEFAULT_EDGE_SCORE,
-                DEFAULT_OTHER_SCORE
-            );
-            // if move is invalid, skip it
-            if worst_case_score.is_none() {
-                col_index += 1;
-                continue;
-            }
-            // if move is better than best performance so far, replace it
-            if worst_case_score.unwrap() < best_worst_case_score {
-                best_worst_case_score = worst_case_score.unwrap();
-                best_moves = Vec::new();
-                best_moves.push(IPosition {
-                    downwards: row_index,
-                    rightwards: col_index,
-                });
-            } else if
-                // if move matches best performance so far, add it to the list
-                worst_case_score.unwrap() == best_worst_case_score
-            {
-                best_moves.push(IPosition {
-                    downwards: row_index,
-                    rightwards: col_index,
-                });
-            }
-            col_index += 1;
+    for index in indices_from_mask(bitboard.legal_moves(player)) {
+        // make move and get score
+        let worst_case_score = worst_score_for_move(
+            &bitboard,
+            index,
+            player,
+            DEFAULT_CORNER_SCORE,
+            DEFAULT_EDGE_SCORE,
+            DEFAULT_OTHER_SCORE
+        );
+        let position = IPosition::position_from_piece_index(index as i8).unwrap();
+        // if move is better than best performance so far, replace it
+        if worst_case_score < best_worst_case_score {
+            best_worst_case_score = worst_case_score;
+            best_moves = vec![position];
+        } else if
+            // if move matches best performance so far, add it to the list
+            worst_case_score == best_worst_case_score
+        {
+            best_moves.push(position);
         }
-        row_index += 1;
-        col_index = 0;
     }
     best_moves
 }
+
+///
+/// Bitboard version of `utils::worst_score_by_playing_piece_at_index`. Expects `index` to be a legal move for `player`.
+fn worst_score_for_move(
+    bitboard: &Bitboard,
+    index: usize,
+    player: IPlayer,
+    corner_score: i16,
+    edge_score: i16,
+    other_score: i16
+) -> i16 {
+    let board_new = bitboard.play(index, player).expect("Expected a legal move");
+    let new_score = board_new.augmented_score(player, corner_score, edge_score, other_score);
+    let opponent = 1 - player;
+    let new_score_opponent = board_new.augmented_score(
+        opponent,
+        corner_score,
+        edge_score,
+        other_score
+    );
+    // how good is this move immediately?
+    let tie_break_score: i16 = new_score - new_score_opponent;
+    let mut worst_case_score: i16 = DEFAULT_BEST_WORST_CASE_SCORE;
+    for opponent_index in indices_from_mask(bitboard.legal_moves(opponent)) {
+        // opponent score after we have already played one position
+        let opponent_score = bitboard
+            .play(opponent_index, opponent)
+            .expect("Expected a legal move")
+            .augmented_score(opponent, corner_score, edge_score, other_score);
+        let total_score = new_score - opponent_score + tie_break_score;
+        if total_score < worst_case_score {
+            worst_case_score = total_score;
+        }
+    }
+    worst_case_score
+}
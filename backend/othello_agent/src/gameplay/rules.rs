@@ -0,0 +1,126 @@
+use crate::error::OthelloError;
+
+use super::{ game::{ GameResult, IPlayer, Move }, position::IPosition };
+
+///
+/// The rules of Othello, written once for every board representation. `IGame` plays the 8x8
+/// board with bitboards and `SizedGame` walks boards of any `BoardSize` square by square. Both
+/// only provide the board itself: which placements are legal, how a placement flips pieces and
+/// the moves played so far. Passes, the end of the game and its result are decided here.
+pub trait Rules {
+    /// Player to move.
+    fn turn(&self) -> IPlayer;
+
+    /// Number of squares on the board.
+    fn squares(&self) -> usize;
+
+    /// The move played `back` moves before the last one, so 0 is the last move. None if fewer
+    /// moves were played.
+    fn previous_move(&self, back: usize) -> Option<Move>;
+
+    /// Squares the player can place a piece on, in piece index order.
+    fn placements(&self, player: IPlayer) -> Vec<IPosition>;
+
+    fn player_has_move(&self, player: IPlayer) -> bool;
+
+    /// Discs a player has on the board.
+    fn disc_count(&self, player: IPlayer) -> u8;
+
+    ///
+    /// Places a piece for the player to move, records the move and hands the turn over.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), OthelloError>` - `InvalidPosition` if the position is off the board,
+    ///   `IllegalMove` if the move is not legal. The game is unchanged on error.
+    fn place(&mut self, position: &IPosition) -> Result<(), OthelloError>;
+
+    /// Records a pass by the player to move and hands the turn over.
+    fn record_pass(&mut self);
+
+    ///
+    /// Legal moves of the player to move: their placements, a single pass if they have none,
+    /// or nothing once the game is over.
+    fn get_legal_moves(&self) -> Vec<Move> {
+        if self.is_over() {
+            return Vec::new();
+        }
+        let placements: Vec<Move> = self
+            .placements(self.turn())
+            .into_iter()
+            .map(Move::Place)
+            .collect();
+        if placements.is_empty() {
+            return vec![Move::Pass];
+        }
+        placements
+    }
+
+    /// The game is over once neither player can move, or after two passes in a row.
+    fn is_over(&self) -> bool {
+        let passed_twice =
+            self.previous_move(0) == Some(Move::Pass) && self.previous_move(1) == Some(Move::Pass);
+        passed_twice || (!self.player_has_move(0) && !self.player_has_move(1))
+    }
+
+    ///
+    /// Plays a move for the player to move.
+    ///
+    /// # Arguments
+    ///
+    /// * `next_move` - The placement or pass.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), OthelloError>` - `GameOver` once the game is over, `IllegalPass` for a pass
+    ///   while a placement is available, or the errors of `place`.
+    fn try_make_move(&mut self, next_move: Move) -> Result<(), OthelloError> {
+        if self.is_over() {
+            return Err(OthelloError::GameOver);
+        }
+        match next_move {
+            Move::Place(position) => self.place(&position),
+            Move::Pass => {
+                if self.player_has_move(self.turn()) {
+                    return Err(OthelloError::IllegalPass { player: self.turn() });
+                }
+                self.record_pass();
+                Ok(())
+            }
+        }
+    }
+
+    ///
+    /// Plays a move for the player to move.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the move is not legal, see `try_make_move`.
+    fn make_move(&mut self, next_move: Move) {
+        if let Err(error) = self.try_make_move(next_move) {
+            panic!("Invalid move: {}", error);
+        }
+    }
+
+    ///
+    /// Outcome of the game, the one place the winner is decided.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<GameResult>` - The winner and final disc counts, None while the game goes on.
+    fn result(&self) -> Option<GameResult> {
+        if !self.is_over() {
+            return None;
+        }
+        let squares = self.squares() as u8;
+        Some(GameResult::from_disc_counts_on(squares, self.disc_count(0), self.disc_count(1)))
+    }
+
+    /// Scores of black and white: the disc counts, or the final scores once the game is over.
+    fn scores(&self) -> [i16; 2] {
+        match self.result() {
+            Some(result) => [result.black_discs as i16, result.white_discs as i16],
+            None => [self.disc_count(0) as i16, self.disc_count(1) as i16],
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+
+use crate::error::OthelloError;
+
+use super::{
+    board_size::BoardSize,
+    constants::{ CODE_CHARS, DIRECTIONS, PLACEHOLDER },
+    encoding::{ string_from_pieces, try_pieces_from_string },
+    game::{ IPiece, IPlayer, Move },
+    position::IPosition,
+    rules::Rules,
+};
+
+///
+/// A game on a board of any `BoardSize`, such as 6x6 or 10x10. It shares `Rules` with `IGame`
+/// and only walks the board square by square where `IGame` uses bitboards, so `IGame` stays the
+/// faster choice for 8x8 games. Piece indices count row by row, see `BoardSize::index_of`.
+pub struct SizedGame {
+    pub size: BoardSize,
+    /// Pieces row by row, 0 for black, 1 for white and 2 for empty.
+    pub pieces: Vec<IPiece>,
+    pub turn: IPlayer,
+    // moves played since the game was created, passes included
+    moves: Vec<Move>,
+}
+
+impl SizedGame {
+    /// Starts a game from the standard starting position of the given size, black to move.
+    pub fn new(size: BoardSize) -> SizedGame {
+        SizedGame { size, pieces: size.initial_pieces(), turn: 0, moves: Vec::new() }
+    }
+
+    ///
+    /// Creates a game from a position.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - Size of the board.
+    /// * `pieces` - Pieces row by row.
+    /// * `turn` - Player to move.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<SizedGame, OthelloError>` - `InvalidBoardLength` if there is not one piece per
+    ///   square.
+    pub fn from_pieces(
+        size: BoardSize,
+        pieces: Vec<IPiece>,
+        turn: IPlayer
+    ) -> Result<SizedGame, OthelloError> {
+        if pieces.len() != size.squares() {
+            return Err(OthelloError::InvalidBoardLength(pieces.len()));
+        }
+        Ok(SizedGame { size, pieces, turn, moves: Vec::new() })
+    }
+
+    /// Decodes a board encoded with `get_board_string`, see `from_pieces`.
+    pub fn from_board_string(
+        size: BoardSize,
+        board_string: &str,
+        code_char_hash: &HashMap<char, u8>,
+        turn: IPlayer
+    ) -> Result<SizedGame, OthelloError> {
+        let pieces = try_pieces_from_string(board_string, size.squares(), code_char_hash)?;
+        SizedGame::from_pieces(size, pieces, turn)
+    }
+
+    pub fn get_board_string(&self) -> String {
+        string_from_pieces(&self.pieces, CODE_CHARS)
+    }
+
+    pub fn piece_at(&self, position: &IPosition) -> IPiece {
+        self.pieces[self.size.index_of(position)]
+    }
+
+    ///
+    /// Squares the player would flip by playing at `position`, empty if the move is not legal.
+    pub fn flips(&self, position: &IPosition, player: IPlayer) -> Vec<IPosition> {
+        if !self.size.contains(position) || self.piece_at(position) != PLACEHOLDER {
+            return Vec::new();
+        }
+        let mut flips = Vec::new();
+        for direction in DIRECTIONS.iter() {
+            let mut line = Vec::new();
+            let mut current = *position;
+            current.add(*direction);
+            while self.size.contains(&current) && self.piece_at(&current) == 1 - player {
+                line.push(current);
+                current.add(*direction);
+            }
+            if self.size.contains(&current) && self.piece_at(&current) == player {
+                flips.extend(line);
+            }
+        }
+        flips
+    }
+
+    pub fn get_valid_moves(&self, player: IPlayer) -> Vec<IPosition> {
+        (0..self.size.squares())
+            .filter_map(|index| self.size.position_of(index))
+            .filter(|position| !self.flips(position, player).is_empty())
+            .collect()
+    }
+
+    /// Moves played since the game was created, passes included.
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    ///
+    /// Plays a move at a piece index of this board, see `Rules::try_make_move`.
+    pub fn try_make_move_at_index(&mut self, move_index: usize) -> Result<(), OthelloError> {
+        let position = self.size.position_of(move_index).ok_or(OthelloError::InvalidPosition {
+            downwards: (move_index / self.size.width()) as i64,
+            rightwards: (move_index % self.size.width()) as i64,
+        })?;
+        self.try_make_move(Move::Place(position))
+    }
+}
+
+impl Rules for SizedGame {
+    fn turn(&self) -> IPlayer {
+        self.turn
+    }
+
+    fn squares(&self) -> usize {
+        self.size.squares()
+    }
+
+    fn previous_move(&self, back: usize) -> Option<Move> {
+        self.moves.iter().rev().nth(back).copied()
+    }
+
+    fn placements(&self, player: IPlayer) -> Vec<IPosition> {
+        self.get_valid_moves(player)
+    }
+
+    fn player_has_move(&self, player: IPlayer) -> bool {
+        (0..self.size.squares())
+            .filter_map(|index| self.size.position_of(index))
+            .any(|position| !self.flips(&position, player).is_empty())
+    }
+
+    fn disc_count(&self, player: IPlayer) -> u8 {
+        self.pieces
+            .iter()
+            .filter(|&&piece| piece == player)
+            .count() as u8
+    }
+
+    fn place(&mut self, position: &IPosition) -> Result<(), OthelloError> {
+        if !self.size.contains(position) {
+            return Err(OthelloError::InvalidPosition {
+                downwards: position.downwards as i64,
+                rightwards: position.rightwards as i64,
+            });
+        }
+        let flips = self.flips(position, self.turn);
+        if flips.is_empty() {
+            let move_index = self.size.index_of(position);
+            return Err(OthelloError::IllegalMove { move_index, player: self.turn });
+        }
+        for square in flips.iter().chain(std::iter::once(position)) {
+            let index = self.size.index_of(square);
+            self.pieces[index] = self.turn;
+        }
+        self.moves.push(Move::Place(*position));
+        self.turn = 1 - self.turn;
+        Ok(())
+    }
+
+    fn record_pass(&mut self) {
+        self.moves.push(Move::Pass);
+        self.turn = 1 - self.turn;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{ rngs::StdRng, seq::SliceRandom, SeedableRng };
+
+    use crate::{
+        error::OthelloError,
+        gameplay::{
+            board_size::BoardSize,
+            constants::CODE_CHARS,
+            encoding::create_code_char_hash,
+            game::{ IGame, Move },
+            position::IPosition,
+            rules::Rules,
+        },
+    };
+
+    use super::SizedGame;
+
+    #[test]
+    fn plays_like_the_standard_game() {
+        let mut rng = StdRng::seed_from_u64(20);
+        for _ in 0..5 {
+            let mut sized = SizedGame::new(BoardSize::STANDARD);
+            let mut game = IGame::new();
+            while !game.is_over() {
+                let legal_moves = game.get_legal_moves();
+                assert_eq!(sized.get_legal_moves(), legal_moves);
+                let next_move = *legal_moves.choose(&mut rng).unwrap();
+                game.make_move(next_move);
+                sized.make_move(next_move);
+            }
+            let board: Vec<u8> = game.board.iter().flatten().copied().collect();
+            assert_eq!(sized.pieces, board);
+            assert_eq!(sized.get_board_string(), game.get_board_string());
+            assert_eq!(sized.result(), game.result());
+        }
+    }
+
+    #[test]
+    fn plays_small_and_large_boards() {
+        let mut rng = StdRng::seed_from_u64(6);
+        for size in [BoardSize::SMALL, BoardSize::LARGE] {
+            let mut game = SizedGame::new(size);
+            assert_eq!(game.get_valid_moves(0).len(), 4);
+            while !game.is_over() {
+                let next_move = *game.get_legal_moves().choose(&mut rng).unwrap();
+                game.make_move(next_move);
+            }
+            let result = game.result().unwrap();
+            assert_eq!((result.black_discs + result.white_discs) as usize, size.squares());
+            assert_eq!(game.try_make_move(Move::Pass), Err(OthelloError::GameOver));
+        }
+    }
+
+    #[test]
+    fn rejects_illegal_moves() {
+        let mut game = SizedGame::new(BoardSize::SMALL);
+        let illegal = OthelloError::IllegalMove { move_index: 0, player: 0 };
+        assert_eq!(game.try_make_move_at_index(0), Err(illegal));
+        let off_board = OthelloError::InvalidPosition { downwards: 6, rightwards: 0 };
+        assert_eq!(game.try_make_move_at_index(36), Err(off_board));
+        let pass = game.try_make_move(Move::Pass);
+        assert_eq!(pass, Err(OthelloError::IllegalPass { player: 0 }));
+        assert_eq!(game.pieces, BoardSize::SMALL.initial_pieces());
+
+        // c2 flips c3 on a 6x6 board
+        assert_eq!(game.try_make_move_at_index(8), Ok(()));
+        assert_eq!(game.piece_at(&IPosition { downwards: 2, rightwards: 2 }), 0);
+        assert_eq!(game.disc_count(0), 4);
+        assert_eq!(game.moves().len(), 1);
+    }
+
+    #[test]
+    fn encodes_and_decodes_boards() {
+        let mut game = SizedGame::new(BoardSize::LARGE);
+        game.make_move(game.get_legal_moves()[0]);
+        let encoded = game.get_board_string();
+        assert_eq!(encoded.len(), 34);
+        let hash = create_code_char_hash(CODE_CHARS);
+        let decoded = SizedGame::from_board_string(BoardSize::LARGE, &encoded, &hash, 1).unwrap();
+        assert_eq!(decoded.pieces, game.pieces);
+        let error = SizedGame::from_board_string(BoardSize::SMALL, &encoded, &hash, 1).err();
+        assert_eq!(error, Some(OthelloError::InvalidBoardLength(34)));
+    }
+}
@@ -0,0 +1,135 @@
+use super::constants::NULL_MOVE_INDEX;
+
+///
+/// The eight rotations and reflections of the square board. Othello's rules do not change
+/// under any of them, so a transformed position has the same value as the original and the
+/// transformed best move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Symmetry {
+    Identity,
+    /// Clockwise quarter turn
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    /// Mirror left to right
+    FlipHorizontal,
+    /// Mirror top to bottom
+    FlipVertical,
+    /// Mirror along the a1-h8 diagonal
+    FlipDiagonal,
+    /// Mirror along the h1-a8 diagonal
+    FlipAntiDiagonal,
+}
+
+impl Symmetry {
+    pub const ALL: [Symmetry; 8] = [
+        Symmetry::Identity,
+        Symmetry::Rotate90,
+        Symmetry::Rotate180,
+        Symmetry::Rotate270,
+        Symmetry::FlipHorizontal,
+        Symmetry::FlipVertical,
+        Symmetry::FlipDiagonal,
+        Symmetry::FlipAntiDiagonal,
+    ];
+
+    /// The symmetry that undoes this one.
+    pub fn inverse(&self) -> Symmetry {
+        match self {
+            Symmetry::Rotate90 => Symmetry::Rotate270,
+            Symmetry::Rotate270 => Symmetry::Rotate90,
+            other => *other,
+        }
+    }
+
+    ///
+    /// Maps a square to its image.
+    ///
+    /// # Arguments
+    ///
+    /// * `downwards` - Row of the square, 0 to 7.
+    /// * `rightwards` - Column of the square, 0 to 7.
+    ///
+    /// # Returns
+    ///
+    /// * `(usize, usize)` - Row and column of the transformed square.
+    pub fn transform_position(&self, downwards: usize, rightwards: usize) -> (usize, usize) {
+        let (row, col) = (downwards, rightwards);
+        match self {
+            Symmetry::Identity => (row, col),
+            Symmetry::Rotate90 => (col, 7 - row),
+            Symmetry::Rotate180 => (7 - row, 7 - col),
+            Symmetry::Rotate270 => (7 - col, row),
+            Symmetry::FlipHorizontal => (row, 7 - col),
+            Symmetry::FlipVertical => (7 - row, col),
+            Symmetry::FlipDiagonal => (col, row),
+            Symmetry::FlipAntiDiagonal => (7 - col, 7 - row),
+        }
+    }
+
+    /// Maps a piece index to its image. `NULL_MOVE_INDEX` (a pass) is left unchanged.
+    pub fn transform_index(&self, index: usize) -> usize {
+        if index == NULL_MOVE_INDEX {
+            return index;
+        }
+        let (row, col) = self.transform_position(index / 8, index % 8);
+        row * 8 + col
+    }
+
+    /// Transforms any 8x8 board, so both `IBoard` and `IBoardForML` can be augmented.
+    pub fn transform_board<T: Copy>(&self, board: [[T; 8]; 8]) -> [[T; 8]; 8] {
+        let mut transformed = board;
+        for (row_index, row) in board.iter().enumerate() {
+            for (col_index, &piece) in row.iter().enumerate() {
+                let (row, col) = self.transform_position(row_index, col_index);
+                transformed[row][col] = piece;
+            }
+        }
+        transformed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::gameplay::{
+        bitboard::{ indices_from_mask, Bitboard },
+        constants::{ INITIAL_BOARD, NULL_MOVE_INDEX },
+    };
+
+    use super::Symmetry;
+
+    #[test]
+    fn transforms_are_distinct_and_invertible() {
+        let images: HashSet<Vec<usize>> = Symmetry::ALL.iter()
+            .map(|symmetry| (0..64).map(|index| symmetry.transform_index(index)).collect())
+            .collect();
+        assert_eq!(images.len(), 8);
+        for symmetry in Symmetry::ALL {
+            for index in 0..64 {
+                let image = symmetry.transform_index(index);
+                assert_eq!(symmetry.inverse().transform_index(image), index);
+            }
+            assert_eq!(symmetry.transform_index(NULL_MOVE_INDEX), NULL_MOVE_INDEX);
+        }
+        // a1 goes to the other corners
+        assert_eq!(Symmetry::Rotate90.transform_index(0), 7);
+        assert_eq!(Symmetry::Rotate180.transform_index(0), 63);
+        assert_eq!(Symmetry::FlipVertical.transform_index(0), 56);
+    }
+
+    #[test]
+    fn legal_moves_follow_the_board() {
+        let board = Bitboard::from_board(INITIAL_BOARD).play(19, 0).unwrap();
+        for symmetry in Symmetry::ALL {
+            let transformed = Bitboard::from_board(symmetry.transform_board(board.to_board()));
+            let mut expected: Vec<usize> = indices_from_mask(board.legal_moves(1))
+                .into_iter()
+                .map(|index| symmetry.transform_index(index))
+                .collect();
+            expected.sort();
+            assert_eq!(indices_from_mask(transformed.legal_moves(1)), expected);
+        }
+    }
+}
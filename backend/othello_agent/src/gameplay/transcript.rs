@@ -0,0 +1,287 @@
+use std::fmt;
+
+use crate::{ error::OthelloError, simulate::history::GameHistory };
+
+use super::{ constants::NULL_MOVE_INDEX, game::{ IGame, Move }, position::IPosition };
+
+/// Token written for a pass.
+pub const PASS_TOKEN: &str = "pa";
+
+/// Spellings accepted for a pass, compared without case. Longer ones come first so `pass` is
+/// not read as `pa` followed by garbage.
+const PASS_TOKENS: [&str; 4] = ["pass", "pa", "ps", "--"];
+
+///
+/// Why a transcript could not be read. Plies count from 1 and include passes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TranscriptError {
+    /// Text at byte `offset` is neither a square nor a pass.
+    InvalidToken {
+        offset: usize,
+        token: String,
+    },
+    IllegalMove {
+        ply: usize,
+        move_index: usize,
+    },
+    /// A pass by a player who has a legal move.
+    IllegalPass {
+        ply: usize,
+    },
+    /// A move after neither player can move.
+    GameOver {
+        ply: usize,
+    },
+    /// A recorded move index that is neither a square nor `NULL_MOVE_INDEX`.
+    InvalidMoveIndex {
+        ply: usize,
+        move_index: usize,
+    },
+}
+
+impl fmt::Display for TranscriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TranscriptError::InvalidToken { offset, token } => {
+                write!(f, "invalid token {:?} at offset {}", token, offset)
+            }
+            TranscriptError::IllegalMove { ply, move_index } => {
+                let name = square_name(*move_index).unwrap_or_else(|| move_index.to_string());
+                write!(f, "illegal move {} at ply {}", name, ply)
+            }
+            TranscriptError::IllegalPass { ply } => {
+                write!(f, "pass at ply {} while a move is available", ply)
+            }
+            TranscriptError::GameOver { ply } => {
+                write!(f, "move at ply {} after the game is over", ply)
+            }
+            TranscriptError::InvalidMoveIndex { ply, move_index } => {
+                write!(f, "move index {} at ply {} is not a square or a pass", move_index, ply)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TranscriptError {}
+
+/// Name of a move, e.g. `f5`, or `PASS_TOKEN` for `NULL_MOVE_INDEX`. None for any other index
+/// off the board.
+pub fn square_name(move_index: usize) -> Option<String> {
+    if move_index == NULL_MOVE_INDEX {
+        return Some(PASS_TOKEN.to_string());
+    }
+    if move_index >= 64 {
+        return None;
+    }
+    IPosition::position_from_piece_index(move_index as i8).map(|position| {
+        position.to_string_position()
+    })
+}
+
+///
+/// Splits a transcript into moves without checking that they can be played. Squares are
+/// written `a1` to `h8` in either case, passes as `pa`, `ps`, `pass` or `--`. Moves can be
+/// written back to back or separated by spaces, commas or full stops.
+///
+/// # Arguments
+///
+/// * `transcript` - The moves, e.g. `f5d6 c3 pa d3`.
+///
+/// # Returns
+///
+/// * `Result<Vec<usize>, TranscriptError>` - Piece indices, `NULL_MOVE_INDEX` for a pass.
+pub fn parse_transcript(transcript: &str) -> Result<Vec<usize>, TranscriptError> {
+    let lowercase = transcript.to_ascii_lowercase();
+    let bytes = lowercase.as_bytes();
+    let mut moves = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let rest = &lowercase[offset..];
+        if bytes[offset].is_ascii_whitespace() || bytes[offset] == b',' || bytes[offset] == b'.' {
+            offset += 1;
+            continue;
+        }
+        if let Some(token) = PASS_TOKENS.iter().find(|token| rest.starts_with(*token)) {
+            moves.push(NULL_MOVE_INDEX);
+            offset += token.len();
+            continue;
+        }
+        let square = rest.get(..2).and_then(IPosition::position_from_string_position);
+        match square {
+            Some(position) => {
+                moves.push(position.to_piece_index());
+                offset += 2;
+            }
+            None => {
+                let token = transcript[offset..].chars().take(2).collect();
+                return Err(TranscriptError::InvalidToken { offset, token });
+            }
+        }
+    }
+    Ok(moves)
+}
+
+///
+/// Writes moves back to back with explicit passes, e.g. `f5d6c3pad3`.
+///
+/// # Returns
+///
+/// * `Result<String, TranscriptError>` - The transcript, or `InvalidMoveIndex` for the first
+///   index that is neither a square nor a pass.
+pub fn format_transcript(moves: &[usize]) -> Result<String, TranscriptError> {
+    moves
+        .iter()
+        .enumerate()
+        .map(|(ply, &move_index)| {
+            square_name(move_index).ok_or(TranscriptError::InvalidMoveIndex {
+                ply: ply + 1,
+                move_index,
+            })
+        })
+        .collect()
+}
+
+///
+/// Plays moves from the starting position and records them. A player without a legal move
+/// passes even if the transcript leaves the pass out, the recorded history always contains it.
+///
+/// # Arguments
+///
+/// * `moves` - Piece indices, `NULL_MOVE_INDEX` for a pass.
+///
+/// # Returns
+///
+/// * `Result<GameHistory, TranscriptError>` - The history, with the board and disc counts after
+///   every move, or the first move that could not be played. A finished game has the final
+///   scores of `IGame::result`.
+pub fn replay_moves(moves: &[usize]) -> Result<GameHistory, TranscriptError> {
+    let mut history = GameHistory::new();
+    let mut game = IGame::new();
+    let mut ply = 0;
+    for &move_index in moves {
+        ply += 1;
+        if move_index != NULL_MOVE_INDEX && game.get_legal_moves() == [Move::Pass] {
+            // the pass was left out
+            game.make_move(Move::Pass);
+            record_move(&mut history, &game, Move::Pass);
+            ply += 1;
+        }
+        let next_move = Move::from_index(move_index).ok_or(TranscriptError::IllegalMove {
+            ply,
+            move_index,
+        })?;
+        game.try_make_move(next_move).map_err(|error| {
+            match error {
+                OthelloError::GameOver => TranscriptError::GameOver { ply },
+                OthelloError::IllegalPass { .. } => TranscriptError::IllegalPass { ply },
+                _ => TranscriptError::IllegalMove { ply, move_index },
+            }
+        })?;
+        record_move(&mut history, &game, next_move);
+    }
+    Ok(history)
+}
+
+/// Adds the board after a move, with the disc counts or the final scores once the game is over.
+fn record_move(history: &mut GameHistory, game: &IGame, played: Move) {
+    history.add_move(game.board, played);
+    match game.result() {
+        Some(result) => history.set_result(&result),
+        None => {
+            let [black_score, white_score] = game.scores();
+            history.set_scores(black_score, white_score);
+        }
+    }
+}
+
+/// Parses and replays a transcript, see `parse_transcript` and `replay_moves`.
+pub fn history_from_transcript(transcript: &str) -> Result<GameHistory, TranscriptError> {
+    replay_moves(&parse_transcript(transcript)?)
+}
+
+/// Writes the moves of a history, the inverse of `history_from_transcript`. See
+/// `format_transcript` for the errors.
+pub fn transcript_from_history(history: &GameHistory) -> Result<String, TranscriptError> {
+    format_transcript(&history.move_history)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gameplay::constants::NULL_MOVE_INDEX;
+
+    use super::{
+        format_transcript,
+        history_from_transcript,
+        parse_transcript,
+        replay_moves,
+        square_name,
+        transcript_from_history,
+        TranscriptError,
+    };
+
+    // black wipes out white in nine moves
+    const WIPEOUT: &str = "d3c3b3d2e1d6d7e3f4";
+    // black has no move after c1 and has to pass
+    const BLACK_PASSES: &str = "d3c3e6d2d1e1b2c1";
+
+    #[test]
+    fn parses_squares_and_passes() {
+        assert_eq!(parse_transcript("F5 d6,c3").unwrap(), vec![37, 43, 18]);
+        let moves = parse_transcript("f5pa d6 PASS c3 -- e6 ps").unwrap();
+        let pass = NULL_MOVE_INDEX;
+        assert_eq!(moves, vec![37, pass, 43, pass, 18, pass, 44, pass]);
+        assert_eq!(format_transcript(&moves).unwrap(), "f5pad6pac3pae6pa");
+        assert_eq!(parse_transcript("").unwrap(), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn reports_invalid_tokens() {
+        let error = parse_transcript("f5 d6 z9").unwrap_err();
+        assert_eq!(error, TranscriptError::InvalidToken { offset: 6, token: "z9".to_string() });
+        let error = parse_transcript("f5d").unwrap_err();
+        assert_eq!(error, TranscriptError::InvalidToken { offset: 2, token: "d".to_string() });
+        assert_eq!(error.to_string(), "invalid token \"d\" at offset 2");
+    }
+
+    #[test]
+    fn refuses_to_write_corrupt_moves() {
+        assert_eq!(square_name(NULL_MOVE_INDEX), Some("pa".to_string()));
+        assert_eq!(square_name(75), None);
+        let error = format_transcript(&[19, 75]).unwrap_err();
+        assert_eq!(error, TranscriptError::InvalidMoveIndex { ply: 2, move_index: 75 });
+    }
+
+    #[test]
+    fn reports_the_ply_of_illegal_moves() {
+        let error = history_from_transcript("f5 d6 a1").unwrap_err();
+        assert_eq!(error, TranscriptError::IllegalMove { ply: 3, move_index: 0 });
+        assert_eq!(error.to_string(), "illegal move a1 at ply 3");
+        let error = history_from_transcript("f5 pa").unwrap_err();
+        assert_eq!(error, TranscriptError::IllegalPass { ply: 2 });
+        let error = history_from_transcript(&format!("{}c4", WIPEOUT)).unwrap_err();
+        assert_eq!(error, TranscriptError::GameOver { ply: 10 });
+    }
+
+    #[test]
+    fn round_trips_with_game_history() {
+        let history = history_from_transcript(WIPEOUT).unwrap();
+        assert_eq!(history.move_history, vec![19, 18, 17, 11, 4, 43, 51, 20, 29]);
+        assert_eq!(history.total_moves, 9);
+        assert_eq!((history.agent0_score, history.agent1_score), (64, 0));
+        assert_eq!(transcript_from_history(&history).unwrap(), WIPEOUT);
+        let replayed = replay_moves(&history.move_history).unwrap();
+        assert_eq!(replayed.board_history, history.board_history);
+    }
+
+    #[test]
+    fn records_passes_left_out_of_the_transcript() {
+        let explicit = history_from_transcript(&format!("{}pa a3", BLACK_PASSES)).unwrap();
+        let implicit = history_from_transcript(&format!("{}a3", BLACK_PASSES)).unwrap();
+        assert_eq!(implicit.move_history, explicit.move_history);
+        assert_eq!(implicit.move_history[8..], [NULL_MOVE_INDEX, 16]);
+        assert_eq!(transcript_from_history(&implicit).unwrap(), format!("{}paa3", BLACK_PASSES));
+        // the pass counts as a ply
+        let error = history_from_transcript(&format!("{}a3h8", BLACK_PASSES)).unwrap_err();
+        assert_eq!(error, TranscriptError::IllegalMove { ply: 11, move_index: 63 });
+    }
+}
@@ -0,0 +1,82 @@
+//!
+//! Zobrist hashing of positions. Every piece on a square and the side to move have a random
+//! key, a position hashes to the xor of its keys, so a move only has to xor in what changed.
+
+use super::{ bitboard::{ indices_from_mask, Bitboard }, game::IPlayer };
+
+/// SplitMix64, used to fill the key tables at compile time.
+const fn split_mix(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+const fn piece_keys() -> [[u64; 64]; 2] {
+    let mut keys = [[0; 64]; 2];
+    let mut index = 0;
+    while index < 128 {
+        keys[index / 64][index % 64] = split_mix(index as u64);
+        index += 1;
+    }
+    keys
+}
+
+/// Key of a piece of each player on each square, indexed by player then piece index.
+pub const PIECE_KEYS: [[u64; 64]; 2] = piece_keys();
+
+/// Key xored in while white is to move.
+pub const WHITE_TO_MOVE_KEY: u64 = split_mix(128);
+
+///
+/// Hashes a position from scratch. `IGame::hash` gives the same value, updated move by move.
+///
+/// # Arguments
+///
+/// * `board` - The pieces of both players.
+/// * `turn` - The player to move.
+///
+/// # Returns
+///
+/// * `u64` - The Zobrist hash.
+pub fn zobrist_hash(board: &Bitboard, turn: IPlayer) -> u64 {
+    let mut hash = if turn == 1 { WHITE_TO_MOVE_KEY } else { 0 };
+    for (keys, &pieces) in PIECE_KEYS.iter().zip(board.pieces.iter()) {
+        for index in indices_from_mask(pieces) {
+            hash ^= keys[index];
+        }
+    }
+    hash
+}
+
+/// Change of the hash when `player` places a piece at `index` and flips the pieces in `flips`.
+pub fn placement_delta(index: usize, flips: u64, player: IPlayer) -> u64 {
+    let mut delta = PIECE_KEYS[player as usize][index] ^ WHITE_TO_MOVE_KEY;
+    for flipped in indices_from_mask(flips) {
+        delta ^= PIECE_KEYS[0][flipped] ^ PIECE_KEYS[1][flipped];
+    }
+    delta
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::gameplay::bitboard::Bitboard;
+
+    use super::{ zobrist_hash, PIECE_KEYS, WHITE_TO_MOVE_KEY };
+
+    #[test]
+    fn keys_are_distinct() {
+        let mut keys: HashSet<u64> = PIECE_KEYS.iter().flatten().copied().collect();
+        keys.insert(WHITE_TO_MOVE_KEY);
+        assert_eq!(keys.len(), 129);
+    }
+
+    #[test]
+    fn hashes_the_side_to_move() {
+        let board = Bitboard::new();
+        assert_eq!(zobrist_hash(&board, 0) ^ zobrist_hash(&board, 1), WHITE_TO_MOVE_KEY);
+        assert_ne!(zobrist_hash(&board, 0), zobrist_hash(&Bitboard { pieces: [0, 0] }, 0));
+    }
+}
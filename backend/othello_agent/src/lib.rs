@@ -17,3 +17,5 @@ pub mod gameplay;
 pub mod agent;
 pub mod simulate;
 pub mod model;
+pub mod backend;
+pub mod error;
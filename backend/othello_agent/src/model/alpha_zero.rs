@@ -0,0 +1,448 @@
+use std::{ cell::RefCell, collections::{ HashMap, VecDeque }, rc::Rc };
+
+use burn::{
+    module::AutodiffModule,
+    optim::{ AdamConfig, GradientsParams, Optimizer },
+    prelude::*,
+    record::{ FullPrecisionSettings, NamedMpkFileRecorder },
+    tensor::backend::AutodiffBackend,
+};
+use rand::{ rngs::StdRng, seq::SliceRandom, Rng, SeedableRng };
+
+use crate::{
+    agent::mcts::{ run_mcts, terminal_value, MctsConfig, PriorPolicy, RolloutPolicy },
+    gameplay::{ bitboard::{ indices_from_mask, Bitboard }, game::IPlayer },
+};
+
+use super::policy_value::{
+    encode_position,
+    PolicyValueBatch,
+    PolicyValueModel,
+    PolicyValueModelConfig,
+    INPUT_PLANES,
+    POLICY_SIZE,
+};
+
+static ARTIFACT_DIR: &str = "tmp";
+
+#[derive(Config)]
+pub struct AlphaZeroConfig {
+    pub model: PolicyValueModelConfig,
+    pub optimizer: AdamConfig,
+    #[config(default = 20)]
+    pub generations: usize,
+    #[config(default = 25)]
+    pub games_per_generation: usize,
+    #[config(default = 200)]
+    pub mcts_iterations: u32,
+    #[config(default = 1.5)]
+    pub exploration: f32,
+    /// Moves are sampled in proportion to visit counts for this many plies, then played greedily.
+    #[config(default = 10)]
+    pub temperature_plies: usize,
+    #[config(default = 50000)]
+    pub replay_capacity: usize,
+    #[config(default = 200)]
+    pub train_steps: usize,
+    #[config(default = 64)]
+    pub batch_size: usize,
+    #[config(default = 1e-3)]
+    pub learning_rate: f64,
+    #[config(default = 20)]
+    pub gating_games: usize,
+    /// Random opening plies played before each gating game so that games differ.
+    #[config(default = 4)]
+    pub gating_random_plies: usize,
+    /// Score (wins plus half of draws) the candidate needs against the best model to replace it.
+    #[config(default = 0.55)]
+    pub gating_threshold: f32,
+    #[config(default = 42)]
+    pub seed: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct SelfPlayExample {
+    pub board: Bitboard,
+    pub player: IPlayer,
+    /// Normalised visit counts of the root moves, indexed like the policy head.
+    pub policy: Vec<f32>,
+    /// Final result for `player`: 1 win, -1 loss, 0 draw.
+    pub value: f32,
+}
+
+///
+/// Fixed size buffer of recent self-play positions. Once full, the oldest positions are dropped.
+pub struct ReplayBuffer {
+    capacity: usize,
+    examples: VecDeque<SelfPlayExample>,
+}
+
+impl ReplayBuffer {
+    pub fn new(capacity: usize) -> Self {
+        ReplayBuffer {
+            capacity,
+            examples: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn add_game(&mut self, examples: Vec<SelfPlayExample>) {
+        for example in examples {
+            if self.examples.len() == self.capacity {
+                self.examples.pop_front();
+            }
+            self.examples.push_back(example);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.examples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.examples.is_empty()
+    }
+
+    /// Samples `batch_size` positions uniformly, with replacement.
+    pub fn sample<R: Rng>(&self, batch_size: usize, rng: &mut R) -> Vec<&SelfPlayExample> {
+        (0..batch_size).map(|_| &self.examples[rng.gen_range(0..self.examples.len())]).collect()
+    }
+}
+
+///
+/// Stacks examples into tensors for `PolicyValueModel::loss`.
+pub fn batch_examples<B: Backend>(
+    examples: &[&SelfPlayExample],
+    device: &B::Device
+) -> PolicyValueBatch<B> {
+    let batch_size = examples.len();
+    let mut features: Vec<f32> = Vec::with_capacity(batch_size * INPUT_PLANES * 64);
+    let mut policies: Vec<f32> = Vec::with_capacity(batch_size * POLICY_SIZE);
+    let mut values: Vec<f32> = Vec::with_capacity(batch_size);
+    for example in examples {
+        features.extend(encode_position(&example.board, example.player));
+        policies.extend(example.policy.iter());
+        values.push(example.value);
+    }
+    PolicyValueBatch {
+        features: Tensor::from_data(
+            Data::new(features, Shape::new([batch_size, INPUT_PLANES, 8, 8])).convert(),
+            device
+        ),
+        policy_targets: Tensor::from_data(
+            Data::new(policies, Shape::new([batch_size, POLICY_SIZE])).convert(),
+            device
+        ),
+        value_targets: Tensor::from_data(
+            Data::new(values, Shape::new([batch_size, 1])).convert(),
+            device
+        ),
+    }
+}
+
+type Evaluations = HashMap<(Bitboard, IPlayer), (Vec<f32>, f32)>;
+
+///
+/// Lets MCTS use a policy-value network for both the priors and the leaf values. Every position
+/// is evaluated once, so the priors of a node expanded after being evaluated as a leaf are free.
+/// Clones share the same network and cache.
+pub struct NetworkEvaluator<'a, B: Backend> {
+    model: &'a PolicyValueModel<B>,
+    device: &'a B::Device,
+    cache: Rc<RefCell<Evaluations>>,
+}
+
+impl<'a, B: Backend> NetworkEvaluator<'a, B> {
+    pub fn new(model: &'a PolicyValueModel<B>, device: &'a B::Device) -> Self {
+        NetworkEvaluator {
+            model,
+            device,
+            cache: Rc::new(RefCell::new(HashMap::new())),
+        }
+    }
+
+    fn evaluate_position(&self, board: &Bitboard, player: IPlayer) -> (Vec<f32>, f32) {
+        self.cache
+            .borrow_mut()
+            .entry((*board, player))
+            .or_insert_with(|| self.model.predict(board, player, self.device))
+            .clone()
+    }
+}
+
+impl<B: Backend> Clone for NetworkEvaluator<'_, B> {
+    fn clone(&self) -> Self {
+        NetworkEvaluator {
+            model: self.model,
+            device: self.device,
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+impl<B: Backend> RolloutPolicy for NetworkEvaluator<'_, B> {
+    fn evaluate(&mut self, board: &Bitboard, player: IPlayer) -> f32 {
+        self.evaluate_position(board, player).1
+    }
+}
+
+impl<B: Backend> PriorPolicy for NetworkEvaluator<'_, B> {
+    fn priors(&mut self, board: &Bitboard, player: IPlayer, moves: &[usize]) -> Vec<f32> {
+        let (policy, _) = self.evaluate_position(board, player);
+        moves
+            .iter()
+            .map(|&index| policy[index])
+            .collect()
+    }
+}
+
+/// Runs network guided MCTS and returns the normalised visit counts for the side to move.
+fn search_policy<B: Backend>(
+    evaluator: &NetworkEvaluator<B>,
+    board: &Bitboard,
+    player: IPlayer,
+    mcts_config: &MctsConfig
+) -> Vec<f32> {
+    let mut rollout = evaluator.clone();
+    let mut prior = evaluator.clone();
+    let visits = run_mcts(board, player, mcts_config, &mut rollout, Some(&mut prior));
+    let total: u32 = visits
+        .iter()
+        .map(|visit| visit.visits)
+        .sum();
+    let mut policy = vec![0.0; POLICY_SIZE];
+    for visit in visits.iter() {
+        policy[visit.move_index] = (visit.visits as f32) / (total.max(1) as f32);
+    }
+    policy
+}
+
+fn argmax(policy: &[f32]) -> usize {
+    policy
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(index, _)| index)
+        .expect("Expected a non-empty policy")
+}
+
+fn sample<R: Rng>(policy: &[f32], rng: &mut R) -> usize {
+    let mut remaining: f32 = rng.gen::<f32>() * policy.iter().sum::<f32>();
+    for (index, &probability) in policy.iter().enumerate() {
+        if probability > 0.0 && remaining < probability {
+            return index;
+        }
+        remaining -= probability;
+    }
+    argmax(policy)
+}
+
+///
+/// Plays one game of the model against itself and returns a training example for every
+/// position where the side to move had a choice of moves. Forced passes are not recorded.
+pub fn self_play_game<B: Backend, R: Rng>(
+    model: &PolicyValueModel<B>,
+    device: &B::Device,
+    config: &AlphaZeroConfig,
+    rng: &mut R
+) -> Vec<SelfPlayExample> {
+    let mcts_config = MctsConfig {
+        iterations: config.mcts_iterations,
+        exploration: config.exploration,
+    };
+    let evaluator = NetworkEvaluator::new(model, device);
+    let mut examples: Vec<SelfPlayExample> = Vec::new();
+    let mut board = Bitboard::new();
+    let mut player: IPlayer = 0;
+    let mut ply = 0;
+    loop {
+        if !board.has_move(player) {
+            if !board.has_move(1 - player) {
+                break;
+            }
+            player = 1 - player;
+            continue;
+        }
+        let policy = search_policy(&evaluator, &board, player, &mcts_config);
+        let index = if ply < config.temperature_plies { sample(&policy, rng) } else { argmax(&policy) };
+        examples.push(SelfPlayExample {
+            board,
+            player,
+            policy,
+            value: 0.0,
+        });
+        board = board.play(index, player).expect("Expected a legal move");
+        player = 1 - player;
+        ply += 1;
+    }
+    for example in examples.iter_mut() {
+        example.value = terminal_value(&board, example.player);
+    }
+    examples
+}
+
+///
+/// Plays `games` games between two models with greedy MCTS, alternating colours, and returns
+/// the first model's score: wins plus half of the draws, divided by the number of games.
+pub fn play_match<B: Backend, R: Rng>(
+    model: &PolicyValueModel<B>,
+    opponent: &PolicyValueModel<B>,
+    device: &B::Device,
+    config: &AlphaZeroConfig,
+    rng: &mut R
+) -> f32 {
+    let mcts_config = MctsConfig {
+        iterations: config.mcts_iterations,
+        exploration: config.exploration,
+    };
+    let mut score = 0.0;
+    for game in 0..config.gating_games {
+        // the model plays black in even games
+        let model_player: IPlayer = (game % 2) as IPlayer;
+        let evaluators = [NetworkEvaluator::new(model, device), NetworkEvaluator::new(opponent, device)];
+        let mut board = Bitboard::new();
+        let mut player: IPlayer = 0;
+        let mut ply = 0;
+        loop {
+            if !board.has_move(player) {
+                if !board.has_move(1 - player) {
+                    break;
+                }
+                player = 1 - player;
+                continue;
+            }
+            let index = if ply < config.gating_random_plies {
+                *indices_from_mask(board.legal_moves(player)).choose(rng).unwrap()
+            } else {
+                let evaluator = if player == model_player { &evaluators[0] } else { &evaluators[1] };
+                argmax(&search_policy(evaluator, &board, player, &mcts_config))
+            };
+            board = board.play(index, player).expect("Expected a legal move");
+            player = 1 - player;
+            ply += 1;
+        }
+        score += (terminal_value(&board, model_player) + 1.0) / 2.0;
+    }
+    score / (config.gating_games.max(1) as f32)
+}
+
+fn create_artifact_dir(artifact_dir: &str) {
+    // Remove existing artifacts so that old checkpoints are not mistaken for new ones
+    std::fs::remove_dir_all(artifact_dir).ok();
+    std::fs::create_dir_all(artifact_dir).ok();
+}
+
+///
+/// AlphaZero-style training: each generation the best model generates self-play games into a
+/// replay buffer, a candidate is trained on samples from the buffer, and the candidate replaces
+/// the best model only if it wins the gating match.
+pub fn run<B: AutodiffBackend>(device: B::Device, experiment_name: &str, config: AlphaZeroConfig) {
+    let formatted_name = format!("{}/{}", ARTIFACT_DIR, experiment_name);
+    create_artifact_dir(&formatted_name);
+    config.save(format!("{formatted_name}/config.json").as_str()).unwrap();
+    B::seed(config.seed);
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let recorder = NamedMpkFileRecorder::<FullPrecisionSettings>::new();
+
+    let mut best: PolicyValueModel<B> = config.model.init(&device);
+    let mut candidate = best.clone();
+    let mut optimizer = config.optimizer.init::<B, PolicyValueModel<B>>();
+    let mut replay_buffer = ReplayBuffer::new(config.replay_capacity);
+
+    for generation in 0..config.generations {
+        let best_inference = best.valid();
+        for _ in 0..config.games_per_generation {
+            let examples = self_play_game(&best_inference, &device, &config, &mut rng);
+            replay_buffer.add_game(examples);
+        }
+        println!("Generation {}: {} positions in replay buffer", generation, replay_buffer.len());
+
+        let mut total_loss = 0.0;
+        for _ in 0..config.train_steps {
+            let examples = replay_buffer.sample(config.batch_size, &mut rng);
+            let batch = batch_examples::<B>(&examples, &device);
+            let loss = candidate.loss(batch);
+            total_loss += loss.clone().into_data().convert::<f32>().value[0];
+            let grads = GradientsParams::from_grads(loss.backward(), &candidate);
+            candidate = optimizer.step(config.learning_rate, candidate, grads);
+        }
+        println!(
+            "Generation {}: mean training loss {}",
+            generation,
+            total_loss / (config.train_steps.max(1) as f32)
+        );
+
+        let score = play_match(&candidate.valid(), &best_inference, &device, &config, &mut rng);
+        println!("Generation {}: candidate scored {} against best model", generation, score);
+        if score >= config.gating_threshold {
+            best = candidate.clone();
+            best.clone()
+                .save_file(format!("{formatted_name}/model"), &recorder)
+                .expect("Failed to save best model");
+            println!("Generation {}: candidate promoted to best model", generation);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use burn::{
+        backend::{ ndarray::NdArrayDevice, Autodiff, NdArray },
+        module::AutodiffModule,
+        optim::AdamConfig,
+    };
+    use rand::{ rngs::StdRng, SeedableRng };
+
+    use crate::model::policy_value::{ PolicyValueModelConfig, POLICY_SIZE };
+
+    use super::{ batch_examples, play_match, self_play_game, AlphaZeroConfig, ReplayBuffer };
+
+    fn small_config() -> AlphaZeroConfig {
+        AlphaZeroConfig::new(PolicyValueModelConfig::new().with_channels(4), AdamConfig::new())
+            .with_mcts_iterations(4)
+            .with_gating_games(2)
+    }
+
+    #[test]
+    fn self_play_produces_valid_examples() {
+        let device = NdArrayDevice::Cpu;
+        let config = small_config();
+        let model = config.model.init::<NdArray>(&device);
+        let mut rng = StdRng::seed_from_u64(1);
+        let examples = self_play_game(&model, &device, &config, &mut rng);
+        // the shortest possible game has nine moves
+        assert!(examples.len() >= 9);
+        for example in examples.iter() {
+            assert!(example.board.has_move(example.player));
+            assert_eq!(example.policy.len(), POLICY_SIZE);
+            assert!((example.policy.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+            assert!([-1.0, 0.0, 1.0].contains(&example.value));
+        }
+        // both players see the same result with opposite signs
+        let last = examples.last().unwrap();
+        assert!(
+            examples
+                .iter()
+                .all(|e| e.value == (if e.player == last.player { last.value } else { -last.value }))
+        );
+
+        let score = play_match(&model, &model, &device, &config, &mut rng);
+        assert!((0.0..=1.0).contains(&score));
+    }
+
+    #[test]
+    fn replay_buffer_drops_oldest_and_batches() {
+        type B = Autodiff<NdArray>;
+        let device = NdArrayDevice::Cpu;
+        let config = small_config();
+        let model = config.model.init::<B>(&device);
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut buffer = ReplayBuffer::new(10);
+        for _ in 0..2 {
+            buffer.add_game(self_play_game(&model.valid(), &device, &config, &mut rng));
+        }
+        assert_eq!(buffer.len(), 10);
+        let examples = buffer.sample(4, &mut rng);
+        let loss = model.loss(batch_examples::<B>(&examples, &device));
+        assert!(loss.into_data().convert::<f32>().value[0].is_finite());
+    }
+}
@@ -1,6 +1,11 @@
 use burn::{ data::dataloader::batcher::Batcher, prelude::* };
 
-use crate::simulate::history::ObservationMoveForML;
+use crate::{
+    gameplay::{ bitboard::Bitboard, encoding::board_from_floats, game::IPlayer },
+    simulate::history::ObservationMoveForML,
+};
+
+use super::policy_value::{ encode_position, INPUT_PLANES };
 
 #[derive(Clone)]
 pub struct OthelloMoveBatcher<B: Backend> {
@@ -46,3 +51,72 @@ impl<B: Backend> Batcher<ObservationMoveForML, OthelloMoveBatch<B>> for OthelloM
         }
     }
 }
+
+#[derive(Clone)]
+pub struct OthelloPolicyBatcher<B: Backend> {
+    device: B::Device,
+}
+
+impl<B: Backend> OthelloPolicyBatcher<B> {
+    pub fn new(device: B::Device) -> Self {
+        Self { device }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct OthelloPolicyBatch<B: Backend> {
+    /// Positions from the side to move's point of view, [batch, INPUT_PLANES, 8, 8]
+    pub features: Tensor<B, 4, Float>,
+    /// Piece index of the move that was played
+    pub targets: Tensor<B, 1, Int>,
+    /// True for squares the side to move cannot play, [batch, 64]
+    pub illegal_mask: Tensor<B, 2, Bool>,
+}
+
+///
+/// The dataset does not record whose turn it is. Black moves first and players alternate
+/// unless one has to pass, so the side to move is given by the number of pieces on the board
+/// unless the played move is only legal for the other player.
+pub fn player_to_move(board: &Bitboard, move_index: usize) -> IPlayer {
+    let by_parity: IPlayer = ((board.count(0) + board.count(1)) % 2) as IPlayer;
+    let legal_for = |player: IPlayer| (board.legal_moves(player) >> move_index) & 1 == 1;
+    if !legal_for(by_parity) && legal_for(1 - by_parity) {
+        return 1 - by_parity;
+    }
+    by_parity
+}
+
+impl<B: Backend> Batcher<ObservationMoveForML, OthelloPolicyBatch<B>> for OthelloPolicyBatcher<B> {
+    fn batch(&self, items: Vec<ObservationMoveForML>) -> OthelloPolicyBatch<B> {
+        let batch_size = items.len();
+        let mut features: Vec<f32> = Vec::with_capacity(batch_size * INPUT_PLANES * 64);
+        let mut targets: Vec<i64> = Vec::with_capacity(batch_size);
+        let mut illegal_mask: Vec<bool> = Vec::with_capacity(batch_size * 64);
+        for item in items.iter() {
+            let board = Bitboard::from_board(board_from_floats(item.board));
+            let player = player_to_move(&board, item.next_move_index);
+            let legal_moves = board.legal_moves(player);
+            features.extend(encode_position(&board, player));
+            targets.push(item.next_move_index as i64);
+            // never mask the target, a bad record should not produce an infinite loss
+            illegal_mask.extend(
+                (0..64).map(|index| index != item.next_move_index && (legal_moves >> index) & 1 == 0)
+            );
+        }
+
+        OthelloPolicyBatch {
+            features: Tensor::from_data(
+                Data::new(features, Shape::new([batch_size, INPUT_PLANES, 8, 8])).convert(),
+                &self.device
+            ),
+            targets: Tensor::from_data(
+                Data::new(targets, Shape::new([batch_size])).convert(),
+                &self.device
+            ),
+            illegal_mask: Tensor::from_data(
+                Data::new(illegal_mask, Shape::new([batch_size, 64])),
+                &self.device
+            ),
+        }
+    }
+}
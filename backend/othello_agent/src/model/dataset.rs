@@ -1,10 +1,12 @@
 use burn::data::dataset::{ transform::{ Mapper, MapperDataset }, Dataset, InMemDataset };
-use std::collections::HashMap;
+use rand::{ rngs::StdRng, Rng, SeedableRng };
+use std::{ collections::HashMap, sync::atomic::{ AtomicU32, Ordering } };
 
 use crate::{
     gameplay::{
         constants::CODE_CHARS,
         encoding::{ board_floats_from_string, create_code_char_hash },
+        symmetry::Symmetry,
     },
     simulate::history::{ ObservationMove, ObservationMoveForML },
 };
@@ -126,3 +128,156 @@ impl Mapper<ObservationMove, ObservationMoveForML> for ConvertSamples {
         self.to_formatted_observation(item)
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AugmentationMode {
+    /// Every item is returned under one random symmetry, drawn again on every access from the
+    /// seed, the index and how often the item was read before, so every epoch differs but a run
+    /// with the same seed sees the same symmetries.
+    Random,
+    /// Every item is returned under all eight symmetries, so the dataset is eight times larger.
+    Exhaustive,
+}
+
+///
+/// Wraps a dataset of observations and applies the board symmetries to the board and the next
+/// move. The winner is unchanged by a symmetry.
+pub struct SymmetryAugmentedDataset<D> {
+    dataset: D,
+    mode: AugmentationMode,
+    seed: u64,
+    // times each item was read in random mode, the epoch of its next read
+    reads: Vec<AtomicU32>,
+}
+
+impl<D: Dataset<ObservationMoveForML>> SymmetryAugmentedDataset<D> {
+    ///
+    /// Wraps a dataset.
+    ///
+    /// # Arguments
+    ///
+    /// * `dataset` - The observations to augment.
+    /// * `mode` - Whether to draw one symmetry per read or return all eight.
+    /// * `seed` - Seed of the random symmetries, unused by `AugmentationMode::Exhaustive`.
+    pub fn new(dataset: D, mode: AugmentationMode, seed: u64) -> Self {
+        let reads = match mode {
+            AugmentationMode::Random => (0..dataset.len()).map(|_| AtomicU32::new(0)).collect(),
+            AugmentationMode::Exhaustive => Vec::new(),
+        };
+        Self { dataset, mode, seed, reads }
+    }
+
+    /// Symmetry of the `epoch`-th read of the item at `index` in random mode.
+    fn random_symmetry(&self, index: usize, epoch: u32) -> Symmetry {
+        let key = (index as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ (epoch as u64);
+        let mut rng = StdRng::seed_from_u64(self.seed ^ key);
+        Symmetry::ALL[rng.gen_range(0..Symmetry::ALL.len())]
+    }
+}
+
+pub fn transform_observation(
+    item: ObservationMoveForML,
+    symmetry: Symmetry
+) -> ObservationMoveForML {
+    ObservationMoveForML {
+        board: symmetry.transform_board(item.board),
+        next_move_index: symmetry.transform_index(item.next_move_index),
+        ..item
+    }
+}
+
+impl<D> Dataset<ObservationMoveForML>
+    for SymmetryAugmentedDataset<D>
+    where D: Dataset<ObservationMoveForML>
+{
+    fn get(&self, index: usize) -> Option<ObservationMoveForML> {
+        match self.mode {
+            AugmentationMode::Random => {
+                let item = self.dataset.get(index)?;
+                let epoch = self.reads[index].fetch_add(1, Ordering::Relaxed);
+                Some(transform_observation(item, self.random_symmetry(index, epoch)))
+            }
+            AugmentationMode::Exhaustive => {
+                let symmetry = Symmetry::ALL[index % Symmetry::ALL.len()];
+                self.dataset
+                    .get(index / Symmetry::ALL.len())
+                    .map(|item| transform_observation(item, symmetry))
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self.mode {
+            AugmentationMode::Random => self.dataset.len(),
+            AugmentationMode::Exhaustive => self.dataset.len() * Symmetry::ALL.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use burn::data::dataset::{ Dataset, InMemDataset };
+
+    use crate::{
+        gameplay::{ constants::INITIAL_BOARD, game::board_to_ml_input, symmetry::Symmetry },
+        simulate::history::ObservationMoveForML,
+    };
+
+    use super::{ AugmentationMode, SymmetryAugmentedDataset };
+
+    fn observations() -> InMemDataset<ObservationMoveForML> {
+        InMemDataset::new(
+            vec![19, 37]
+                .into_iter()
+                .map(|next_move_index| ObservationMoveForML {
+                    board: board_to_ml_input(INITIAL_BOARD),
+                    game_id: 1,
+                    winner: 0,
+                    next_move_index,
+                })
+                .collect()
+        )
+    }
+
+    #[test]
+    fn exhaustive_augmentation_covers_every_symmetry() {
+        let mode = AugmentationMode::Exhaustive;
+        let dataset = SymmetryAugmentedDataset::new(observations(), mode, 0);
+        assert_eq!(dataset.len(), 16);
+        for (offset, symmetry) in Symmetry::ALL.iter().enumerate() {
+            let item = dataset.get(8 + offset).unwrap();
+            assert_eq!(item.next_move_index, symmetry.transform_index(37));
+            assert_eq!(item.board, symmetry.transform_board(board_to_ml_input(INITIAL_BOARD)));
+            assert_eq!(item.winner, 0);
+        }
+        assert!(dataset.get(16).is_none());
+    }
+
+    #[test]
+    fn random_augmentation_keeps_length() {
+        let dataset = SymmetryAugmentedDataset::new(observations(), AugmentationMode::Random, 7);
+        assert_eq!(dataset.len(), 2);
+        let item = dataset.get(0).unwrap();
+        assert!(
+            Symmetry::ALL.iter().any(|symmetry| {
+                symmetry.transform_index(19) == item.next_move_index
+            })
+        );
+        assert!(dataset.get(2).is_none());
+    }
+
+    #[test]
+    fn same_seed_draws_the_same_symmetries() {
+        let read_epochs = |seed| {
+            let mode = AugmentationMode::Random;
+            let dataset = SymmetryAugmentedDataset::new(observations(), mode, seed);
+            (0..20)
+                .map(|_| dataset.get(1).unwrap().next_move_index)
+                .collect::<Vec<usize>>()
+        };
+        let moves = read_epochs(3);
+        assert_eq!(moves, read_epochs(3));
+        // a new symmetry is drawn for every epoch
+        assert!(moves.iter().any(|&index| index != moves[0]));
+    }
+}
@@ -0,0 +1,114 @@
+use core::marker::PhantomData;
+
+use burn::{
+    prelude::*,
+    train::{
+        metric::{
+            state::{ FormatOptions, NumericMetricState },
+            Adaptor,
+            Metric,
+            MetricEntry,
+            MetricMetadata,
+            Numeric,
+        },
+        ClassificationOutput,
+    },
+};
+
+///
+/// Share of items whose target is among the `k` highest scoring outputs.
+pub struct TopKAccuracyMetric<B: Backend> {
+    k: usize,
+    state: NumericMetricState,
+    _b: PhantomData<B>,
+}
+
+pub struct TopKAccuracyInput<B: Backend> {
+    outputs: Tensor<B, 2>,
+    targets: Tensor<B, 1, Int>,
+}
+
+impl<B: Backend> TopKAccuracyMetric<B> {
+    pub fn new(k: usize) -> Self {
+        Self {
+            k,
+            state: NumericMetricState::new(),
+            _b: PhantomData,
+        }
+    }
+}
+
+///
+/// Counts the items whose target scores among the `k` highest outputs of its row.
+///
+/// # Arguments
+///
+/// * `outputs` - Row major scores, `n_classes` per item.
+/// * `targets` - One target class per item.
+pub fn top_k_hits(outputs: &[f32], targets: &[i64], n_classes: usize, k: usize) -> usize {
+    outputs
+        .chunks(n_classes)
+        .zip(targets.iter())
+        .filter(|(scores, &target)| {
+            let target_score = scores[target as usize];
+            // ties are resolved in favour of the target
+            scores
+                .iter()
+                .filter(|&&score| score > target_score)
+                .count() < k
+        })
+        .count()
+}
+
+impl<B: Backend> Metric for TopKAccuracyMetric<B> {
+    const NAME: &'static str = "Top-K Accuracy";
+
+    type Input = TopKAccuracyInput<B>;
+
+    fn update(&mut self, input: &TopKAccuracyInput<B>, _metadata: &MetricMetadata) -> MetricEntry {
+        let [batch_size, n_classes] = input.outputs.dims();
+        let outputs = input.outputs.clone().into_data().convert::<f32>().value;
+        let targets = input.targets.clone().into_data().convert::<i64>().value;
+        let hits = top_k_hits(&outputs, &targets, n_classes, self.k);
+
+        self.state.update(
+            (100.0 * (hits as f64)) / (batch_size as f64),
+            batch_size,
+            FormatOptions::new(&format!("Top-{} Accuracy", self.k)).unit("%").precision(2)
+        )
+    }
+
+    fn clear(&mut self) {
+        self.state.reset()
+    }
+}
+
+impl<B: Backend> Numeric for TopKAccuracyMetric<B> {
+    fn value(&self) -> f64 {
+        self.state.value()
+    }
+}
+
+impl<B: Backend> Adaptor<TopKAccuracyInput<B>> for ClassificationOutput<B> {
+    fn adapt(&self) -> TopKAccuracyInput<B> {
+        TopKAccuracyInput {
+            outputs: self.output.clone(),
+            targets: self.targets.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::top_k_hits;
+
+    #[test]
+    fn counts_targets_within_top_k() {
+        let outputs = [0.1, 0.5, 0.3, 0.1, 0.9, 0.0, 0.05, 0.05, 0.4, 0.2, 0.3, 0.1];
+        let targets = [2, 2, 3];
+        assert_eq!(top_k_hits(&outputs, &targets, 4, 1), 0);
+        assert_eq!(top_k_hits(&outputs, &targets, 4, 2), 2);
+        assert_eq!(top_k_hits(&outputs, &targets, 4, 3), 2);
+        assert_eq!(top_k_hits(&outputs, &targets, 4, 4), 3);
+    }
+}
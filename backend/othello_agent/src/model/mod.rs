@@ -2,3 +2,8 @@ pub mod dataset;
 pub mod train;
 pub mod batch;
 pub mod model;
+pub mod policy_value;
+pub mod alpha_zero;
+pub mod policy;
+pub mod metric;
+pub mod train_policy;
@@ -98,9 +98,18 @@ pub struct ConvBlock<B: Backend> {
 
 impl<B: Backend> ConvBlock<B> {
     pub fn new(channels: [usize; 2], kernel_size: [usize; 2], device: &B::Device) -> Self {
+        Self::with_padding(channels, kernel_size, PaddingConfig2d::Valid, device)
+    }
+
+    pub fn with_padding(
+        channels: [usize; 2],
+        kernel_size: [usize; 2],
+        padding: PaddingConfig2d,
+        device: &B::Device
+    ) -> Self {
         let conv = nn::conv::Conv2dConfig
             ::new(channels, kernel_size)
-            .with_padding(PaddingConfig2d::Valid)
+            .with_padding(padding)
             .init(device);
         let norm = nn::BatchNormConfig::new(channels[1]).init(device);
 
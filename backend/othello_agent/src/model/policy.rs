@@ -0,0 +1,189 @@
+use burn::{
+    nn::{ loss::CrossEntropyLossConfig, PaddingConfig2d },
+    prelude::*,
+    tensor::{ activation::softmax, backend::AutodiffBackend },
+    train::{ ClassificationOutput, TrainOutput, TrainStep, ValidStep },
+};
+
+use crate::gameplay::{ bitboard::{ indices_from_mask, Bitboard }, game::IPlayer };
+
+use super::{ batch::OthelloPolicyBatch, model::ConvBlock, policy_value::{ encode_position, INPUT_PLANES } };
+
+/// One output per square, passes are never predicted.
+pub const POLICY_MOVES: usize = 64;
+/// Logit given to illegal moves so that softmax assigns them no probability.
+const ILLEGAL_LOGIT: f32 = -1.0e9;
+
+#[derive(Module, Debug)]
+pub struct PolicyModel<B: Backend> {
+    conv1: ConvBlock<B>,
+    conv2: ConvBlock<B>,
+    conv3: ConvBlock<B>,
+    policy_conv: ConvBlock<B>,
+    fc: nn::Linear<B>,
+}
+
+#[derive(Config, Debug)]
+pub struct PolicyModelConfig {
+    #[config(default = 32)]
+    pub channels: usize,
+}
+
+impl PolicyModelConfig {
+    /// Returns the initialized model.
+    pub fn init<B: Backend>(&self, device: &B::Device) -> PolicyModel<B> {
+        PolicyModel::new(device, self.channels)
+    }
+}
+
+impl<B: Backend> PolicyModel<B> {
+    pub fn new(device: &B::Device, channels: usize) -> Self {
+        let same = || PaddingConfig2d::Same;
+        Self {
+            conv1: ConvBlock::with_padding([INPUT_PLANES, channels], [3, 3], same(), device),
+            conv2: ConvBlock::with_padding([channels, channels], [3, 3], same(), device),
+            conv3: ConvBlock::with_padding([channels, channels], [3, 3], same(), device),
+            policy_conv: ConvBlock::with_padding([channels, 2], [1, 1], same(), device),
+            fc: nn::LinearConfig::new(2 * 64, POLICY_MOVES).init(device),
+        }
+    }
+
+    /// Returns the move logits, [batch, POLICY_MOVES].
+    pub fn forward(&self, input: Tensor<B, 4>) -> Tensor<B, 2> {
+        let [batch_size, _, _, _] = input.dims();
+        let x = self.conv1.forward(input);
+        let x = self.conv2.forward(x);
+        let x = self.conv3.forward(x);
+        let x = self.policy_conv.forward(x).reshape([batch_size, 2 * 64]);
+        self.fc.forward(x)
+    }
+
+    /// Returns the move logits with illegal moves pushed to a large negative value.
+    pub fn forward_masked(&self, input: Tensor<B, 4>, illegal_mask: Tensor<B, 2, Bool>) -> Tensor<B, 2> {
+        self.forward(input).mask_fill(illegal_mask, ILLEGAL_LOGIT)
+    }
+
+    pub fn forward_classification(&self, item: OthelloPolicyBatch<B>) -> ClassificationOutput<B> {
+        let targets = item.targets;
+        let output = self.forward_masked(item.features, item.illegal_mask);
+        let loss = CrossEntropyLossConfig::new()
+            .init(&output.device())
+            .forward(output.clone(), targets.clone());
+
+        ClassificationOutput {
+            loss,
+            output,
+            targets,
+        }
+    }
+
+    ///
+    /// Move probabilities for a single position.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<(usize, f32)>` - Piece index and probability of every legal move, empty if the side
+    ///   to move has to pass.
+    pub fn predict(&self, board: &Bitboard, player: IPlayer, device: &B::Device) -> Vec<(usize, f32)> {
+        let legal_moves = indices_from_mask(board.legal_moves(player));
+        if legal_moves.is_empty() {
+            return Vec::new();
+        }
+        let input = Tensor::<B, 4>::from_data(
+            Data::new(encode_position(board, player), Shape::new([1, INPUT_PLANES, 8, 8])).convert(),
+            device
+        );
+        let illegal_mask = Tensor::<B, 2, Bool>::from_data(
+            Data::new(
+                (0..POLICY_MOVES).map(|index| !legal_moves.contains(&index)).collect(),
+                Shape::new([1, POLICY_MOVES])
+            ),
+            device
+        );
+        let probabilities = softmax(self.forward_masked(input, illegal_mask), 1)
+            .into_data()
+            .convert::<f32>().value;
+        legal_moves
+            .into_iter()
+            .map(|index| (index, probabilities[index]))
+            .collect()
+    }
+}
+
+impl<B: AutodiffBackend> TrainStep<OthelloPolicyBatch<B>, ClassificationOutput<B>> for PolicyModel<B> {
+    fn step(&self, item: OthelloPolicyBatch<B>) -> TrainOutput<ClassificationOutput<B>> {
+        let item = self.forward_classification(item);
+
+        TrainOutput::new(self, item.loss.backward(), item)
+    }
+}
+
+impl<B: Backend> ValidStep<OthelloPolicyBatch<B>, ClassificationOutput<B>> for PolicyModel<B> {
+    fn step(&self, item: OthelloPolicyBatch<B>) -> ClassificationOutput<B> {
+        self.forward_classification(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use burn::{
+        backend::{ ndarray::NdArrayDevice, NdArray },
+        data::dataloader::batcher::Batcher,
+    };
+
+    use crate::{
+        gameplay::{
+            bitboard::Bitboard,
+            constants::{ CODE_CHARS, INITIAL_BOARD },
+            encoding::{ board_floats_from_string, create_code_char_hash, string_from_board },
+        },
+        model::batch::{ player_to_move, OthelloPolicyBatcher },
+        simulate::history::ObservationMoveForML,
+    };
+
+    use super::PolicyModelConfig;
+
+    fn observation(board: Bitboard, next_move_index: usize) -> ObservationMoveForML {
+        let encoded = string_from_board(board.to_board(), CODE_CHARS);
+        ObservationMoveForML {
+            board: board_floats_from_string(&encoded, &create_code_char_hash(CODE_CHARS)),
+            game_id: 0,
+            winner: 2,
+            next_move_index,
+        }
+    }
+
+    #[test]
+    fn infers_side_to_move() {
+        let board = Bitboard::new();
+        // d3 (19) opens for black
+        assert_eq!(player_to_move(&board, 19), 0);
+        let board = board.play(19, 0).unwrap();
+        // c3 (18) replies for white
+        assert_eq!(player_to_move(&board, 18), 1);
+    }
+
+    #[test]
+    fn masks_illegal_moves() {
+        let device = NdArrayDevice::Cpu;
+        let batcher = OthelloPolicyBatcher::<NdArray>::new(device);
+        let opening = Bitboard::from_board(INITIAL_BOARD);
+        let after_d3 = opening.play(19, 0).unwrap();
+        let batch = batcher.batch(vec![observation(opening, 19), observation(after_d3, 18)]);
+        assert_eq!(batch.targets.clone().into_data().convert::<i64>().value, vec![19, 18]);
+        let mask = batch.illegal_mask.clone().into_data().value;
+        assert_eq!(mask[..64].iter().filter(|&&illegal| !illegal).count(), 4);
+        assert_eq!(mask[64..].iter().filter(|&&illegal| !illegal).count(), 3);
+
+        let model = PolicyModelConfig::new().with_channels(4).init::<NdArray>(&device);
+        let output = model.forward_classification(batch);
+        let predicted = output.output.argmax(1).into_data().convert::<i64>().value;
+        assert!(!mask[predicted[0] as usize]);
+        assert!(!mask[64 + (predicted[1] as usize)]);
+        assert!(output.loss.into_data().convert::<f32>().value[0].is_finite());
+
+        let probabilities = model.predict(&after_d3, 1, &device);
+        assert_eq!(probabilities.len(), 3);
+        assert!((probabilities.iter().map(|(_, p)| p).sum::<f32>() - 1.0).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,179 @@
+use burn::{ nn::PaddingConfig2d, prelude::*, tensor::activation::{ log_softmax, softmax, tanh } };
+
+use crate::gameplay::{ bitboard::{ indices_from_mask, Bitboard }, game::IPlayer };
+
+use super::model::ConvBlock;
+
+/// One output per square plus one for passing.
+pub const POLICY_SIZE: usize = 65;
+pub const PASS_POLICY_INDEX: usize = 64;
+/// Input planes: side to move's pieces, opponent's pieces and legal moves.
+pub const INPUT_PLANES: usize = 3;
+
+///
+/// Encodes a position from the point of view of the side to move as `INPUT_PLANES` 8x8 planes.
+pub fn encode_position(board: &Bitboard, player: IPlayer) -> Vec<f32> {
+    let planes = [
+        board.pieces[player as usize],
+        board.pieces[1 - (player as usize)],
+        board.legal_moves(player),
+    ];
+    let mut encoded = vec![0.0; INPUT_PLANES * 64];
+    for (plane_index, plane) in planes.iter().enumerate() {
+        for index in indices_from_mask(*plane) {
+            encoded[plane_index * 64 + index] = 1.0;
+        }
+    }
+    encoded
+}
+
+#[derive(Clone, Debug)]
+pub struct PolicyValueBatch<B: Backend> {
+    /// [batch, INPUT_PLANES, 8, 8]
+    pub features: Tensor<B, 4>,
+    /// Target move distribution, [batch, POLICY_SIZE]
+    pub policy_targets: Tensor<B, 2>,
+    /// Game outcome for the side to move, [batch, 1]
+    pub value_targets: Tensor<B, 2>,
+}
+
+#[derive(Module, Debug)]
+pub struct PolicyValueModel<B: Backend> {
+    conv1: ConvBlock<B>,
+    conv2: ConvBlock<B>,
+    conv3: ConvBlock<B>,
+    policy_conv: ConvBlock<B>,
+    policy_fc: nn::Linear<B>,
+    value_conv: ConvBlock<B>,
+    value_fc1: nn::Linear<B>,
+    value_fc2: nn::Linear<B>,
+    activation: nn::Gelu,
+}
+
+#[derive(Config, Debug)]
+pub struct PolicyValueModelConfig {
+    #[config(default = 32)]
+    pub channels: usize,
+}
+
+impl PolicyValueModelConfig {
+    /// Returns the initialized model.
+    pub fn init<B: Backend>(&self, device: &B::Device) -> PolicyValueModel<B> {
+        PolicyValueModel::new(device, self.channels)
+    }
+}
+
+impl<B: Backend> PolicyValueModel<B> {
+    pub fn new(device: &B::Device, channels: usize) -> Self {
+        // padded convolutions keep the 8x8 board shape through the trunk
+        let same = || PaddingConfig2d::Same;
+        Self {
+            conv1: ConvBlock::with_padding([INPUT_PLANES, channels], [3, 3], same(), device),
+            conv2: ConvBlock::with_padding([channels, channels], [3, 3], same(), device),
+            conv3: ConvBlock::with_padding([channels, channels], [3, 3], same(), device),
+            policy_conv: ConvBlock::with_padding([channels, 2], [1, 1], same(), device),
+            policy_fc: nn::LinearConfig::new(2 * 64, POLICY_SIZE).init(device),
+            value_conv: ConvBlock::with_padding([channels, 1], [1, 1], same(), device),
+            value_fc1: nn::LinearConfig::new(64, 64).init(device),
+            value_fc2: nn::LinearConfig::new(64, 1).init(device),
+            activation: nn::Gelu::new(),
+        }
+    }
+
+    ///
+    /// Returns the policy logits, [batch, POLICY_SIZE], and the value in [-1, 1] for the side to
+    /// move, [batch, 1].
+    pub fn forward(&self, input: Tensor<B, 4>) -> (Tensor<B, 2>, Tensor<B, 2>) {
+        let [batch_size, _, _, _] = input.dims();
+        let x = self.conv1.forward(input);
+        let x = self.conv2.forward(x);
+        let x = self.conv3.forward(x);
+
+        let policy = self.policy_conv.forward(x.clone()).reshape([batch_size, 2 * 64]);
+        let policy = self.policy_fc.forward(policy);
+
+        let value = self.value_conv.forward(x).reshape([batch_size, 64]);
+        let value = self.activation.forward(self.value_fc1.forward(value));
+        let value = tanh(self.value_fc2.forward(value));
+        (policy, value)
+    }
+
+    ///
+    /// Sum of the value mean squared error and the policy cross entropy against the target
+    /// distribution.
+    pub fn loss(&self, batch: PolicyValueBatch<B>) -> Tensor<B, 1> {
+        let (policy, value) = self.forward(batch.features);
+        let value_loss = (value - batch.value_targets).powf_scalar(2.0).mean();
+        let policy_loss = (batch.policy_targets * log_softmax(policy, 1))
+            .sum_dim(1)
+            .mean()
+            .neg();
+        value_loss + policy_loss
+    }
+
+    ///
+    /// Evaluates a single position.
+    ///
+    /// # Returns
+    ///
+    /// * `(Vec<f32>, f32)` - Move probabilities renormalised over the legal moves and the value for
+    ///   the side to move. Only the pass entry is set when there are no legal moves.
+    pub fn predict(&self, board: &Bitboard, player: IPlayer, device: &B::Device) -> (Vec<f32>, f32) {
+        let input = Tensor::<B, 4>::from_data(
+            Data::new(encode_position(board, player), Shape::new([1, INPUT_PLANES, 8, 8])).convert(),
+            device
+        );
+        let (policy, value) = self.forward(input);
+        let policy = softmax(policy, 1).into_data().convert::<f32>().value;
+        let value = value.into_data().convert::<f32>().value[0];
+        let legal = board.legal_moves(player);
+        let mut priors = vec![0.0; POLICY_SIZE];
+        if legal == 0 {
+            priors[PASS_POLICY_INDEX] = 1.0;
+            return (priors, value);
+        }
+        let legal_indices = indices_from_mask(legal);
+        let total: f32 = legal_indices
+            .iter()
+            .map(|&index| policy[index])
+            .sum();
+        for index in legal_indices.iter() {
+            priors[*index] = if total > 0.0 {
+                policy[*index] / total
+            } else {
+                1.0 / (legal_indices.len() as f32)
+            };
+        }
+        (priors, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use burn::backend::{ ndarray::NdArrayDevice, NdArray };
+
+    use crate::gameplay::bitboard::Bitboard;
+
+    use super::{ encode_position, PolicyValueModelConfig, INPUT_PLANES, POLICY_SIZE };
+
+    #[test]
+    fn encodes_side_to_move_first() {
+        let encoded = encode_position(&Bitboard::new(), 1);
+        assert_eq!(encoded.len(), INPUT_PLANES * 64);
+        // d4 (index 27) belongs to player 1, e4 (index 28) to player 0
+        assert_eq!(encoded[27], 1.0);
+        assert_eq!(encoded[64 + 28], 1.0);
+        assert_eq!(encoded[128..].iter().sum::<f32>(), 4.0);
+    }
+
+    #[test]
+    fn predicts_distribution_over_legal_moves() {
+        let device = NdArrayDevice::Cpu;
+        let model = PolicyValueModelConfig::new().init::<NdArray>(&device);
+        let (priors, value) = model.predict(&Bitboard::new(), 0, &device);
+        assert_eq!(priors.len(), POLICY_SIZE);
+        assert!((priors.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        assert_eq!(priors.iter().filter(|&&p| p > 0.0).count(), 4);
+        assert!((-1.0..=1.0).contains(&value));
+    }
+}
@@ -21,7 +21,7 @@ use burn::{
 
 use super::{
     batch::OthelloMoveBatcher,
-    dataset::OthelloMovesDataset,
+    dataset::{ AugmentationMode, OthelloMovesDataset, SymmetryAugmentedDataset },
     model::{ Model, ModelConfig },
 };
 
@@ -66,11 +66,17 @@ pub fn run<B: AutodiffBackend>(device: B::Device, experiment_name: &str) {
     let batcher_train = OthelloMoveBatcher::<B>::new(device.clone());
     let batcher_valid = OthelloMoveBatcher::<B::InnerBackend>::new(device.clone());
 
+    // the training split is shown under a random symmetry each time, the test split as played
+    let dataset_train = SymmetryAugmentedDataset::new(
+        OthelloMovesDataset::train(),
+        AugmentationMode::Random,
+        config.seed
+    );
     let dataloader_train = DataLoaderBuilder::new(batcher_train)
         .batch_size(config.batch_size)
         .shuffle(config.seed)
         .num_workers(config.num_workers)
-        .build(OthelloMovesDataset::train());
+        .build(dataset_train);
     let dataloader_test = DataLoaderBuilder::new(batcher_valid)
         .batch_size(config.batch_size)
         .shuffle(config.seed)
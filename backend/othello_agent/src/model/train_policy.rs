@@ -0,0 +1,123 @@
+use burn::{
+    data::dataloader::DataLoaderBuilder,
+    optim::{ decay::WeightDecayConfig, AdamConfig },
+    prelude::*,
+    record::{ CompactRecorder, FullPrecisionSettings, NamedMpkFileRecorder },
+    tensor::backend::AutodiffBackend,
+    train::{
+        metric::{
+            store::{ Aggregate, Direction, Split },
+            AccuracyMetric,
+            CpuMemory,
+            CpuUse,
+            LossMetric,
+        },
+        LearnerBuilder,
+        MetricEarlyStoppingStrategy,
+        StoppingCondition,
+    },
+};
+
+use super::{
+    batch::OthelloPolicyBatcher,
+    dataset::{ AugmentationMode, OthelloMovesDataset, SymmetryAugmentedDataset },
+    metric::TopKAccuracyMetric,
+    policy::PolicyModelConfig,
+};
+
+static ARTIFACT_DIR: &str = "tmp";
+pub const POLICY_ARTIFACT_DIR: &str = "tmp/othello_policy_training_artifacts";
+
+#[derive(Config)]
+pub struct OthelloPolicyTrainingConfig {
+    pub model: PolicyModelConfig,
+    #[config(default = 5)]
+    pub num_epochs: usize,
+
+    #[config(default = 128)]
+    pub batch_size: usize,
+
+    #[config(default = 4)]
+    pub num_workers: usize,
+
+    #[config(default = 42)]
+    pub seed: u64,
+
+    #[config(default = 1e-3)]
+    pub learning_rate: f64,
+
+    pub optimizer: AdamConfig,
+}
+
+fn create_artifact_dir(artifact_dir: &str) {
+    // Remove existing artifacts before to get an accurate learner summary
+    std::fs::remove_dir_all(artifact_dir).ok();
+    std::fs::create_dir_all(artifact_dir).ok();
+}
+
+///
+/// Trains the move prediction model on the moves played in the eOthello dataset, reporting
+/// top-1 and top-3 accuracy.
+pub fn run<B: AutodiffBackend>(device: B::Device, experiment_name: &str) {
+    let formatted_name = format!("{}/{}", ARTIFACT_DIR, experiment_name);
+    create_artifact_dir(&formatted_name);
+    // Config
+    let config_optimizer = AdamConfig::new().with_weight_decay(Some(WeightDecayConfig::new(5e-5)));
+    let config = OthelloPolicyTrainingConfig::new(PolicyModelConfig::new(), config_optimizer);
+    B::seed(config.seed);
+
+    // Data
+    let batcher_train = OthelloPolicyBatcher::<B>::new(device.clone());
+    let batcher_valid = OthelloPolicyBatcher::<B::InnerBackend>::new(device.clone());
+
+    // the training split is shown under a random symmetry each time, the test split as played
+    let dataset_train = SymmetryAugmentedDataset::new(
+        OthelloMovesDataset::train(),
+        AugmentationMode::Random,
+        config.seed
+    );
+    let dataloader_train = DataLoaderBuilder::new(batcher_train)
+        .batch_size(config.batch_size)
+        .shuffle(config.seed)
+        .num_workers(config.num_workers)
+        .build(dataset_train);
+    let dataloader_test = DataLoaderBuilder::new(batcher_valid)
+        .batch_size(config.batch_size)
+        .shuffle(config.seed)
+        .num_workers(config.num_workers)
+        .build(OthelloMovesDataset::test());
+
+    // Model
+    let learner = LearnerBuilder::new(&formatted_name)
+        .metric_train_numeric(AccuracyMetric::new())
+        .metric_valid_numeric(AccuracyMetric::new())
+        .metric_train_numeric(TopKAccuracyMetric::new(3))
+        .metric_valid_numeric(TopKAccuracyMetric::new(3))
+        .metric_train_numeric(CpuUse::new())
+        .metric_valid_numeric(CpuUse::new())
+        .metric_train_numeric(CpuMemory::new())
+        .metric_valid_numeric(CpuMemory::new())
+        .metric_train_numeric(LossMetric::new())
+        .metric_valid_numeric(LossMetric::new())
+        .with_file_checkpointer(CompactRecorder::new())
+        .early_stopping(
+            MetricEarlyStoppingStrategy::new::<LossMetric<B>>(
+                Aggregate::Mean,
+                Direction::Lowest,
+                Split::Valid,
+                StoppingCondition::NoImprovementSince { n_epochs: 1 }
+            )
+        )
+        .devices(vec![device.clone()])
+        .num_epochs(config.num_epochs)
+        .summary()
+        .build(config.model.init(&device), config.optimizer.init(), config.learning_rate);
+
+    let model_trained = learner.fit(dataloader_train, dataloader_test);
+
+    config.save(format!("{formatted_name}/config.json").as_str()).unwrap();
+    let recorder = NamedMpkFileRecorder::<FullPrecisionSettings>::new();
+    model_trained
+        .save_file(format!("{formatted_name}/model"), &recorder)
+        .expect("Failed to save trained model");
+}
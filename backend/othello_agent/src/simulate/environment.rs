@@ -1,7 +1,12 @@
+use rand::{ rngs::StdRng, Rng, SeedableRng };
 use rl_examples::{ environment::Environment, environments::blackjack::Player };
 
 use crate::{
-    gameplay::{ game::{ IBoard, IGame, IPlayer }, position::IPosition, utils::random_board },
+    error::OthelloError,
+    gameplay::{
+        game::{ IBoard, IGame, IPlayer, Move },
+        packed::StateEncoding,
+    },
     simulate::history::{ GameHistory, GameHistoryStore },
 };
 
@@ -54,17 +59,29 @@ pub struct OthelloEnvironment {
     game: IGame,
     history_store: GameHistoryStore,
     current_game_history: GameHistory,
+    state_encoding: StateEncoding,
+    // decides who starts, the random boards and the history ids
+    rng: StdRng,
 }
 
 impl OthelloEnvironment {
     pub fn new() -> Self {
+        OthelloEnvironment::with_rng(StdRng::from_entropy())
+    }
+
+    ///
+    /// Creates an environment that draws every random choice from `rng`, so the same seed
+    /// replays the same games for the same actions.
+    pub fn with_rng(mut rng: StdRng) -> Self {
         let mut new_env = OthelloEnvironment {
             player_a: OthelloPlayer::new("Player A".to_string(), 0, OthelloPlayerType::RL),
             player_b: OthelloPlayer::new("Player B".to_string(), 1, OthelloPlayerType::Rules),
             player_a_starts: true,
             game: IGame::new(),
             history_store: GameHistoryStore::new(),
-            current_game_history: GameHistory::new(),
+            current_game_history: GameHistory::with_rng(&mut rng),
+            state_encoding: StateEncoding::default(),
+            rng,
         };
         if new_env.player_a_starts {
             new_env.player_a.set_turn_id(0);
@@ -73,10 +90,27 @@ impl OthelloEnvironment {
         }
         new_env
     }
+    /// History of every move with the board after it, passes included. Its rows for the
+    /// training data leave the passes out, see `GameHistory::get_formatted_data`.
     pub fn get_game_history(&self) -> GameHistory {
         self.current_game_history.clone()
     }
 
+    /// Every move of the current game, passes included, to replay it with `IGame::from_moves`.
+    pub fn get_moves(&self) -> Vec<Move> {
+        self.game.moves()
+    }
+
+    /// Sets how `get_state` writes the board, code chars by default.
+    pub fn update_state_encoding(&mut self, state_encoding: StateEncoding) {
+        self.state_encoding = state_encoding;
+    }
+
+    /// Zobrist hash of the current board and player to move.
+    pub fn get_state_hash(&self) -> u64 {
+        self.game.hash()
+    }
+
     pub fn get_player_a(&self) -> OthelloPlayer {
         self.player_a.duplicate()
     }
@@ -89,15 +123,15 @@ impl OthelloEnvironment {
         self.player_b.duplicate()
     }
 
+    ///
+    /// Starts a new game from a random position that is not over, see `random_position`.
     pub fn reset_with_random_board(&mut self) {
-        let (board, last_piece, turn) = random_board();
-        let new_game = IGame::from_board(board, last_piece, turn);
-        self.game = new_game;
-        self.game = IGame::from_board(board, last_piece, turn);
+        self.game = IGame::random_starting_state_with_rng(&mut self.rng);
+        let turn = self.game.turn;
         self.player_a.has_move = false;
         self.player_b.has_move = false;
         // make random player start
-        self.player_a_starts = rand::random();
+        self.player_a_starts = self.rng.gen();
         let not_turn = 1 - turn;
         // TODO: UPDATE TURN ID TO BE OF TYPE IPLAYER
         if self.player_a_starts {
@@ -108,6 +142,52 @@ impl OthelloEnvironment {
             self.player_a.set_turn_id(not_turn as i8);
         }
     }
+
+    ///
+    /// Step function for the environment that returns an error instead of panicking.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - The piece index to play for the current player
+    ///
+    /// # Returns
+    ///
+    /// * `Result<f64, OthelloError>` - The reward for the action, `GameOver` if no player has a
+    ///   move, or `InvalidPosition` / `IllegalMove` if the action cannot be played. The
+    ///   environment is unchanged on error.
+    pub fn try_step(&mut self, action: usize) -> Result<f64, OthelloError> {
+        if self.game.is_over() {
+            return Err(OthelloError::GameOver);
+        }
+        self.game.try_make_move_at_index(action)?;
+        self.current_game_history.add_board(self.game.board, action, false);
+        // SET UP FOR NEXT ROUNDs
+        // check if either player has move
+        self.player_a.has_move = self.game.player_has_move(self.player_a.turn_id as u8);
+        self.player_b.has_move = self.game.player_has_move(self.player_b.turn_id as u8);
+        // the current player passes if they have no move, the pass is kept in the history
+        if !self.game.is_over() && self.game.get_legal_moves() == [Move::Pass] {
+            self.game.make_move(Move::Pass);
+            self.current_game_history.add_move(self.game.board, Move::Pass);
+        }
+        // game is over if no player has move
+        if let Some(result) = self.game.result() {
+            // set scores in game history
+            self.current_game_history.set_result(&result);
+            // return reward based on the winner
+            match result.winner {
+                // player a wins
+                Some(0) => Ok(1.0),
+                // player a loses
+                Some(_) => Ok(-1.0),
+                // tie
+                None => Ok(0.0),
+            }
+        } else {
+            // game continues if at least one player has move
+            Ok(0.0)
+        }
+    }
 }
 
 impl Environment for OthelloEnvironment {
@@ -116,7 +196,7 @@ impl Environment for OthelloEnvironment {
         self.player_a.has_move = false;
         self.player_b.has_move = false;
         // make random player start
-        self.player_a_starts = rand::random();
+        self.player_a_starts = self.rng.gen();
         if self.player_a_starts {
             self.player_a.set_turn_id(1);
             self.player_b.set_turn_id(0);
@@ -127,7 +207,7 @@ impl Environment for OthelloEnvironment {
     }
 
     fn get_state(&self) -> String {
-        self.game.get_board_string()
+        self.game.packed().to_state(self.state_encoding)
     }
 
     ///
@@ -141,7 +221,7 @@ impl Environment for OthelloEnvironment {
     ///
     /// # Panics
     ///
-    /// Panics if the action is invalid or the game is over.
+    /// Panics if the action is invalid or the game is over, see `OthelloEnvironment::try_step`.
     ///
     ///
     /// # Returns
@@ -149,57 +229,14 @@ impl Environment for OthelloEnvironment {
     /// * `f64` - The reward for the action
     ///
     fn step(&mut self, action: usize) -> f64 {
-        if !self.player_a.has_move && !self.player_b.has_move {
-            panic!("Game is over. No player has move");
-        }
-        let position = IPosition::position_from_piece_index(action as i8);
-        if position.is_none() {
-            panic!(
-                "Invalid action. Ensure index is valid (between 0 and 63 for standard board size)"
-            );
-        }
-        let position = position.unwrap();
-        self.game.make_move_at_position(&position);
-        let move_index = position.to_piece_index();
-        self.current_game_history.add_board(self.game.board, move_index, false);
-        // SET UP FOR NEXT ROUNDs
-        // check if either player has move
-        self.player_a.has_move = self.game.player_has_move(self.player_a.turn_id as u8);
-        self.player_b.has_move = self.game.player_has_move(self.player_b.turn_id as u8);
-        // toggle turn if current player has no move
-        if self.game.turn == 0 && !self.player_a.has_move {
-            self.game.toggle_turn();
-        } else {
-            if self.game.turn == 1 && !self.player_b.has_move {
-                self.game.toggle_turn();
-            }
-        }
-        // game is over if no player has move
-        if !self.player_a.has_move && !self.player_b.has_move {
-            // check scores for each player
-            let agenta_score = self.game.score_for_player(0);
-            let agentb_score = self.game.score_for_player(1);
-            // set scores in game history
-            self.current_game_history.set_scores(agenta_score, agentb_score);
-            // return reward based on scores
-            if agenta_score > agentb_score {
-                // player a wins
-                return 1.0;
-            } else if agenta_score < agentb_score {
-                // player a loses
-                return -1.0;
-            } else {
-                // tie
-                return 0.0;
-            }
-        } else {
-            // game continues if at least one player has move
-            0.0
+        match self.try_step(action) {
+            Ok(reward) => reward,
+            Err(error) => panic!("Invalid action: {}", error),
         }
     }
 
     fn is_terminal(&self) -> bool {
-        self.player_a.has_move == false && self.player_b.has_move == false
+        self.game.is_over()
     }
 
     fn get_number_of_possible_actions(&self) -> usize {
@@ -230,3 +267,112 @@ impl Environment for OthelloEnvironment {
         actions
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::{ rngs::StdRng, SeedableRng };
+    use rl_examples::environment::Environment;
+
+    use crate::{
+        error::OthelloError,
+        gameplay::{
+            constants::{ CODE_CHARS, INITIAL_BOARD },
+            encoding::create_code_char_hash,
+            game::{ IGame, Move },
+            packed::{ PackedPosition, StateEncoding },
+        },
+    };
+
+    use super::OthelloEnvironment;
+
+    #[test]
+    fn try_step_reports_errors() {
+        let mut env = OthelloEnvironment::new();
+        let illegal = OthelloError::IllegalMove { move_index: 0, player: 0 };
+        assert_eq!(env.try_step(0), Err(illegal));
+        let off_board = OthelloError::InvalidPosition { downwards: 25, rightwards: 7 };
+        assert_eq!(env.try_step(207), Err(off_board));
+        assert_eq!(env.game.board, INITIAL_BOARD);
+        assert_eq!(env.get_game_history().total_moves, 0);
+
+        // black wipes out white in nine moves
+        let moves = [19, 18, 17, 11, 4, 43, 51, 20];
+        for move_index in moves {
+            assert_eq!(env.try_step(move_index), Ok(0.0));
+        }
+        assert_eq!(env.try_step(29), Ok(1.0));
+        assert!(env.is_terminal());
+        assert_eq!(env.try_step(30), Err(OthelloError::GameOver));
+        let history = env.get_game_history();
+        assert_eq!((history.agent0_score, history.agent1_score), (64, 0));
+    }
+
+    #[test]
+    fn records_passes_in_the_moves() {
+        let mut env = OthelloEnvironment::new();
+        // black has no move after c1, white plays again
+        for move_index in [19, 18, 44, 11, 3, 4, 9, 2] {
+            assert_eq!(env.try_step(move_index), Ok(0.0));
+        }
+        assert_eq!(env.get_current_turn_id(), 1);
+        assert!(!env.is_terminal());
+        assert_eq!(env.try_step(16), Ok(0.0));
+
+        let moves = env.get_moves();
+        assert_eq!(moves.len(), 10);
+        assert_eq!(moves[8], Move::Pass);
+        assert_eq!(IGame::from_moves(&moves).unwrap().board, env.game.board);
+        // the history replays exactly, its training rows leave the pass out
+        let history = env.get_game_history();
+        assert_eq!(history.moves(), Ok(moves));
+        assert_eq!(history.total_moves, 10);
+        assert_eq!(history.get_formatted_data().len(), 8);
+        assert!(history.get_formatted_data().iter().all(|row| row.next_move_index < 64));
+    }
+
+    #[test]
+    fn writes_states_in_either_encoding() {
+        let mut env = OthelloEnvironment::new();
+        assert_eq!(env.get_state(), "---------h-yq---------");
+        env.update_state_encoding(StateEncoding::Packed);
+        let state = env.get_state();
+        assert_eq!(state, "00000010080000000000000810000000");
+        let hash = create_code_char_hash(CODE_CHARS);
+        let position = PackedPosition::try_from_state(&state, &hash).unwrap();
+        assert_eq!(position.to_board(), INITIAL_BOARD);
+        assert_eq!(env.get_state_hash(), position.zobrist_hash(0));
+    }
+
+    #[test]
+    fn can_play_after_a_reset() {
+        let mut env = OthelloEnvironment::new();
+        env.reset();
+        assert!(!env.is_terminal());
+        assert_eq!(env.try_step(19), Ok(0.0));
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_games() {
+        let mut first = OthelloEnvironment::with_rng(StdRng::seed_from_u64(9));
+        let mut second = OthelloEnvironment::with_rng(StdRng::seed_from_u64(9));
+        assert_eq!(first.get_game_history().id, second.get_game_history().id);
+        for _ in 0..5 {
+            first.reset_with_random_board();
+            second.reset_with_random_board();
+            assert_eq!(first.get_moves(), second.get_moves());
+            assert_eq!(first.get_player_a().turn_id, second.get_player_a().turn_id);
+        }
+    }
+
+    #[test]
+    fn can_play_after_a_random_reset() {
+        let mut env = OthelloEnvironment::new();
+        for _ in 0..20 {
+            env.reset_with_random_board();
+            assert!(!env.is_terminal());
+            let actions = env.get_actions();
+            assert!(!actions.is_empty());
+            assert!(env.try_step(actions[0]).is_ok());
+        }
+    }
+}
@@ -1,8 +1,11 @@
+use rand::Rng;
 use serde::{ Deserialize, Serialize };
 
-use crate::gameplay::constants::CODE_CHARS;
-use crate::gameplay::encoding::{ board_from_string, string_from_board };
-use crate::gameplay::game::{ IBoard, IBoardForML };
+use crate::gameplay::constants::{ CODE_CHARS, NULL_MOVE_INDEX };
+use crate::error::OthelloError;
+use crate::gameplay::encoding::{ board_from_string, create_code_char_hash, string_from_board };
+use crate::gameplay::game::{ GameResult, IBoard, IBoardForML, Move };
+use crate::gameplay::packed::{ PackedPosition, StateEncoding };
 use crate::gameplay::utils::augmented_score_for_player;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -36,7 +39,20 @@ pub struct ObservationMoveForML {
     pub next_move_index: usize,
 }
 
-#[derive(Debug, Clone)]
+/// Who played a game, and where and when, as far as the source of the game records it.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Default)]
+pub struct GameMetadata {
+    pub black_name: Option<String>,
+    pub white_name: Option<String>,
+    pub black_rating: Option<f32>,
+    pub white_rating: Option<f32>,
+    // date as written by the source
+    pub date: Option<String>,
+    // tournament or server the game was played at
+    pub place: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub struct GameHistory {
     // board history.. vector of encoded boards
     pub board_history: Vec<String>,
@@ -49,10 +65,17 @@ pub struct GameHistory {
     pub agent1_score: i16,
     // id of game... should be autoincremented
     pub id: u32,
+    // players and event, empty for games played here
+    #[serde(default)]
+    pub metadata: GameMetadata,
 }
 
 impl GameHistory {
     pub fn new() -> Self {
+        GameHistory::with_rng(&mut rand::thread_rng())
+    }
+    /// Empty history with a random id drawn from `rng`.
+    pub fn with_rng<R: Rng>(rng: &mut R) -> Self {
         GameHistory {
             board_history: Vec::new(),
             move_history: Vec::new(),
@@ -60,16 +83,53 @@ impl GameHistory {
             agent0_score: 0,
             agent1_score: 0,
             // random id
-            id: rand::random(),
+            id: rng.gen(),
+            metadata: GameMetadata::default(),
         }
     }
     pub fn set_scores(&mut self, agent0_score: i16, agent1_score: i16) {
         self.agent0_score = agent0_score;
         self.agent1_score = agent1_score;
     }
+    /// Sets the final scores of a finished game, see `IGame::result`.
+    pub fn set_result(&mut self, result: &GameResult) {
+        self.set_scores(result.black_discs as i16, result.white_discs as i16);
+    }
+    /// Keeps the first `total_moves` moves, for moves that were taken back.
+    pub fn truncate(&mut self, total_moves: usize) {
+        self.board_history.truncate(total_moves);
+        self.move_history.truncate(total_moves);
+        self.total_moves = self.move_history.len() as u16;
+    }
     pub fn set_id(&mut self, id: u32) {
         self.id = id;
     }
+    ///
+    /// Appends a move with the board after it. A pass is kept as `NULL_MOVE_INDEX`, so the
+    /// history replays exactly, see `moves`.
+    pub fn add_move(&mut self, board: IBoard, played: Move) {
+        self.add_board(board, played.to_index(), false);
+    }
+
+    ///
+    /// Every move of the history, passes included, to replay it with `IGame::from_moves`.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<Move>, OthelloError>` - The moves, or `InvalidPosition` for an index that
+    ///   is neither a square nor `NULL_MOVE_INDEX`.
+    pub fn moves(&self) -> Result<Vec<Move>, OthelloError> {
+        self.move_history
+            .iter()
+            .map(|&move_index| {
+                Move::from_index(move_index).ok_or(OthelloError::InvalidPosition {
+                    downwards: (move_index / 8) as i64,
+                    rightwards: (move_index % 8) as i64,
+                })
+            })
+            .collect()
+    }
+
     pub fn add_board(&mut self, board: IBoard, move_index: usize, set_scores: bool) {
         self.board_history.push(string_from_board(board, CODE_CHARS));
         self.move_history.push(move_index);
@@ -83,14 +143,64 @@ impl GameHistory {
         self.set_scores(agent0_score, agent1_score);
     }
 
+    ///
+    /// Copy of this history without passes (`NULL_MOVE_INDEX`), the form the training data
+    /// expects since every row is labelled with the next move played.
+    pub fn without_passes(&self) -> GameHistory {
+        let mut history = self.clone();
+        history.board_history.clear();
+        history.move_history.clear();
+        let moves = self.board_history.iter().zip(self.move_history.iter());
+        for (board, &move_index) in moves.filter(|(_, &move_index)| move_index != NULL_MOVE_INDEX) {
+            history.board_history.push(board.clone());
+            history.move_history.push(move_index);
+        }
+        history.total_moves = history.move_history.len() as u16;
+        history
+    }
+
+    ///
+    /// Boards of the history as packed positions. Boards in either `StateEncoding` are read.
+    pub fn packed_boards(&self) -> Result<Vec<PackedPosition>, OthelloError> {
+        let code_char_hash = create_code_char_hash(CODE_CHARS);
+        self.board_history
+            .iter()
+            .map(|board| PackedPosition::try_from_state(board, &code_char_hash))
+            .collect()
+    }
+
+    ///
+    /// Copy of this history with its boards written in `encoding`. The csv datasets expect
+    /// `StateEncoding::CodeChars`.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<GameHistory, OthelloError>` - The copy, or the error of a board that could not
+    ///   be read.
+    pub fn with_state_encoding(
+        &self,
+        encoding: StateEncoding
+    ) -> Result<GameHistory, OthelloError> {
+        let mut history = self.clone();
+        history.board_history = self
+            .packed_boards()?
+            .iter()
+            .map(|position| position.to_state(encoding))
+            .collect();
+        Ok(history)
+    }
+
+    /// Rows of the moves dataset, each board labelled with the next piece placed. Passes are
+    /// left out, see `without_passes`.
     pub fn get_formatted_data(&self) -> Vec<ObservationMove> {
+        let placements = self.without_passes();
         let mut data: Vec<ObservationMove> = Vec::new();
-        for (i, board) in self.board_history.iter().enumerate() {
+        for (i, board) in placements.board_history.iter().enumerate() {
             // next move index is the index of the move that will be made next
-            if i + 1 == (self.total_moves as usize) {
+            if i + 1 == (placements.total_moves as usize) {
                 break;
             }
-            let next_move_index = self.move_history[i + 1];
+            let next_move_index = placements.move_history[i + 1];
             let winner = if self.agent0_score > self.agent1_score {
                 0
             } else if self.agent1_score > self.agent0_score {
@@ -166,3 +276,21 @@ impl GameHistoryStore {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::gameplay::{ constants::INITIAL_BOARD, packed::StateEncoding };
+
+    use super::GameHistory;
+
+    #[test]
+    fn converts_state_encodings() {
+        let mut history = GameHistory::new();
+        history.add_board(INITIAL_BOARD, 19, false);
+        let packed = history.with_state_encoding(StateEncoding::Packed).unwrap();
+        assert_eq!(packed.board_history, vec!["00000010080000000000000810000000"]);
+        assert_eq!(packed.packed_boards(), history.packed_boards());
+        let code_chars = packed.with_state_encoding(StateEncoding::CodeChars).unwrap();
+        assert_eq!(code_chars, history);
+    }
+}
@@ -0,0 +1,424 @@
+use std::{ fmt, io };
+
+use crate::gameplay::{
+    constants::INITIAL_BOARD,
+    transcript::{ parse_transcript, replay_moves, TranscriptError },
+};
+
+use super::history::{ GameHistory, GameHistoryStore, GameMetadata };
+
+/// Size of the WTHOR file header, shared by game, player and tournament files.
+const WTHOR_HEADER_SIZE: usize = 16;
+/// Size of one game in a `.wtb` file: ids, scores and 60 moves.
+const WTHOR_GAME_SIZE: usize = 68;
+/// Size of one name in a `WTHOR.JOU` player file.
+const WTHOR_PLAYER_SIZE: usize = 20;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportError {
+    /// The input is cut off or not in the expected format.
+    Malformed(String),
+    /// The game is not 8x8 Othello from the usual starting position.
+    UnsupportedVariant(String),
+    /// The moves cannot be played.
+    Transcript(TranscriptError),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::Malformed(reason) => write!(f, "malformed game record: {}", reason),
+            ImportError::UnsupportedVariant(variant) => {
+                write!(f, "unsupported variant: {}", variant)
+            }
+            ImportError::Transcript(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<TranscriptError> for ImportError {
+    fn from(error: TranscriptError) -> Self {
+        ImportError::Transcript(error)
+    }
+}
+
+///
+/// Replays the moves of an imported game and fills in its scores.
+///
+/// # Arguments
+///
+/// * `moves` - Piece indices, `NULL_MOVE_INDEX` for a pass.
+/// * `black_score` - Final disc count of black given by the source, if any. Empty squares are
+///   counted for the winner, so white has the rest of the 64 squares.
+/// * `metadata` - Players and event.
+///
+/// # Returns
+///
+/// * `Result<GameHistory, ImportError>` - The history with every pass, including the ones the
+///   source left out. Its training rows skip them, see `GameHistory::get_formatted_data`.
+fn game_history_from_moves(
+    moves: &[usize],
+    black_score: Option<i16>,
+    metadata: GameMetadata
+) -> Result<GameHistory, ImportError> {
+    let mut history = replay_moves(moves)?;
+    if let Some(black_score) = black_score {
+        history.set_scores(black_score, 64 - black_score);
+    }
+    history.metadata = metadata;
+    Ok(history)
+}
+
+/// Splits the body of a GGF game into its `KEY[value]` properties, in order.
+fn ggf_properties(body: &str) -> Result<Vec<(&str, &str)>, ImportError> {
+    let mut properties = Vec::new();
+    let mut rest = body.trim_start();
+    while !rest.is_empty() {
+        let open = rest
+            .find('[')
+            .ok_or_else(|| ImportError::Malformed(format!("expected a property at {:?}", rest)))?;
+        let close = rest[open..]
+            .find(']')
+            .map(|close| open + close)
+            .ok_or_else(|| ImportError::Malformed("unterminated property".to_string()))?;
+        properties.push((rest[..open].trim(), &rest[open + 1..close]));
+        rest = rest[close + 1..].trim_start();
+    }
+    Ok(properties)
+}
+
+/// Checks that a GGF `BO` property holds the usual starting position with black to move.
+fn check_ggf_board(board: &str) -> Result<(), ImportError> {
+    let mut fields = board.split_whitespace();
+    let size = fields.next().unwrap_or_default();
+    let squares: String = fields.collect();
+    let expected: String = INITIAL_BOARD.iter()
+        .flatten()
+        .map(|&piece| {
+            match piece {
+                0 => '*',
+                1 => 'O',
+                _ => '-',
+            }
+        })
+        .chain(std::iter::once('*'))
+        .collect();
+    if size != "8" || squares != expected {
+        return Err(ImportError::UnsupportedVariant(format!("starting board {}", board)));
+    }
+    Ok(())
+}
+
+/// Reads a GGF result such as `+12.000` or `-64.000:r` as black's final disc count.
+fn ggf_black_score(result: &str) -> Result<i16, ImportError> {
+    let margin = result.split(':').next().unwrap_or_default().trim();
+    let margin: f32 = margin
+        .parse()
+        .map_err(|_| ImportError::Malformed(format!("result {}", result)))?;
+    Ok(((64.0 + margin) / 2.0).round().clamp(0.0, 64.0) as i16)
+}
+
+fn parse_ggf_game(body: &str) -> Result<GameHistory, ImportError> {
+    let mut moves = Vec::new();
+    let mut black_score = None;
+    let mut metadata = GameMetadata::default();
+    for (key, value) in ggf_properties(body)? {
+        match key {
+            "GM" if !value.eq_ignore_ascii_case("othello") => {
+                return Err(ImportError::UnsupportedVariant(format!("game {}", value)));
+            }
+            "TY" if value.trim() != "8" => {
+                return Err(ImportError::UnsupportedVariant(format!("type {}", value)));
+            }
+            "BO" => check_ggf_board(value)?,
+            "PB" => {
+                metadata.black_name = Some(value.to_string());
+            }
+            "PW" => {
+                metadata.white_name = Some(value.to_string());
+            }
+            "RB" => {
+                metadata.black_rating = value.trim().parse().ok();
+            }
+            "RW" => {
+                metadata.white_rating = value.trim().parse().ok();
+            }
+            "DT" => {
+                metadata.date = Some(value.to_string());
+            }
+            "PC" => {
+                metadata.place = Some(value.to_string());
+            }
+            "RE" => {
+                black_score = Some(ggf_black_score(value)?);
+            }
+            // a move can carry an evaluation and the time taken, e.g. f5/1.50/3.2
+            "B" | "W" => {
+                let square = value.split('/').next().unwrap_or_default();
+                moves.extend(parse_transcript(square)?);
+            }
+            _ => {}
+        }
+    }
+    game_history_from_moves(&moves, black_score, metadata)
+}
+
+///
+/// Reads the games of a GGF (Generic Game Format) file, e.g. from GGS archives. Each game is
+/// written `(;GM[Othello]PB[..]PW[..]RE[+2.000]BO[8 ..]B[f5]W[d6]...;)`.
+///
+/// # Arguments
+///
+/// * `text` - Contents of the file.
+///
+/// # Returns
+///
+/// * `Vec<Result<GameHistory, ImportError>>` - One entry per game, in file order, so a broken
+///   or unsupported game does not stop the others from being read.
+pub fn parse_ggf(text: &str) -> Vec<Result<GameHistory, ImportError>> {
+    let mut games = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("(;") {
+        let Some(end) = rest[start..].find(";)") else {
+            games.push(Err(ImportError::Malformed("unterminated game".to_string())));
+            break;
+        };
+        games.push(parse_ggf_game(&rest[start + 2..start + end]));
+        rest = &rest[start + end + 2..];
+    }
+    games
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+///
+/// Checks a WTHOR header and the size of the records after it.
+///
+/// # Returns
+///
+/// * `Result<&[u8], ImportError>` - The bytes of the records.
+fn wthor_records(
+    bytes: &[u8],
+    record_count: usize,
+    record_size: usize
+) -> Result<&[u8], ImportError> {
+    let end = WTHOR_HEADER_SIZE + record_count * record_size;
+    if bytes.len() < end {
+        let length = bytes.len();
+        let reason = format!("expected {} records but the file has {} bytes", record_count, length);
+        return Err(ImportError::Malformed(reason));
+    }
+    Ok(&bytes[WTHOR_HEADER_SIZE..end])
+}
+
+///
+/// Reads the player names of a `WTHOR.JOU` file. A player's id in the game files is their
+/// index in the returned list.
+pub fn parse_wthor_players(bytes: &[u8]) -> Result<Vec<String>, ImportError> {
+    if bytes.len() < WTHOR_HEADER_SIZE {
+        return Err(ImportError::Malformed("the player file has no header".to_string()));
+    }
+    let player_count = read_u16(bytes, 8) as usize;
+    let records = wthor_records(bytes, player_count, WTHOR_PLAYER_SIZE)?;
+    let names = records
+        .chunks(WTHOR_PLAYER_SIZE)
+        .map(|record| {
+            let length = record
+                .iter()
+                .position(|&byte| byte == 0)
+                .unwrap_or(record.len());
+            // names are latin-1, which maps one to one onto the first unicode code points
+            record[..length]
+                .iter()
+                .map(|&byte| byte as char)
+                .collect::<String>()
+                .trim()
+                .to_string()
+        })
+        .collect();
+    Ok(names)
+}
+
+/// Converts a WTHOR move, 10 * rank + file with both counted from 1, to a piece index.
+fn wthor_move_index(byte: u8) -> Option<usize> {
+    let (rank, file) = ((byte / 10) as usize, (byte % 10) as usize);
+    if !(1..=8).contains(&rank) || !(1..=8).contains(&file) {
+        return None;
+    }
+    Some((rank - 1) * 8 + (file - 1))
+}
+
+///
+/// Reads the games of a WTHOR `.wtb` database, the format of the French federation's archive.
+///
+/// # Arguments
+///
+/// * `bytes` - Contents of the file.
+/// * `players` - Names from `parse_wthor_players`, may be empty.
+///
+/// # Returns
+///
+/// * `Result<Vec<Result<GameHistory, ImportError>>, ImportError>` - One entry per game, or an
+///   error if the file itself cannot be read.
+pub fn parse_wthor(
+    bytes: &[u8],
+    players: &[String]
+) -> Result<Vec<Result<GameHistory, ImportError>>, ImportError> {
+    if bytes.len() < WTHOR_HEADER_SIZE {
+        return Err(ImportError::Malformed("the game file has no header".to_string()));
+    }
+    let game_count = read_u32(bytes, 4) as usize;
+    let year = read_u16(bytes, 10);
+    let board_size = bytes[12];
+    if board_size != 0 && board_size != 8 {
+        return Err(ImportError::UnsupportedVariant(format!("board size {}", board_size)));
+    }
+    let records = wthor_records(bytes, game_count, WTHOR_GAME_SIZE)?;
+    let games = records
+        .chunks(WTHOR_GAME_SIZE)
+        .map(|record| {
+            let name = |id: u16| players.get(id as usize).cloned();
+            let metadata = GameMetadata {
+                black_name: name(read_u16(record, 2)),
+                white_name: name(read_u16(record, 4)),
+                date: Some(year.to_string()),
+                ..GameMetadata::default()
+            };
+            let mut moves = Vec::new();
+            // the moves are padded with zeros, passes are not written
+            for (ply, &byte) in record[8..].iter().take_while(|&&byte| byte != 0).enumerate() {
+                let move_index = wthor_move_index(byte).ok_or_else(|| {
+                    ImportError::Malformed(format!("move {} at ply {}", byte, ply + 1))
+                })?;
+                moves.push(move_index);
+            }
+            game_history_from_moves(&moves, Some(record[6] as i16), metadata)
+        })
+        .collect();
+    Ok(games)
+}
+
+/// Keeps the games that could be read, reporting how many were skipped.
+fn store_from_games(games: Vec<Result<GameHistory, ImportError>>) -> GameHistoryStore {
+    let mut game_history_store = GameHistoryStore::new();
+    let mut skipped = 0;
+    for game in games {
+        match game {
+            Ok(game) => game_history_store.add_game(game),
+            Err(error) => {
+                skipped += 1;
+                println!("Skipping game: {}", error);
+            }
+        }
+    }
+    println!("Imported {} games, skipped {}", game_history_store.total_games, skipped);
+    game_history_store
+}
+
+/// Reads a GGF file into a store that can be written with `write_history_to_file`.
+pub fn load_ggf_file(path: &str) -> io::Result<GameHistoryStore> {
+    let text = std::fs::read_to_string(path)?;
+    Ok(store_from_games(parse_ggf(&text)))
+}
+
+///
+/// Reads a WTHOR database into a store that can be written with `write_history_to_file`.
+///
+/// # Arguments
+///
+/// * `path` - Path of the `.wtb` game file.
+/// * `players_path` - Path of the `WTHOR.JOU` player file, games have no names without it.
+pub fn load_wthor_file(path: &str, players_path: Option<&str>) -> io::Result<GameHistoryStore> {
+    let invalid = |error: ImportError| io::Error::new(io::ErrorKind::InvalidData, error);
+    let players = match players_path {
+        Some(players_path) => parse_wthor_players(&std::fs::read(players_path)?).map_err(invalid)?,
+        None => Vec::new(),
+    };
+    let games = parse_wthor(&std::fs::read(path)?, &players).map_err(invalid)?;
+    Ok(store_from_games(games))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gameplay::transcript::{ transcript_from_history, TranscriptError };
+
+    use super::{
+        load_ggf_file,
+        load_wthor_file,
+        parse_ggf,
+        parse_wthor,
+        parse_wthor_players,
+        ImportError,
+    };
+
+    fn fixture(name: &str) -> String {
+        format!("{}/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    #[test]
+    fn reads_ggf_games() {
+        let text = std::fs::read_to_string(fixture("sample.ggf")).unwrap();
+        let games = parse_ggf(&text);
+        assert_eq!(games.len(), 4);
+
+        let wipeout = games[0].as_ref().unwrap();
+        assert_eq!(transcript_from_history(wipeout).unwrap(), "d3c3b3d2e1d6d7e3f4");
+        assert_eq!((wipeout.agent0_score, wipeout.agent1_score), (64, 0));
+        assert_eq!(wipeout.metadata.black_name.as_deref(), Some("alice"));
+        assert_eq!(wipeout.metadata.white_name.as_deref(), Some("bob"));
+        assert_eq!(wipeout.metadata.black_rating, Some(1850.5));
+        assert_eq!(wipeout.metadata.date.as_deref(), Some("2003.01.26_21:38:14.GMT"));
+
+        // the pass the file left out is recorded, the resignation sets the score
+        let resigned = games[1].as_ref().unwrap();
+        assert_eq!(transcript_from_history(resigned).unwrap(), "d3c3e6d2d1e1b2c1paa3");
+        // like in the eOthello data, no training row is labelled with it
+        assert_eq!(resigned.get_formatted_data().len(), 8);
+        assert_eq!((resigned.agent0_score, resigned.agent1_score), (20, 44));
+        assert_eq!(resigned.metadata.white_rating, None);
+
+        let random_start = games[2].as_ref().unwrap_err();
+        assert!(matches!(random_start, ImportError::UnsupportedVariant(_)));
+        let illegal = games[3].as_ref().unwrap_err();
+        let error = TranscriptError::IllegalMove { ply: 3, move_index: 0 };
+        assert_eq!(*illegal, ImportError::Transcript(error));
+
+        let store = load_ggf_file(&fixture("sample.ggf")).unwrap();
+        assert_eq!(store.total_games, 2);
+        // every stored game can be turned into training rows
+        assert_eq!(store.history[0].get_formatted_data().len(), 8);
+    }
+
+    #[test]
+    fn reads_wthor_games() {
+        let players = parse_wthor_players(&std::fs::read(fixture("WTHOR.JOU")).unwrap()).unwrap();
+        assert_eq!(players, vec!["Alice", "Bob", "Chloé"]);
+
+        let bytes = std::fs::read(fixture("sample.wtb")).unwrap();
+        let games = parse_wthor(&bytes, &players).unwrap();
+        assert_eq!(games.len(), 2);
+        let game = games[0].as_ref().unwrap();
+        // black passes after c1
+        assert_eq!(transcript_from_history(game).unwrap(), "d3c3e6d2d1e1b2c1paa3");
+        assert_eq!((game.agent0_score, game.agent1_score), (24, 40));
+        assert_eq!(game.metadata.black_name.as_deref(), Some("Chloé"));
+        assert_eq!(game.metadata.white_name.as_deref(), Some("Alice"));
+        assert_eq!(game.metadata.date.as_deref(), Some("2004"));
+        let error = TranscriptError::IllegalMove { ply: 2, move_index: 63 };
+        assert_eq!(games[1].as_ref().unwrap_err(), &ImportError::Transcript(error));
+
+        let store = load_wthor_file(&fixture("sample.wtb"), None).unwrap();
+        assert_eq!(store.total_games, 1);
+        assert_eq!(store.history[0].metadata.black_name, None);
+        // a cut off file is reported instead of read past its end
+        assert!(matches!(parse_wthor(&bytes[..100], &players), Err(ImportError::Malformed(_))));
+    }
+}
@@ -1,3 +1,6 @@
 pub mod history;
+pub mod import;
 pub mod environment;
+pub mod sized_environment;
 pub mod utils;
+pub mod self_play;
@@ -0,0 +1,818 @@
+//!
+//! Games between two agents, played on several threads, for generating training data. Finished
+//! games are appended to a csv file in the format of `GameHistoryStore::write_history_to_file`,
+//! and a run that was stopped picks up where it left off. Every game draws its randomness from
+//! the seed of the run and its id, so the same seed writes the same file on any thread count.
+//! The settings of the run and the rows of every finished game are recorded next to the csv
+//! file, see `run_record_path`, so that a run is only resumed with the same settings.
+
+use std::{
+    collections::{ BTreeMap, HashMap, HashSet },
+    fmt,
+    fs::{ self, File, OpenOptions },
+    io::{ self, BufRead, BufReader, Write },
+    path::Path,
+    sync::{ atomic::{ AtomicUsize, Ordering }, mpsc, Arc },
+    thread,
+    time::Instant,
+};
+
+use rand::{ rngs::StdRng, seq::SliceRandom, Rng, SeedableRng };
+use rl_examples::agents::agent::Agent;
+use serde::{ Deserialize, Serialize };
+
+use crate::{
+    agent::{
+        mcts::{ MctsAgent, MctsConfig, RandomRollout },
+        rule_based::RuleAgent,
+        search_based::{ SearchAgent, SearchBudget, SearchConfig },
+        transposition::TranspositionTable,
+        value_based::ValueAgent,
+    },
+    backend::{ default_device, DefaultBackend },
+    error::OthelloError,
+    gameplay::{
+        constants::INITIAL_BOARD,
+        game::{ IGame, IPlayer, Move },
+        random_position::{ random_position, MAX_RANDOM_PLIES },
+    },
+    model::model::Model,
+    simulate::history::{ GameHistory, ObservationMove },
+};
+
+/// Agents that can play self play games, by the names the binaries and the server use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AgentKind {
+    /// Plays a uniformly random legal move.
+    Random,
+    RuleBased,
+    SearchBased,
+    Mcts,
+    /// Needs a trained value model, see `ValueAgent::load_value_model`.
+    ValueBased,
+}
+
+impl AgentKind {
+    pub const NAMES: [&'static str; 5] = [
+        "random",
+        "rule_based",
+        "search_based",
+        "mcts",
+        "value_based",
+    ];
+
+    /// Agent for one of `NAMES`, None for any other name.
+    pub fn from_name(name: &str) -> Option<AgentKind> {
+        match name {
+            "random" => Some(AgentKind::Random),
+            "rule_based" => Some(AgentKind::RuleBased),
+            "search_based" => Some(AgentKind::SearchBased),
+            "mcts" => Some(AgentKind::Mcts),
+            "value_based" => Some(AgentKind::ValueBased),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AgentKind::Random => "random",
+            AgentKind::RuleBased => "rule_based",
+            AgentKind::SearchBased => "search_based",
+            AgentKind::Mcts => "mcts",
+            AgentKind::ValueBased => "value_based",
+        }
+    }
+}
+
+/// What a self play run plays, see `run_self_play`.
+#[derive(Debug, Clone)]
+pub struct SelfPlayConfig {
+    pub games: usize,
+    pub threads: usize,
+    pub black: AgentKind,
+    pub white: AgentKind,
+    /// Random placements played before the agents take over, from 0 for the initial board up
+    /// to `MAX_RANDOM_PLIES`.
+    pub opening_plies: usize,
+    /// Print a progress line every this many finished games.
+    pub report_every: usize,
+    /// Size of the transposition table of the search agents of each thread, cleared before
+    /// every game so that games do not depend on each other.
+    pub table_megabytes: usize,
+    /// Nodes per move of the search agents. A node budget, unlike a time budget, gives the
+    /// same moves on every run.
+    pub search_nodes: u64,
+    /// Seed of the run, game `i` is played with the seed plus `i`.
+    pub seed: u64,
+}
+
+impl Default for SelfPlayConfig {
+    fn default() -> Self {
+        SelfPlayConfig {
+            games: 100,
+            threads: thread::available_parallelism().map_or(1, |threads| threads.get()),
+            black: AgentKind::RuleBased,
+            white: AgentKind::RuleBased,
+            opening_plies: 0,
+            report_every: 10,
+            table_megabytes: 16,
+            search_nodes: 100_000,
+            seed: rand::random(),
+        }
+    }
+}
+
+///
+/// Settings of a run that decide which games it plays. Threads and reporting are left out, they
+/// do not change the games.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunSettings {
+    pub black: String,
+    pub white: String,
+    pub opening_plies: usize,
+    pub search_nodes: u64,
+    pub table_megabytes: usize,
+    pub seed: u64,
+}
+
+impl RunSettings {
+    pub fn from_config(config: &SelfPlayConfig) -> RunSettings {
+        RunSettings {
+            black: config.black.name().to_string(),
+            white: config.white.name().to_string(),
+            opening_plies: config.opening_plies,
+            search_nodes: config.search_nodes,
+            table_megabytes: config.table_megabytes,
+            seed: config.seed,
+        }
+    }
+}
+
+impl fmt::Display for RunSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} against {}, {} opening plies, {} search nodes, {} MB table, seed {}",
+            self.black,
+            self.white,
+            self.opening_plies,
+            self.search_nodes,
+            self.table_megabytes,
+            self.seed
+        )
+    }
+}
+
+/// A line of the run record for a game whose rows were all written.
+#[derive(Debug, Serialize, Deserialize)]
+struct FinishedGame {
+    game_id: u32,
+    rows: usize,
+}
+
+///
+/// What a run recorded next to its csv file: its settings, then the number of rows of every
+/// game it finished.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunRecord {
+    pub settings: RunSettings,
+    pub games: HashMap<u32, usize>,
+}
+
+#[derive(Debug)]
+pub enum SelfPlayError {
+    Csv(csv::Error),
+    Io(io::Error),
+    /// The csv file has games but no run record, so its settings are unknown.
+    MissingRunRecord(String),
+    /// The run record does not start with the settings of a run.
+    InvalidRunRecord(String),
+    /// The csv file was written by a run with other settings.
+    SettingsMismatch {
+        recorded: Box<RunSettings>,
+        requested: Box<RunSettings>,
+    },
+    /// A game could not be played, e.g. an opening longer than `MAX_RANDOM_PLIES`.
+    Othello(OthelloError),
+}
+
+impl fmt::Display for SelfPlayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SelfPlayError::Csv(error) => write!(f, "{}", error),
+            SelfPlayError::Io(error) => write!(f, "{}", error),
+            SelfPlayError::MissingRunRecord(path) => {
+                write!(f, "{} has games but no run record, use another output file", path)
+            }
+            SelfPlayError::InvalidRunRecord(path) => write!(f, "{} is not a run record", path),
+            SelfPlayError::SettingsMismatch { recorded, requested } => {
+                write!(
+                    f,
+                    "the file was written by a run of {}, not {}; pass the same settings or use \
+                     another output file",
+                    recorded,
+                    requested
+                )
+            }
+            SelfPlayError::Othello(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for SelfPlayError {}
+
+impl From<csv::Error> for SelfPlayError {
+    fn from(error: csv::Error) -> Self {
+        SelfPlayError::Csv(error)
+    }
+}
+
+impl From<OthelloError> for SelfPlayError {
+    fn from(error: OthelloError) -> Self {
+        SelfPlayError::Othello(error)
+    }
+}
+
+impl From<io::Error> for SelfPlayError {
+    fn from(error: io::Error) -> Self {
+        SelfPlayError::Io(error)
+    }
+}
+
+/// Outcome counts of a self play run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SelfPlaySummary {
+    /// Games found in the output file from an earlier run.
+    pub skipped: usize,
+    pub played: usize,
+    pub black_wins: usize,
+    pub white_wins: usize,
+    pub draws: usize,
+}
+
+/// An agent of one game.
+enum SelfPlayer {
+    Random(StdRng),
+    Rule(RuleAgent),
+    Search(SearchAgent),
+    Mcts(MctsAgent),
+    Value(Box<ValueAgent<DefaultBackend>>),
+}
+
+impl SelfPlayer {
+    fn new(
+        kind: AgentKind,
+        player: IPlayer,
+        config: &SelfPlayConfig,
+        table: &Arc<TranspositionTable>,
+        value_model: Option<&Model<DefaultBackend>>,
+        rng: &mut StdRng
+    ) -> SelfPlayer {
+        let agent_rng = StdRng::seed_from_u64(rng.gen());
+        match kind {
+            AgentKind::Random => SelfPlayer::Random(agent_rng),
+            AgentKind::RuleBased => {
+                let mut agent = RuleAgent::new(player, INITIAL_BOARD);
+                agent.set_rng(agent_rng);
+                SelfPlayer::Rule(agent)
+            }
+            AgentKind::SearchBased => {
+                let search_config = SearchConfig {
+                    budget: SearchBudget::Nodes(config.search_nodes),
+                    ..SearchConfig::default()
+                };
+                let mut agent = SearchAgent::with_config(player, INITIAL_BOARD, search_config);
+                agent.set_transposition_table(Some(Arc::clone(table)));
+                SelfPlayer::Search(agent)
+            }
+            AgentKind::Mcts => {
+                let rollout_policy = Box::new(RandomRollout::new(agent_rng));
+                let mcts_config = MctsConfig::default();
+                SelfPlayer::Mcts(
+                    MctsAgent::with_config(player, INITIAL_BOARD, mcts_config, rollout_policy)
+                )
+            }
+            AgentKind::ValueBased => {
+                let model = value_model.expect("The value based agent needs a value model");
+                let (model, device) = (model.clone(), default_device());
+                let mut agent = ValueAgent::with_model(player, INITIAL_BOARD, model, device);
+                agent.set_rng(agent_rng);
+                SelfPlayer::Value(Box::new(agent))
+            }
+        }
+    }
+
+    /// Piece index of the move to play. The side to move has at least one placement.
+    fn select_action(&mut self, game: &IGame) -> usize {
+        match self {
+            SelfPlayer::Random(rng) => {
+                *placement_indices(game).choose(rng).expect("Expected a legal placement")
+            }
+            SelfPlayer::Rule(agent) => {
+                agent.update_board(game.board);
+                agent.select_action()
+            }
+            SelfPlayer::Search(agent) => {
+                agent.update_board(game.board);
+                agent.select_action()
+            }
+            SelfPlayer::Mcts(agent) => {
+                agent.update_game(game);
+                agent.select_action()
+            }
+            SelfPlayer::Value(agent) => {
+                agent.update_board(game.board);
+                agent.update_possible_move_indices(placement_indices(game));
+                agent.select_action()
+            }
+        }
+    }
+}
+
+fn placement_indices(game: &IGame) -> Vec<usize> {
+    game.get_legal_moves()
+        .iter()
+        .filter(|&&legal_move| legal_move != Move::Pass)
+        .map(Move::to_index)
+        .collect()
+}
+
+///
+/// Plays one game. The history holds the board after every placement of the agents, labelled
+/// with the move, starting with the board the opening left, like `OthelloEnvironment` does.
+///
+/// # Arguments
+///
+/// * `id` - Id of the game in the history, added to the seed of the run.
+/// * `config` - Agents, opening and seed.
+/// * `table` - Transposition table of the thread, emptied first.
+/// * `value_model` - Model of the value based agent.
+///
+/// # Returns
+///
+/// * `Result<GameHistory, OthelloError>` - The finished game, with its final scores, or the
+///   error of `random_position` if the opening could not be played.
+fn play_game(
+    id: u32,
+    config: &SelfPlayConfig,
+    table: &Arc<TranspositionTable>,
+    value_model: Option<&Model<DefaultBackend>>
+) -> Result<GameHistory, OthelloError> {
+    let mut rng = StdRng::seed_from_u64(config.seed.wrapping_add(id as u64));
+    table.clear();
+    let mut black = SelfPlayer::new(config.black, 0, config, table, value_model, &mut rng);
+    let mut white = SelfPlayer::new(config.white, 1, config, table, value_model, &mut rng);
+    let opening_plies = config.opening_plies;
+    let mut game = random_position(opening_plies..=opening_plies, &mut rng)?;
+    let mut history = GameHistory::new();
+    history.set_id(id);
+    if let Some(last) = game.plies().iter().rev().find(|ply| ply.played != Move::Pass) {
+        history.add_board(game.board, last.played.to_index(), false);
+    }
+    while !game.is_over() {
+        if game.get_legal_moves() == [Move::Pass] {
+            game.make_move(Move::Pass);
+            continue;
+        }
+        let agent = if game.turn == 0 { &mut black } else { &mut white };
+        let action = agent.select_action(&game);
+        if let Err(error) = game.try_make_move_at_index(action) {
+            panic!("Agent chose an illegal move: {}", error);
+        }
+        history.add_board(game.board, action, false);
+    }
+    history.set_result(&game.result().expect("Expected a finished game"));
+    Ok(history)
+}
+
+/// Path of the run record kept next to a self play csv file, one JSON object per line.
+pub fn run_record_path(file_path: &str) -> String {
+    format!("{}.run", file_path)
+}
+
+///
+/// Reads the run record of a self play csv file.
+///
+/// # Arguments
+///
+/// * `file_path` - The csv file, not the record itself.
+///
+/// # Returns
+///
+/// * `Result<Option<RunRecord>, SelfPlayError>` - None if there is no record. A game line that
+///   cannot be read was cut off by a stopped run, its game does not count as finished.
+pub fn read_run_record(file_path: &str) -> Result<Option<RunRecord>, SelfPlayError> {
+    let record_path = run_record_path(file_path);
+    if !Path::new(&record_path).exists() {
+        return Ok(None);
+    }
+    let mut lines = BufReader::new(File::open(&record_path)?).lines();
+    let settings = match lines.next().transpose()? {
+        Some(line) => serde_json::from_str::<RunSettings>(&line).ok(),
+        None => None,
+    };
+    let Some(settings) = settings else {
+        return Err(SelfPlayError::InvalidRunRecord(record_path));
+    };
+    let mut games = HashMap::new();
+    for line in lines {
+        if let Ok(game) = serde_json::from_str::<FinishedGame>(&line?) {
+            games.insert(game.game_id, game.rows);
+        }
+    }
+    Ok(Some(RunRecord { settings, games }))
+}
+
+/// Number of rows of each game id in a csv file, empty if the file does not exist.
+fn rows_per_game(file_path: &str) -> Result<HashMap<u32, usize>, csv::Error> {
+    let mut rows = HashMap::new();
+    if !Path::new(file_path).exists() {
+        return Ok(rows);
+    }
+    let mut reader = csv::Reader::from_path(file_path)?;
+    for observation in reader.deserialize::<ObservationMove>() {
+        *rows.entry(observation?.game_id).or_insert(0) += 1;
+    }
+    Ok(rows)
+}
+
+///
+/// Ids of the games of a self play csv file that are complete: the run record lists them and
+/// the file has all of their rows. Empty if the file does not exist.
+///
+/// # Arguments
+///
+/// * `file_path` - A file written by `run_self_play`.
+pub fn recorded_game_ids(file_path: &str) -> Result<HashSet<u32>, SelfPlayError> {
+    let rows = rows_per_game(file_path)?;
+    if rows.is_empty() {
+        return Ok(HashSet::new());
+    }
+    let Some(record) = read_run_record(file_path)? else {
+        return Err(SelfPlayError::MissingRunRecord(file_path.to_string()));
+    };
+    Ok(complete_games(&record, &rows))
+}
+
+fn complete_games(record: &RunRecord, rows: &HashMap<u32, usize>) -> HashSet<u32> {
+    record.games
+        .iter()
+        .filter(|&(id, count)| rows.get(id) == Some(count))
+        .map(|(&id, _)| id)
+        .collect()
+}
+
+///
+/// Keeps only the rows of the given games in a csv file, dropping the rows a stopped run wrote
+/// for a game it did not finish.
+fn keep_games(file_path: &str, games: &HashSet<u32>) -> Result<(), SelfPlayError> {
+    let kept_path = format!("{}.kept", file_path);
+    {
+        let mut reader = csv::Reader::from_path(file_path)?;
+        let mut writer = csv::Writer::from_path(&kept_path)?;
+        for observation in reader.deserialize::<ObservationMove>() {
+            let observation = observation?;
+            if games.contains(&observation.game_id) {
+                writer.serialize(observation)?;
+            }
+        }
+        writer.flush()?;
+    }
+    fs::rename(&kept_path, file_path)?;
+    Ok(())
+}
+
+///
+/// Checks a csv file against the settings of a run and prepares it to be resumed: rows of
+/// unfinished games are dropped and the run record is written again with the settings and the
+/// games that are complete.
+///
+/// # Returns
+///
+/// * `Result<HashSet<u32>, SelfPlayError>` - Ids of the complete games. `SettingsMismatch` if
+///   the file was written with other settings, `MissingRunRecord` if they are unknown.
+fn prepare_run(file_path: &str, settings: &RunSettings) -> Result<HashSet<u32>, SelfPlayError> {
+    let rows = rows_per_game(file_path)?;
+    let record = match read_run_record(file_path)? {
+        Some(record) if !rows.is_empty() => record,
+        None if !rows.is_empty() => {
+            return Err(SelfPlayError::MissingRunRecord(file_path.to_string()));
+        }
+        // nothing was written yet, whatever ran before
+        _ => RunRecord { settings: settings.clone(), games: HashMap::new() },
+    };
+    if record.settings != *settings {
+        return Err(SelfPlayError::SettingsMismatch {
+            recorded: Box::new(record.settings),
+            requested: Box::new(settings.clone()),
+        });
+    }
+    let complete = complete_games(&record, &rows);
+    if rows.keys().any(|id| !complete.contains(id)) {
+        keep_games(file_path, &complete)?;
+    }
+    let mut record_file = File::create(run_record_path(file_path))?;
+    writeln!(record_file, "{}", json_line(settings))?;
+    for id in complete.iter() {
+        writeln!(record_file, "{}", json_line(&FinishedGame { game_id: *id, rows: rows[id] }))?;
+    }
+    record_file.flush()?;
+    Ok(complete)
+}
+
+fn json_line<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).expect("Expected the run record to serialize")
+}
+
+///
+/// Opens a csv file to append games to, writing the header only if the file is new or empty.
+pub fn open_history_writer(file_path: &str) -> Result<csv::Writer<File>, csv::Error> {
+    let file = OpenOptions::new().create(true).append(true).open(file_path)?;
+    let is_empty = file.metadata()?.len() == 0;
+    Ok(csv::WriterBuilder::new().has_headers(is_empty).from_writer(file))
+}
+
+///
+/// Appends the rows of one game, flushed so that a stopped run loses no finished game.
+///
+/// # Returns
+///
+/// * `Result<usize, csv::Error>` - The number of rows written.
+pub fn append_history(
+    writer: &mut csv::Writer<File>,
+    history: &GameHistory
+) -> Result<usize, csv::Error> {
+    let observations = history.get_formatted_data();
+    for observation in observations.iter() {
+        writer.serialize(observation)?;
+    }
+    writer.flush()?;
+    Ok(observations.len())
+}
+
+///
+/// Plays `config.games` games across `config.threads` threads and appends them to `file_path`
+/// in the order of their ids as they finish. Game `i` gets the id `i`, games that are already
+/// complete in the file are not played again, so running the same command again resumes a
+/// stopped run. A game is listed in the run record once all of its rows are written.
+///
+/// # Arguments
+///
+/// * `config` - Agents, number of games and threads, opening, reporting and seed.
+/// * `file_path` - The csv file to append to.
+/// * `value_model` - Model of the value based agent, needed if either side is `ValueBased`.
+///
+/// # Returns
+///
+/// * `Result<SelfPlaySummary, SelfPlayError>` - Counts of the games played. `SettingsMismatch`
+///   if the file was written with other settings, `Othello` if a game could not be played, for
+///   example with more than `MAX_RANDOM_PLIES` opening plies, or the error of reading or writing
+///   the files.
+pub fn run_self_play(
+    config: &SelfPlayConfig,
+    file_path: &str,
+    value_model: Option<&Model<DefaultBackend>>
+) -> Result<SelfPlaySummary, SelfPlayError> {
+    let opening_plies = config.opening_plies;
+    if opening_plies > MAX_RANDOM_PLIES {
+        return Err(OthelloError::InvalidPlyRange { min: opening_plies, max: opening_plies }.into());
+    }
+    let recorded = prepare_run(file_path, &RunSettings::from_config(config))?;
+    let pending: Vec<u32> = (0..config.games as u32)
+        .filter(|id| !recorded.contains(id))
+        .collect();
+    let mut summary = SelfPlaySummary {
+        skipped: config.games - pending.len(),
+        ..SelfPlaySummary::default()
+    };
+    if summary.skipped > 0 {
+        println!("Resuming after {} games already in {}", summary.skipped, file_path);
+    }
+    if pending.is_empty() {
+        return Ok(summary);
+    }
+    let mut writer = open_history_writer(file_path)?;
+    let mut record_file = OpenOptions::new().append(true).open(run_record_path(file_path))?;
+    let uses_search = [config.black, config.white].contains(&AgentKind::SearchBased);
+    let table_megabytes = if uses_search { config.table_megabytes } else { 0 };
+    let next_game = AtomicUsize::new(0);
+    let started = Instant::now();
+    thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel::<Result<GameHistory, OthelloError>>();
+        for _ in 0..config.threads.clamp(1, pending.len()) {
+            let sender = sender.clone();
+            let (pending, next_game) = (&pending, &next_game);
+            // models cannot be shared between threads, each thread gets a copy of the weights
+            let value_model = value_model.cloned();
+            scope.spawn(move || {
+                let table = Arc::new(TranspositionTable::with_megabytes(table_megabytes));
+                while let Some(&id) = pending.get(next_game.fetch_add(1, Ordering::Relaxed)) {
+                    let history = play_game(id, config, &table, value_model.as_ref());
+                    // the receiver only hangs up on an error
+                    if sender.send(history).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+        // games that finished before a game with a lower id, written once it is in
+        let mut finished_early = BTreeMap::new();
+        for history in receiver {
+            let history = history?;
+            finished_early.insert(history.id, history);
+            while let Some(history) = pending
+                .get(summary.played)
+                .and_then(|id| finished_early.remove(id))
+            {
+                let rows = append_history(&mut writer, &history)?;
+                let finished_game = FinishedGame { game_id: history.id, rows };
+                writeln!(record_file, "{}", json_line(&finished_game))?;
+                summary.played += 1;
+                match history.agent0_score.cmp(&history.agent1_score) {
+                    std::cmp::Ordering::Greater => summary.black_wins += 1,
+                    std::cmp::Ordering::Less => summary.white_wins += 1,
+                    std::cmp::Ordering::Equal => summary.draws += 1,
+                }
+                let finished = summary.played == pending.len();
+                if summary.played.is_multiple_of(config.report_every.max(1)) || finished {
+                    let seconds = started.elapsed().as_secs_f64();
+                    println!(
+                        "Games: {}/{} ({:.1} per second), black wins {}, white wins {}, draws {}",
+                        summary.skipped + summary.played,
+                        config.games,
+                        (summary.played as f64) / seconds.max(f64::EPSILON),
+                        summary.black_wins,
+                        summary.white_wins,
+                        summary.draws
+                    );
+                }
+            }
+        }
+        Ok(summary)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::{ error::OthelloError, gameplay::random_position::MAX_RANDOM_PLIES };
+
+    use super::{
+        read_run_record,
+        recorded_game_ids,
+        run_record_path,
+        run_self_play,
+        AgentKind,
+        SelfPlayConfig,
+        SelfPlayError,
+    };
+
+    fn remove_run(path: &str) {
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(run_record_path(path));
+    }
+
+    #[test]
+    fn parses_agent_names() {
+        for name in AgentKind::NAMES {
+            assert_eq!(AgentKind::from_name(name).map(|kind| kind.name()), Some(name));
+        }
+        assert_eq!(AgentKind::from_name("human"), None);
+    }
+
+    #[test]
+    fn plays_and_resumes_games() {
+        let path = std::env::temp_dir().join("othello_self_play_test.csv");
+        let path = path.to_str().unwrap();
+        remove_run(path);
+        let mut config = SelfPlayConfig {
+            games: 6,
+            threads: 3,
+            black: AgentKind::Random,
+            white: AgentKind::RuleBased,
+            opening_plies: 4,
+            ..SelfPlayConfig::default()
+        };
+        let summary = run_self_play(&config, path, None).unwrap();
+        assert_eq!(summary.played, 6);
+        assert_eq!(summary.black_wins + summary.white_wins + summary.draws, 6);
+        assert_eq!(recorded_game_ids(path).unwrap(), (0..6).collect());
+
+        config.games = 8;
+        let summary = run_self_play(&config, path, None).unwrap();
+        assert_eq!((summary.skipped, summary.played), (6, 2));
+        assert_eq!(recorded_game_ids(path).unwrap().len(), 8);
+        // a single header, in the format of the store
+        let contents = fs::read_to_string(path).unwrap();
+        let header = "board_string,game_id,winner,next_move_index";
+        assert_eq!(contents.lines().filter(|&line| line == header).count(), 1);
+        assert!(contents.starts_with(header));
+        remove_run(path);
+    }
+
+    #[test]
+    fn refuses_to_resume_with_other_settings() {
+        let path = std::env::temp_dir().join("othello_self_play_settings_test.csv");
+        let path = path.to_str().unwrap();
+        remove_run(path);
+        let mut config = SelfPlayConfig {
+            games: 2,
+            threads: 2,
+            black: AgentKind::Random,
+            white: AgentKind::Random,
+            seed: 3,
+            ..SelfPlayConfig::default()
+        };
+        run_self_play(&config, path, None).unwrap();
+        assert_eq!(read_run_record(path).unwrap().unwrap().settings.seed, 3);
+        config.seed = 4;
+        let error = run_self_play(&config, path, None).err();
+        assert!(matches!(error, Some(SelfPlayError::SettingsMismatch { .. })));
+        config.seed = 3;
+        config.opening_plies = 2;
+        assert!(run_self_play(&config, path, None).is_err());
+
+        // games of unknown settings are not resumed either
+        fs::remove_file(run_record_path(path)).unwrap();
+        let error = run_self_play(&config, path, None).err();
+        assert!(matches!(error, Some(SelfPlayError::MissingRunRecord(_))));
+        remove_run(path);
+    }
+
+    #[test]
+    fn rejects_openings_past_the_last_random_ply() {
+        let path = std::env::temp_dir().join("othello_self_play_opening_test.csv");
+        let path = path.to_str().unwrap();
+        remove_run(path);
+        let config = SelfPlayConfig {
+            games: 1,
+            black: AgentKind::Random,
+            white: AgentKind::Random,
+            opening_plies: MAX_RANDOM_PLIES + 1,
+            ..SelfPlayConfig::default()
+        };
+        let error = run_self_play(&config, path, None).err();
+        let invalid = OthelloError::InvalidPlyRange { min: 60, max: 60 };
+        assert!(matches!(error, Some(SelfPlayError::Othello(error)) if error == invalid));
+        assert!(!std::path::Path::new(path).exists());
+    }
+
+    #[test]
+    fn plays_unfinished_games_again() {
+        let path = std::env::temp_dir().join("othello_self_play_partial_test.csv");
+        let path = path.to_str().unwrap();
+        remove_run(path);
+        let config = SelfPlayConfig {
+            games: 3,
+            threads: 1,
+            black: AgentKind::Random,
+            white: AgentKind::RuleBased,
+            seed: 8,
+            ..SelfPlayConfig::default()
+        };
+        run_self_play(&config, path, None).unwrap();
+        let complete = fs::read_to_string(path).unwrap();
+
+        // a run stopped while writing the last game leaves some of its rows behind
+        let lines: Vec<&str> = complete.lines().collect();
+        let cut = lines.len() - 5;
+        fs::write(path, lines[..cut].join("\n") + "\n").unwrap();
+        let record = fs::read_to_string(run_record_path(path)).unwrap();
+        let record_lines: Vec<&str> = record.lines().collect();
+        fs::write(run_record_path(path), record_lines[..3].join("\n") + "\n").unwrap();
+        assert_eq!(recorded_game_ids(path).unwrap(), (0..2).collect());
+
+        let summary = run_self_play(&config, path, None).unwrap();
+        assert_eq!((summary.skipped, summary.played), (2, 1));
+        assert_eq!(fs::read_to_string(path).unwrap(), complete);
+        remove_run(path);
+    }
+
+    #[test]
+    fn same_seed_writes_the_same_games() {
+        let contents: Vec<String> = [1, 4]
+            .iter()
+            .map(|&threads| {
+                let path = std::env::temp_dir().join(format!("othello_seed_test_{}.csv", threads));
+                let path = path.to_str().unwrap();
+                remove_run(path);
+                let config = SelfPlayConfig {
+                    games: 8,
+                    threads,
+                    black: AgentKind::Random,
+                    white: AgentKind::RuleBased,
+                    opening_plies: 2,
+                    seed: 25,
+                    ..SelfPlayConfig::default()
+                };
+                run_self_play(&config, path, None).unwrap();
+                let contents = fs::read_to_string(path).unwrap();
+                remove_run(path);
+                contents
+            })
+            .collect();
+        assert_eq!(contents[0], contents[1]);
+    }
+}
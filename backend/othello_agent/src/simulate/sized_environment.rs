@@ -0,0 +1,130 @@
+use rl_examples::environment::Environment;
+
+use crate::{
+    error::OthelloError,
+    gameplay::{ board_size::BoardSize, game::Move, rules::Rules, sized_game::SizedGame },
+};
+
+///
+/// Environment for games on boards other than 8x8, e.g. 6x6 for quick experiments. Actions are
+/// piece indices of that board. As in `OthelloEnvironment`, rewards are from black's point of
+/// view and a player without a move passes on their own.
+pub struct SizedOthelloEnvironment {
+    size: BoardSize,
+    game: SizedGame,
+}
+
+impl SizedOthelloEnvironment {
+    pub fn new(size: BoardSize) -> Self {
+        SizedOthelloEnvironment { size, game: SizedGame::new(size) }
+    }
+
+    pub fn get_game(&self) -> &SizedGame {
+        &self.game
+    }
+
+    ///
+    /// Step function that returns an error instead of panicking.
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - The piece index to play for the current player
+    ///
+    /// # Returns
+    ///
+    /// * `Result<f64, OthelloError>` - 1.0 once black has won, -1.0 once white has won and 0.0
+    ///   otherwise, or the error of `SizedGame::try_make_move_at_index`.
+    pub fn try_step(&mut self, action: usize) -> Result<f64, OthelloError> {
+        self.game.try_make_move_at_index(action)?;
+        if !self.game.is_over() && self.game.get_legal_moves() == [Move::Pass] {
+            self.game.make_move(Move::Pass);
+        }
+        match self.game.result().map(|result| result.winner) {
+            Some(Some(0)) => Ok(1.0),
+            Some(Some(_)) => Ok(-1.0),
+            _ => Ok(0.0),
+        }
+    }
+}
+
+impl Environment for SizedOthelloEnvironment {
+    fn reset(&mut self) {
+        self.game = SizedGame::new(self.size);
+    }
+
+    ///
+    /// Plays a move for the current player and returns the reward.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the action is invalid or the game is over, see `try_step`.
+    fn step(&mut self, action: usize) -> f64 {
+        match self.try_step(action) {
+            Ok(reward) => reward,
+            Err(error) => panic!("Invalid action: {}", error),
+        }
+    }
+
+    fn get_state(&self) -> String {
+        self.game.get_board_string()
+    }
+
+    fn get_actions(&self) -> Vec<usize> {
+        self.game
+            .get_valid_moves(self.game.turn)
+            .iter()
+            .map(|position| self.size.index_of(position))
+            .collect()
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.game.is_over()
+    }
+
+    fn get_number_of_possible_actions(&self) -> usize {
+        self.get_actions().len()
+    }
+
+    fn get_number_of_possible_states(&self) -> usize {
+        panic!("Not implemented. Too many possible states")
+    }
+
+    fn get_total_number_of_actions_taken(&self) -> usize {
+        self.game.moves().len()
+    }
+
+    fn all_possible_states(&self) -> Vec<String> {
+        panic!("Not implemented. Too many possible states")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rl_examples::environment::Environment;
+
+    use crate::gameplay::{ board_size::BoardSize, rules::Rules };
+
+    use super::SizedOthelloEnvironment;
+
+    #[test]
+    fn plays_6x6_games_to_the_end() {
+        let mut env = SizedOthelloEnvironment::new(BoardSize::SMALL);
+        assert_eq!(env.get_state().len(), 12);
+        let mut reward = 0.0;
+        while !env.is_terminal() {
+            let action = env.get_actions()[0];
+            reward = env.step(action);
+        }
+        let result = env.get_game().result().unwrap();
+        let expected = match result.winner {
+            Some(0) => 1.0,
+            Some(_) => -1.0,
+            None => 0.0,
+        };
+        assert_eq!(reward, expected);
+
+        env.reset();
+        assert_eq!(env.get_actions(), vec![8, 13, 22, 27]);
+        assert_eq!(env.get_total_number_of_actions_taken(), 0);
+    }
+}
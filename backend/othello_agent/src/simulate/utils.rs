@@ -1,8 +1,21 @@
-pub fn split_csv_dataset(
+use rand::Rng;
+
+///
+/// Splits the rows of a csv file between a train and a test file.
+///
+/// # Arguments
+///
+/// * `path` - The csv file to split.
+/// * `train_path` - Where to write the train rows.
+/// * `test_path` - Where to write the test rows.
+/// * `train_ratio` - Chance of a row going to the train file.
+/// * `rng` - Decides where each row goes, seed it to get the same split again.
+pub fn split_csv_dataset<R: Rng>(
     path: &str,
     train_path: &str,
     test_path: &str,
-    train_ratio: f64
+    train_ratio: f64,
+    rng: &mut R
 ) -> Result<(), Box<dyn std::error::Error>> {
     let mut rdr = csv::Reader::from_path(path)?;
     let mut wtr_train = csv::Writer::from_path(train_path)?;
@@ -12,7 +25,7 @@ pub fn split_csv_dataset(
     wtr_test.write_record(rdr.headers()?.iter())?;
     for result in rdr.records() {
         let record = result?;
-        let rand: f64 = rand::random();
+        let rand: f64 = rng.gen();
         if rand < train_ratio {
             wtr_train.write_record(&record)?;
         } else {
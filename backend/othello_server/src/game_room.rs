@@ -0,0 +1,636 @@
+use othello_agent::{
+    error::OthelloError,
+    gameplay::{ constants::NULL_MOVE_INDEX, game::{ IGame, IPlayer, Move }, position::IPosition },
+    simulate::history::GameHistory,
+};
+use serde::{ Deserialize, Serialize };
+use std::collections::HashMap;
+
+use crate::storage::{ now, record_move, StoredGame };
+
+/// Seconds a room nobody is connected to is kept in memory after the last member left.
+const IDLE_ROOM_TTL: u64 = 60 * 60;
+
+///
+/// Messages clients send over the websocket to play a game. Text that does not parse as one of
+/// these is relayed to the room like before.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Creates a game, with a random id if none is given, and takes the black seat.
+    CreateGame {
+        game_id: Option<String>,
+    },
+    /// Takes the free seat of a game, or watches it if both seats are taken. A player who
+    /// lost their connection gets their seat back with the token of their `GameJoined`.
+    JoinGame {
+        game_id: String,
+        rejoin_token: Option<String>,
+    },
+    MakeMove {
+        move_index: usize,
+    },
+    Pass,
+    Resign,
+    /// Asks for a snapshot of the current game.
+    GetState,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Black,
+    White,
+    Spectator,
+}
+
+impl Role {
+    pub fn player(&self) -> Option<IPlayer> {
+        match self {
+            Role::Black => Some(0),
+            Role::White => Some(1),
+            Role::Spectator => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum GameStatus {
+    WaitingForOpponent,
+    InProgress,
+    /// `winner` is None for a draw.
+    Finished {
+        winner: Option<IPlayer>,
+        resigned: bool,
+    },
+}
+
+///
+/// Everything a client needs to draw the game. The server sends one to every member of the
+/// game after each change.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameSnapshot {
+    pub game_id: String,
+    /// Board encoded with `string_from_board`
+    pub board: String,
+    pub turn: IPlayer,
+    /// Piece index of the last move, None at the start and after a pass.
+    pub last_move: Option<usize>,
+    /// Legal moves of the side to move, empty if it has to pass.
+    pub legal_moves: Vec<usize>,
+    /// Disc counts of black and white
+    pub scores: [i16; 2],
+    #[serde(flatten)]
+    pub status: GameStatus,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// `rejoin_token` is only sent to players, see `ClientMessage::JoinGame`.
+    GameJoined {
+        game_id: String,
+        role: Role,
+        rejoin_token: Option<String>,
+    },
+    /// Sent to the rest of the game when a member leaves or disconnects.
+    PlayerLeft {
+        game_id: String,
+        role: Role,
+    },
+    State(GameSnapshot),
+    Error {
+        message: String,
+    },
+}
+
+impl ServerMessage {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Server messages always serialize")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoomError {
+    NotAPlayer,
+    WaitingForOpponent,
+    GameOver,
+    NotYourTurn,
+    IllegalMove,
+    /// A pass was sent while the player has a legal move.
+    MustMove,
+}
+
+impl RoomError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            RoomError::NotAPlayer => "Spectators cannot play",
+            RoomError::WaitingForOpponent => "The game has not started",
+            RoomError::GameOver => "The game is over",
+            RoomError::NotYourTurn => "It is not your turn",
+            RoomError::IllegalMove => "That move is not legal",
+            RoomError::MustMove => "You can only pass when you have no legal move",
+        }
+    }
+}
+
+/// A taken seat. The token stays with the seat while its user is disconnected.
+struct Seat {
+    uid: Option<usize>,
+    rejoin_token: String,
+}
+
+///
+/// A game owned by the server. Players only send intentions, the room checks them against its
+/// `IGame` and is the only place the board changes.
+pub struct GameRoom {
+    pub game_id: String,
+    game: IGame,
+    /// Black and white, reserved for their players while the game is running
+    seats: [Option<Seat>; 2],
+    spectators: Vec<usize>,
+    last_move: Option<usize>,
+    status: GameStatus,
+    history: GameHistory,
+    created_at: u64,
+    /// Seconds since the unix epoch of the last time a member joined or left
+    updated_at: u64,
+}
+
+impl GameRoom {
+    pub fn new(game_id: String) -> GameRoom {
+        GameRoom {
+            game_id,
+            game: IGame::new(),
+            seats: [None, None],
+            spectators: Vec::new(),
+            last_move: None,
+            status: GameStatus::WaitingForOpponent,
+            history: GameHistory::new(),
+            created_at: now(),
+            updated_at: now(),
+        }
+    }
+
+    ///
+    /// Rebuilds a room from a stored game by replaying its moves, so share links keep working
+    /// after a restart. The seats of a running game are reserved for the stored rejoin tokens,
+    /// so only its players can resume it. A game that has not started gets empty seats.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<GameRoom, OthelloError>` - The room, or the first stored move that is not a
+    ///   legal move of the replayed game.
+    pub fn from_stored(stored: &StoredGame) -> Result<GameRoom, OthelloError> {
+        let mut room = GameRoom::new(stored.game_id.clone());
+        for &move_index in stored.history.move_history.iter() {
+            let player = room.game.turn;
+            let next_move = Move::from_index(move_index).ok_or(OthelloError::IllegalMove {
+                move_index,
+                player,
+            })?;
+            room.game.try_make_move(next_move)?;
+            room.last_move = match next_move {
+                Move::Place(_) => Some(move_index),
+                Move::Pass => None,
+            };
+        }
+        room.history = stored.history.clone();
+        room.created_at = stored.created_at;
+        if stored.finished {
+            room.status = GameStatus::Finished {
+                winner: stored.winner,
+                resigned: stored.resigned,
+            };
+        } else if let [Some(black_token), Some(white_token)] = &stored.rejoin_tokens {
+            room.seats = [black_token, white_token].map(|rejoin_token| {
+                Some(Seat { uid: None, rejoin_token: rejoin_token.clone() })
+            });
+            room.status = GameStatus::InProgress;
+        }
+        Ok(room)
+    }
+
+    /// The game as it is saved in the store.
+    pub fn to_stored(&self) -> StoredGame {
+        let (finished, winner, resigned) = match self.status {
+            GameStatus::Finished { winner, resigned } => (true, winner, resigned),
+            _ => (false, None, false),
+        };
+        // only the seats of a running game are reserved, see `leave`
+        let rejoin_tokens = match self.status {
+            GameStatus::InProgress => [Role::Black, Role::White].map(|role| {
+                self.rejoin_token(role).map(str::to_string)
+            }),
+            _ => [None, None],
+        };
+        StoredGame {
+            game_id: self.game_id.clone(),
+            players: ["human".to_string(), "human".to_string()],
+            history: self.history.clone(),
+            finished,
+            winner,
+            resigned,
+            created_at: self.created_at,
+            updated_at: now(),
+            rejoin_tokens,
+        }
+    }
+
+    ///
+    /// Adds a user to the room, seating them as black, then white, then as a spectator.
+    /// A user who is already in the room keeps their role.
+    ///
+    /// # Arguments
+    ///
+    /// * `uid` - The joining user.
+    /// * `rejoin_token` - Token of a seat the user held before, see `rejoin_token`. It gives
+    ///   the seat back even while the game is running.
+    ///
+    /// # Returns
+    ///
+    /// * `Role` - The seat or spectator role the user has now.
+    pub fn join(&mut self, uid: usize, rejoin_token: Option<&str>) -> Role {
+        self.updated_at = now();
+        let held_seat = self.seats.iter().position(|seat| {
+            seat.as_ref().is_some_and(|seat| Some(seat.rejoin_token.as_str()) == rejoin_token)
+        });
+        if let Some(index) = held_seat {
+            self.spectators.retain(|&spectator| spectator != uid);
+            if let Some(seat) = self.seats[index].as_mut() {
+                seat.uid = Some(uid);
+            }
+            return if index == 0 { Role::Black } else { Role::White };
+        }
+        if let Some(role) = self.role_of(uid) {
+            return role;
+        }
+        let role = match self.seats.iter().position(Option::is_none) {
+            Some(index) => {
+                let rejoin_token = uuid::Uuid::new_v4().to_string();
+                self.seats[index] = Some(Seat { uid: Some(uid), rejoin_token });
+                if index == 0 { Role::Black } else { Role::White }
+            }
+            None => {
+                self.spectators.push(uid);
+                Role::Spectator
+            }
+        };
+        if self.status == GameStatus::WaitingForOpponent && self.seats.iter().all(Option::is_some) {
+            self.status = GameStatus::InProgress;
+        }
+        role
+    }
+
+    ///
+    /// Removes a user. A player's seat stays reserved for their rejoin token while the game
+    /// is running, and is freed before the start and after the finish.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Role>` - The role the user left, None if they were not in the room.
+    pub fn leave(&mut self, uid: usize) -> Option<Role> {
+        let role = self.role_of(uid)?;
+        self.updated_at = now();
+        self.spectators.retain(|&spectator| spectator != uid);
+        if let Some(index) = role.player() {
+            if self.status == GameStatus::InProgress {
+                if let Some(seat) = self.seats[index as usize].as_mut() {
+                    seat.uid = None;
+                }
+            } else {
+                self.seats[index as usize] = None;
+            }
+        }
+        Some(role)
+    }
+
+    /// Token that gives the seat of `role` back to its player after a reconnect.
+    pub fn rejoin_token(&self, role: Role) -> Option<&str> {
+        let seat = self.seats[role.player()? as usize].as_ref()?;
+        Some(seat.rejoin_token.as_str())
+    }
+
+    /// User connected to the seat of a player, None for a free or reserved seat.
+    fn seated(&self, player: usize) -> Option<usize> {
+        self.seats[player].as_ref().and_then(|seat| seat.uid)
+    }
+
+    pub fn role_of(&self, uid: usize) -> Option<Role> {
+        if self.seated(0) == Some(uid) {
+            Some(Role::Black)
+        } else if self.seated(1) == Some(uid) {
+            Some(Role::White)
+        } else if self.spectators.contains(&uid) {
+            Some(Role::Spectator)
+        } else {
+            None
+        }
+    }
+
+    /// Everyone who should receive updates about the game.
+    pub fn members(&self) -> Vec<usize> {
+        (0..2)
+            .filter_map(|player| self.seated(player))
+            .chain(self.spectators.iter().copied())
+            .collect()
+    }
+
+    /// True once nobody is connected and no seat is reserved, so the room can be dropped.
+    pub fn is_empty(&self) -> bool {
+        self.members().is_empty() && self.status != GameStatus::InProgress
+    }
+
+    ///
+    /// True once nobody has been connected for longer than the room is kept in memory. A game
+    /// abandoned while it is running stays in the store, joining it resumes it.
+    pub fn is_abandoned(&self, now: u64) -> bool {
+        self.members().is_empty() && now.saturating_sub(self.updated_at) > IDLE_ROOM_TTL
+    }
+
+    /// Frees the seats of disconnected players once the game is over.
+    fn free_reserved_seats(&mut self) {
+        for seat in self.seats.iter_mut() {
+            if seat.as_ref().is_some_and(|seat| seat.uid.is_none()) {
+                *seat = None;
+            }
+        }
+    }
+
+    fn check_can_play(&self, uid: usize) -> Result<IPlayer, RoomError> {
+        let player = self
+            .role_of(uid)
+            .and_then(|role| role.player())
+            .ok_or(RoomError::NotAPlayer)?;
+        match self.status {
+            GameStatus::WaitingForOpponent => Err(RoomError::WaitingForOpponent),
+            GameStatus::Finished { .. } => Err(RoomError::GameOver),
+            GameStatus::InProgress if self.game.turn != player => Err(RoomError::NotYourTurn),
+            GameStatus::InProgress => Ok(player),
+        }
+    }
+
+    /// Ends the game once neither player can move.
+    fn update_status(&mut self) {
+        if let Some(result) = self.game.result() {
+            self.status = GameStatus::Finished { winner: result.winner, resigned: false };
+            self.free_reserved_seats();
+        }
+    }
+
+    pub fn make_move(&mut self, uid: usize, move_index: usize) -> Result<(), RoomError> {
+        self.check_can_play(uid)?;
+        self.game.try_make_move_at_index(move_index).map_err(|_| RoomError::IllegalMove)?;
+        record_move(&mut self.history, &self.game, move_index);
+        self.last_move = Some(move_index);
+        self.update_status();
+        Ok(())
+    }
+
+    pub fn pass(&mut self, uid: usize) -> Result<(), RoomError> {
+        self.check_can_play(uid)?;
+        self.game.try_make_move(Move::Pass).map_err(|_| RoomError::MustMove)?;
+        record_move(&mut self.history, &self.game, NULL_MOVE_INDEX);
+        self.last_move = None;
+        Ok(())
+    }
+
+    pub fn resign(&mut self, uid: usize) -> Result<(), RoomError> {
+        let player = self
+            .role_of(uid)
+            .and_then(|role| role.player())
+            .ok_or(RoomError::NotAPlayer)?;
+        if let GameStatus::Finished { .. } = self.status {
+            return Err(RoomError::GameOver);
+        }
+        self.status = GameStatus::Finished { winner: Some(1 - player), resigned: true };
+        self.free_reserved_seats();
+        Ok(())
+    }
+
+    pub fn snapshot(&self) -> GameSnapshot {
+        let legal_moves = match self.status {
+            GameStatus::Finished { .. } => Vec::new(),
+            _ =>
+                self.game
+                    .get_valid_moves(self.game.turn)
+                    .iter()
+                    .map(IPosition::to_piece_index)
+                    .collect(),
+        };
+        GameSnapshot {
+            game_id: self.game_id.clone(),
+            board: self.game.get_board_string(),
+            turn: self.game.turn,
+            last_move: self.last_move,
+            legal_moves,
+            scores: self.game.scores(),
+            status: self.status.clone(),
+        }
+    }
+}
+
+///
+/// Drops the rooms nobody has been connected to for a while, they stay in the store.
+///
+/// # Arguments
+///
+/// * `rooms` - The rooms of the server.
+/// * `now` - Seconds since the unix epoch.
+pub fn remove_abandoned_rooms(rooms: &mut HashMap<String, GameRoom>, now: u64) {
+    rooms.retain(|_, room| !room.is_abandoned(now));
+}
+
+#[cfg(test)]
+mod tests {
+    use othello_agent::error::OthelloError;
+
+    use std::collections::HashMap;
+
+    use super::{
+        remove_abandoned_rooms,
+        ClientMessage,
+        GameRoom,
+        GameStatus,
+        Role,
+        RoomError,
+        ServerMessage,
+        IDLE_ROOM_TTL,
+    };
+
+    fn started_room() -> GameRoom {
+        let mut room = GameRoom::new("test".to_string());
+        assert_eq!(room.join(1, None), Role::Black);
+        assert_eq!(room.join(2, None), Role::White);
+        assert_eq!(room.join(3, None), Role::Spectator);
+        room
+    }
+
+    #[test]
+    fn parses_client_messages() {
+        let message: ClientMessage = serde_json
+            ::from_str(r#"{"type": "make_move", "move_index": 19}"#)
+            .unwrap();
+        assert_eq!(message, ClientMessage::MakeMove { move_index: 19 });
+        let message: ClientMessage = serde_json::from_str(r#"{"type": "pass"}"#).unwrap();
+        assert_eq!(message, ClientMessage::Pass);
+        // the legacy realtime move format is not a protocol message
+        let legacy = r#"{"move_index": 19, "player": 0}"#;
+        assert!(serde_json::from_str::<ClientMessage>(legacy).is_err());
+        // the rejoin token can be left out
+        let message: ClientMessage = serde_json
+            ::from_str(r#"{"type": "join_game", "game_id": "abc"}"#)
+            .unwrap();
+        assert_eq!(message, ClientMessage::JoinGame {
+            game_id: "abc".to_string(),
+            rejoin_token: None,
+        });
+    }
+
+    #[test]
+    fn validates_and_applies_moves() {
+        let mut room = GameRoom::new("test".to_string());
+        room.join(1, None);
+        assert_eq!(room.make_move(1, 19), Err(RoomError::WaitingForOpponent));
+        room.join(2, None);
+        assert_eq!(room.snapshot().legal_moves, vec![19, 26, 37, 44]);
+        assert_eq!(room.make_move(2, 19), Err(RoomError::NotYourTurn));
+        assert_eq!(room.make_move(1, 0), Err(RoomError::IllegalMove));
+        assert_eq!(room.pass(1), Err(RoomError::MustMove));
+        room.join(3, None);
+        assert_eq!(room.make_move(3, 19), Err(RoomError::NotAPlayer));
+        assert_eq!(room.make_move(1, 19), Ok(()));
+
+        let snapshot = room.snapshot();
+        assert_eq!(snapshot.turn, 1);
+        assert_eq!(snapshot.last_move, Some(19));
+        assert_eq!(snapshot.scores, [4, 1]);
+        assert_eq!(snapshot.status, GameStatus::InProgress);
+        let json = ServerMessage::State(snapshot.clone()).to_json();
+        assert!(json.contains(r#""type":"state""#));
+        assert!(json.contains(r#""status":"in_progress""#));
+        let parsed = serde_json::from_str::<ServerMessage>(&json).unwrap();
+        assert_eq!(parsed, ServerMessage::State(snapshot));
+    }
+
+    #[test]
+    fn resigning_ends_the_game() {
+        let mut room = started_room();
+        assert_eq!(room.resign(3), Err(RoomError::NotAPlayer));
+        assert_eq!(room.resign(2), Ok(()));
+        let finished = GameStatus::Finished { winner: Some(0), resigned: true };
+        assert_eq!(room.snapshot().status, finished);
+        assert!(room.snapshot().legal_moves.is_empty());
+        assert_eq!(room.make_move(1, 19), Err(RoomError::GameOver));
+    }
+
+    #[test]
+    fn finishes_when_nobody_can_move() {
+        let mut room = started_room();
+        // black wipes out white in nine moves
+        for (ply, move_index) in [19, 18, 17, 11, 4, 43, 51, 20, 29].into_iter().enumerate() {
+            let uid = if ply % 2 == 0 { 1 } else { 2 };
+            assert_eq!(room.make_move(uid, move_index), Ok(()));
+        }
+        let snapshot = room.snapshot();
+        // the empty squares go to black
+        assert_eq!(snapshot.scores, [64, 0]);
+        assert_eq!(snapshot.status, GameStatus::Finished { winner: Some(0), resigned: false });
+
+        let stored = room.to_stored();
+        assert!(stored.finished);
+        assert_eq!(stored.winner, Some(0));
+        assert_eq!(stored.history.total_moves, 9);
+        assert_eq!((stored.history.agent0_score, stored.history.agent1_score), (64, 0));
+    }
+
+    #[test]
+    fn restores_stored_games() {
+        let mut room = started_room();
+        room.make_move(1, 19).unwrap();
+        room.make_move(2, 18).unwrap();
+        let mut restored = GameRoom::from_stored(&room.to_stored()).ok().unwrap();
+        assert_eq!(restored.snapshot(), room.snapshot());
+        // the seats wait for their players
+        assert_eq!(restored.join(4, None), Role::Spectator);
+        let black_token = room.rejoin_token(Role::Black).unwrap();
+        let white_token = room.rejoin_token(Role::White).unwrap();
+        assert_eq!(restored.join(5, Some(black_token)), Role::Black);
+        assert_eq!(restored.join(6, Some(white_token)), Role::White);
+        assert_eq!(restored.make_move(5, 17), Ok(()));
+
+        // a game that has not started is open to anyone
+        let mut waiting = GameRoom::new("waiting".to_string());
+        waiting.join(1, None);
+        let mut restored = GameRoom::from_stored(&waiting.to_stored()).ok().unwrap();
+        assert_eq!(restored.snapshot().status, GameStatus::WaitingForOpponent);
+        assert_eq!(restored.join(4, None), Role::Black);
+
+        // a corrupt row is an error instead of a panic
+        let mut stored = room.to_stored();
+        stored.history.move_history.push(0);
+        let error = OthelloError::IllegalMove { move_index: 0, player: 0 };
+        assert_eq!(GameRoom::from_stored(&stored).err(), Some(error));
+        stored.history.move_history = vec![19, 64];
+        let error = OthelloError::IllegalMove { move_index: 64, player: 1 };
+        assert_eq!(GameRoom::from_stored(&stored).err(), Some(error));
+    }
+
+    #[test]
+    fn seats_are_freed_before_the_start() {
+        let mut room = GameRoom::new("test".to_string());
+        room.join(1, None);
+        assert_eq!(room.leave(1), Some(Role::Black));
+        assert_eq!(room.leave(1), None);
+        assert!(room.is_empty());
+        assert_eq!(room.join(2, None), Role::Black);
+        // joining twice keeps the existing role
+        assert_eq!(room.join(2, None), Role::Black);
+    }
+
+    #[test]
+    fn seats_are_reserved_during_the_game() {
+        let mut room = started_room();
+        let token = room.rejoin_token(Role::White).unwrap().to_string();
+        assert_eq!(room.rejoin_token(Role::Spectator), None);
+        assert_eq!(room.leave(2), Some(Role::White));
+        assert_eq!(room.members(), vec![1, 3]);
+        assert!(!room.is_empty());
+        // nobody else can take the seat, not even a spectator
+        assert_eq!(room.join(4, None), Role::Spectator);
+        assert_eq!(room.join(3, Some("guess")), Role::Spectator);
+        // the player gets it back on a new connection
+        assert_eq!(room.join(5, Some(&token)), Role::White);
+        room.make_move(1, 19).unwrap();
+        assert_eq!(room.make_move(5, 18), Ok(()));
+
+        // once the game is over the seat is free again
+        room.leave(5);
+        room.resign(1).unwrap();
+        assert_eq!(room.join(6, None), Role::White);
+        room.leave(6);
+        for uid in [1, 3, 4] {
+            room.leave(uid);
+        }
+        assert!(room.is_empty());
+    }
+
+    #[test]
+    fn drops_abandoned_rooms() {
+        let mut rooms = HashMap::new();
+        let mut abandoned = started_room();
+        for uid in [1, 2, 3] {
+            abandoned.leave(uid);
+        }
+        let left = abandoned.updated_at;
+        rooms.insert("abandoned".to_string(), abandoned);
+        rooms.insert("watched".to_string(), started_room());
+        remove_abandoned_rooms(&mut rooms, left + IDLE_ROOM_TTL);
+        assert_eq!(rooms.len(), 2);
+        // a room with someone connected is kept however long it is idle
+        remove_abandoned_rooms(&mut rooms, left + IDLE_ROOM_TTL + 1);
+        assert!(rooms.contains_key("watched"));
+        assert!(!rooms.contains_key("abandoned"));
+    }
+}
@@ -1,6 +1,8 @@
 use actix_cors::Cors;
 use actix_web::{
+    error::ErrorBadRequest,
     get,
+    http::header,
     middleware,
     post,
     web::{ self, ServiceConfig },
@@ -13,10 +15,10 @@ use futures::StreamExt;
 use rl_examples::agents::agent::Agent;
 use othello_agent::{
     agent::{ rule_based::RuleAgent },
+    error::check_player,
     gameplay::{
         constants::CODE_CHARS,
-        encoding::{ board_from_string, create_code_char_hash },
-        position::IPosition,
+        encoding::{ create_code_char_hash, try_board_from_string },
         game::{ IBoard, IPlayer },
     },
 };
@@ -25,7 +27,37 @@ use shuttle_actix_web::ShuttleActixWeb;
 use std::{ collections::HashMap, sync::{ atomic::AtomicUsize, Arc } };
 use tokio::sync::{ mpsc::{ self, UnboundedReceiver, UnboundedSender }, RwLock };
 
-type AppState = (mpsc::UnboundedSender<WsState>, Users, Rooms);
+mod game_room;
+mod next_move;
+mod sessions;
+mod storage;
+
+use game_room::{ remove_abandoned_rooms, ClientMessage, GameRoom, ServerMessage };
+use next_move::{
+    move_index_from_action,
+    next_move_for_agent,
+    next_move_value_based,
+    SharedValueModel,
+};
+use sessions::{ create_game, get_game, make_move, take_back, Sessions };
+use storage::{
+    game_transcript,
+    now,
+    recent_games,
+    spawn_saver,
+    stored_game,
+    GameStore,
+    SaveSender,
+};
+
+type AppState = (
+    mpsc::UnboundedSender<WsState>,
+    Users,
+    Rooms,
+    Games,
+    web::Data<GameStore>,
+    SaveSender,
+);
 
 #[derive(Debug, Clone)]
 enum WsState {
@@ -42,6 +74,7 @@ struct Msg {
 
 type Users = Arc<RwLock<HashMap<usize, UnboundedSender<Message>>>>;
 type Rooms = Arc<RwLock<HashMap<String, Vec<usize>>>>;
+type Games = Arc<RwLock<HashMap<String, GameRoom>>>;
 
 static NEXT_USERID: AtomicUsize = AtomicUsize::new(1);
 
@@ -50,7 +83,10 @@ async fn echo_handler(
     mut msg_stream: actix_ws::MessageStream,
     tx: mpsc::UnboundedSender<WsState>,
     users: Users,
-    rooms: Rooms
+    rooms: Rooms,
+    games: Games,
+    store: web::Data<GameStore>,
+    saver: SaveSender
 ) {
     // generate a unique id for this user
     let uid = NEXT_USERID.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
@@ -70,7 +106,11 @@ async fn echo_handler(
                     continue;
                 }
             };
-            session_2.text(msg_txt).await.expect("Failed to send message");
+            // the socket is closed, its handler removes the user once the stream ends
+            if let Err(error) = session_2.text(msg_txt).await {
+                println!("Failed to send message to user {}: {}", uid, error);
+                break;
+            }
         }
     });
     while let Some(Ok(msg)) = msg_stream.next().await {
@@ -83,7 +123,10 @@ async fn echo_handler(
             Message::Text(s) => {
                 // first parse the message too see if it incudes a command
                 let msg = s.trim();
-                if msg.starts_with('/') {
+                // typed game messages are handled by the server, everything else is relayed
+                if let Ok(game_message) = serde_json::from_str::<ClientMessage>(msg) {
+                    handle_game_message(game_message, uid, &users, &games, &store, &saver).await;
+                } else if msg.starts_with('/') {
                     let v: Vec<&str> = msg.splitn(2, ' ').collect();
                     match v[0] {
                         "/join" => {
@@ -154,11 +197,224 @@ async fn echo_handler(
     }
     // broadcast that the user has left
     broadcast_msg(format!("Someone disconnected"), &users, &rooms, uid, vec![uid]).await;
+    leave_games(uid, &users, &games).await;
     // remove user
     users.write().await.remove(&uid);
     let _ = session.close(None).await;
 }
 
+/// Sends a message to the given users.
+async fn send_to(users: &Users, uids: &[usize], msg: &ServerMessage) {
+    let text = msg.to_json();
+    for uid in uids {
+        if let Some(tx) = users.read().await.get(uid) {
+            // a closed socket is cleaned up by its own handler, the others still get the message
+            if let Err(error) = tx.send(Message::Text(text.clone().into())) {
+                println!("Failed to send message to user {}: {}", uid, error);
+            }
+        }
+    }
+}
+
+/// Finds the game the user plays in or watches.
+fn game_id_of(games: &HashMap<String, GameRoom>, uid: usize) -> Option<String> {
+    games
+        .values()
+        .find(|game| game.role_of(uid).is_some())
+        .map(|game| game.game_id.clone())
+}
+
+/// Loads a stored game as a room off the async workers, None if it is missing or broken.
+async fn restore_room(store: &web::Data<GameStore>, game_id: &str) -> Option<GameRoom> {
+    let store = store.clone();
+    let id = game_id.to_string();
+    let stored = match web::block(move || store.get(&id)).await {
+        Ok(Ok(stored)) => stored?,
+        Ok(Err(error)) => {
+            println!("Failed to load game {}: {}", game_id, error);
+            return None;
+        }
+        Err(error) => {
+            println!("Failed to load game {}: {}", game_id, error);
+            return None;
+        }
+    };
+    // a game that cannot be replayed is treated as missing
+    match GameRoom::from_stored(&stored) {
+        Ok(room) => Some(room),
+        Err(error) => {
+            println!("Failed to restore game {}: {}", game_id, error);
+            None
+        }
+    }
+}
+
+///
+/// Creates a room and stores it right away, so its id cannot be taken by another game.
+///
+/// # Returns
+///
+/// * `Option<GameRoom>` - The room, None if a stored game already has the id.
+async fn create_room(store: &web::Data<GameStore>, game_id: Option<String>) -> Option<GameRoom> {
+    let room = GameRoom::new(game_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string()));
+    let store = store.clone();
+    let stored = room.to_stored();
+    match web::block(move || store.insert(&stored)).await {
+        Ok(Ok(inserted)) => inserted.then_some(room),
+        // a broken database does not stop play, the room is checked against memory only
+        Ok(Err(error)) => {
+            println!("Failed to store game {}: {}", room.game_id, error);
+            Some(room)
+        }
+        Err(error) => {
+            println!("Failed to store game {}: {}", room.game_id, error);
+            Some(room)
+        }
+    }
+}
+
+async fn handle_game_message(
+    msg: ClientMessage,
+    uid: usize,
+    users: &Users,
+    games: &Games,
+    store: &web::Data<GameStore>,
+    saver: &SaveSender
+) {
+    // the store is read and written before the games are locked
+    let created = match &msg {
+        ClientMessage::CreateGame { game_id } => create_room(store, game_id.clone()).await,
+        _ => None,
+    };
+    // games that are no longer in memory, e.g. after a restart, are resumed from the store
+    let restored = match &msg {
+        ClientMessage::JoinGame { game_id, .. } if !games.read().await.contains_key(game_id) => {
+            restore_room(store, game_id).await
+        }
+        _ => None,
+    };
+    let mut games_guard = games.write().await;
+    // new and resumed games are the only thing that grows the map, so clean up before adding one
+    if created.is_some() || restored.is_some() {
+        remove_abandoned_rooms(&mut games_guard, now());
+    }
+    let current_game_id = game_id_of(&games_guard, uid);
+    let game_id = match &msg {
+        ClientMessage::CreateGame { .. } => {
+            // the id of a stored game or of one in memory is taken
+            let Some(room) = created.filter(|room| !games_guard.contains_key(&room.game_id)) else {
+                let error = ServerMessage::Error { message: "Game already exists".to_string() };
+                drop(games_guard);
+                send_to(users, &[uid], &error).await;
+                return;
+            };
+            let game_id = room.game_id.clone();
+            games_guard.insert(game_id.clone(), room);
+            game_id
+        }
+        ClientMessage::JoinGame { game_id, .. } => {
+            // another message may have restored the game while it was loaded
+            if let Some(room) = restored {
+                games_guard.entry(game_id.clone()).or_insert(room);
+            }
+            game_id.clone()
+        }
+        _ => {
+            let Some(game_id) = current_game_id.clone() else {
+                let error = ServerMessage::Error { message: "Join a game first".to_string() };
+                drop(games_guard);
+                send_to(users, &[uid], &error).await;
+                return;
+            };
+            game_id
+        }
+    };
+    let Some(game) = games_guard.get_mut(&game_id) else {
+        let error = ServerMessage::Error { message: format!("Game {} does not exist", game_id) };
+        drop(games_guard);
+        send_to(users, &[uid], &error).await;
+        return;
+    };
+    let result = match msg {
+        ClientMessage::CreateGame { .. } => {
+            join_game(game, uid, None, users).await;
+            Ok(())
+        }
+        ClientMessage::JoinGame { rejoin_token, .. } => {
+            join_game(game, uid, rejoin_token.as_deref(), users).await;
+            Ok(())
+        }
+        ClientMessage::MakeMove { move_index } => game.make_move(uid, move_index),
+        ClientMessage::Pass => game.pass(uid),
+        ClientMessage::Resign => game.resign(uid),
+        ClientMessage::GetState => {
+            send_to(users, &[uid], &ServerMessage::State(game.snapshot())).await;
+            return;
+        }
+    };
+    match result {
+        Ok(()) => {
+            // saved in the order of the changes, the write itself happens outside the lock
+            if let Err(error) = saver.send(game.to_stored()) {
+                println!("Failed to queue game {} for saving: {}", game_id, error);
+            }
+            let members = game.members();
+            let state = ServerMessage::State(game.snapshot());
+            send_to(users, &members, &state).await;
+        }
+        Err(error) => {
+            let error = ServerMessage::Error { message: error.message().to_string() };
+            send_to(users, &[uid], &error).await;
+        }
+    }
+    // a user is in at most one game, leave the previous one after switching
+    if let Some(previous_game_id) = current_game_id.filter(|previous| *previous != game_id) {
+        leave_game(&mut games_guard, &previous_game_id, uid, users).await;
+    }
+}
+
+/// Seats a user and sends them their role, with the token to rejoin if they play.
+async fn join_game(game: &mut GameRoom, uid: usize, rejoin_token: Option<&str>, users: &Users) {
+    let role = game.join(uid, rejoin_token);
+    let joined = ServerMessage::GameJoined {
+        game_id: game.game_id.clone(),
+        role,
+        rejoin_token: game.rejoin_token(role).map(str::to_string),
+    };
+    send_to(users, &[uid], &joined).await;
+}
+
+/// Removes a user from a game, drops the game once it is empty and tells the others.
+async fn leave_game(
+    games: &mut HashMap<String, GameRoom>,
+    game_id: &str,
+    uid: usize,
+    users: &Users
+) {
+    let Some(game) = games.get_mut(game_id) else {
+        return;
+    };
+    let Some(role) = game.leave(uid) else {
+        return;
+    };
+    if game.is_empty() {
+        games.remove(game_id);
+        return;
+    }
+    let members = game.members();
+    let left = ServerMessage::PlayerLeft { game_id: game_id.to_string(), role };
+    send_to(users, &members, &left).await;
+    send_to(users, &members, &ServerMessage::State(game.snapshot())).await;
+}
+
+/// Removes a disconnected user from their game and tells the others.
+async fn leave_games(uid: usize, users: &Users, games: &Games) {
+    let mut games_guard = games.write().await;
+    if let Some(game_id) = game_id_of(&games_guard, uid) {
+        leave_game(&mut games_guard, &game_id, uid, users).await;
+    }
+}
+
 async fn broadcast_msg(
     msg: String,
     users: &Users,
@@ -184,7 +440,10 @@ async fn broadcast_msg(
                 continue;
             }
             if let Some(tx) = users.read().await.get(uid) {
-                tx.send(Message::Text(msg.clone().into())).expect("Failed to send message");
+                // like in send_to, a closed socket is cleaned up by its own handler
+                if let Err(error) = tx.send(Message::Text(msg.clone().into())) {
+                    println!("Failed to send message to user {}: {}", uid, error);
+                }
             }
         }
     }
@@ -210,6 +469,15 @@ async fn websocket(
     let rooms_state = app_state.2.clone();
     let rooms_state2 = rooms_state.clone();
 
+    // games state... used for keeping track of the games played over the websocket
+    let games_state = app_state.3.clone();
+
+    // store state... used for resuming the games played over the websocket
+    let store_state = app_state.4.clone();
+
+    // saver state... used for saving the games played over the websocket
+    let saver_state = app_state.5.clone();
+
     // send connected state
     if let Err(e) = tx_ws_state.send(WsState::Connected) {
         println!("Failed to send connected state: {e:?}");
@@ -217,7 +485,16 @@ async fn websocket(
 
     // echo handler
     actix_web::rt::spawn(
-        echo_handler(session, msg_stream, tx_ws_state2, users_state2, rooms_state2)
+        echo_handler(
+            session,
+            msg_stream,
+            tx_ws_state2,
+            users_state2,
+            rooms_state2,
+            games_state,
+            store_state,
+            saver_state
+        )
     );
     Ok(response)
 }
@@ -248,20 +525,12 @@ async fn next_move_rule_based(
 ) -> Result<web::Json<MoveResponse>, actix_web::Error> {
     let (board_str, player) = path.into_inner();
     let hash_map = create_code_char_hash(CODE_CHARS);
-    let board: IBoard = board_from_string(&board_str, &hash_map);
+    let board: IBoard = try_board_from_string(&board_str, &hash_map).map_err(ErrorBadRequest)?;
+    let player = check_player(player).map_err(ErrorBadRequest)?;
     let mut agent = RuleAgent::new(player, board);
     let new_action = agent.select_action();
-    let move_position: Option<IPosition> = IPosition::position_from_piece_index(new_action as i8);
-    if move_position.is_none() {
-        return Ok(
-            web::Json({ MoveResponse {
-                    move_index: -2,
-                } })
-        );
-    }
-    let move_index: i8 = move_position.unwrap().to_piece_index() as i8;
     let response = MoveResponse {
-        move_index,
+        move_index: move_index_from_action(new_action),
     };
     Ok(web::Json(response))
 }
@@ -296,15 +565,24 @@ async fn actix_web() -> ShuttleActixWeb<impl FnOnce(&mut ServiceConfig) + Send +
 
     let users = Users::default();
     let rooms = Rooms::default();
+    let games = Games::default();
+    // finished and running games are kept on disk so they survive restarts
+    let store = web::Data::new(GameStore::open_default().expect("Failed to open the game store"));
+
+    let saver = spawn_saver(store.clone());
 
-    let app_state = web::Data::new((tx_ws_state, users, rooms));
+    let app_state = web::Data::new((tx_ws_state, users, rooms, games, store.clone(), saver));
+    // load the value model once, every request shares it
+    let value_model = web::Data::new(SharedValueModel::load());
+    let sessions = web::Data::new(Sessions::default());
 
     let config = move |cfg: &mut ServiceConfig| {
         let cors = Cors::default()
             .allowed_origin("http://localhost:3000")
             .allowed_origin("https://othelloverse.com")
             .allowed_origin("https://www.othelloverse.com")
-            .allowed_methods(vec!["GET", "POST"]);
+            .allowed_methods(vec!["GET", "POST"])
+            .allowed_header(header::CONTENT_TYPE);
         cfg.service(
             web
                 ::scope("/api")
@@ -312,7 +590,19 @@ async fn actix_web() -> ShuttleActixWeb<impl FnOnce(&mut ServiceConfig) + Send +
                 .service(web::resource("/ws").app_data(app_state).route(web::get().to(websocket)))
                 .service(hello)
                 .service(echo)
+                .app_data(value_model)
+                .app_data(sessions)
+                .app_data(store)
                 .service(next_move_rule_based)
+                .service(next_move_value_based)
+                .service(next_move_for_agent)
+                .service(create_game)
+                .service(get_game)
+                .service(make_move)
+                .service(take_back)
+                .service(recent_games)
+                .service(game_transcript)
+                .service(stored_game)
                 .route("/hey", web::get().to(manual_hello))
                 .wrap(middleware::NormalizePath::trim())
         );
@@ -0,0 +1,304 @@
+use std::sync::{ Arc, Mutex };
+
+use actix_web::{ get, web, HttpResponse };
+use othello_agent::{
+    agent::{
+        mcts::MctsAgent,
+        rule_based::RuleAgent,
+        search_based::SearchAgent,
+        value_based::ValueAgent,
+    },
+    backend::{ default_device, DefaultBackend },
+    error::{ check_player, OthelloError },
+    gameplay::{
+        bitboard::{ indices_from_mask, Bitboard },
+        constants::CODE_CHARS,
+        encoding::{ create_code_char_hash, try_board_from_string },
+        game::{ IBoard, IPlayer, Move },
+    },
+    model::model::Model,
+};
+use rl_examples::agents::agent::Agent;
+use serde::Serialize;
+
+/// Move index sent to clients for a pass, see `wire_move_index`.
+pub const PASS_MOVE_INDEX: i8 = -2;
+
+/// Agents that can be asked for a move.
+pub const AGENT_NAMES: [&str; 4] = ["rule_based", "search_based", "mcts", "value_based"];
+
+///
+/// Value model loaded once at startup and shared by every request. None if no trained model
+/// was found, in which case the value based agent is unavailable.
+#[derive(Clone)]
+pub struct SharedValueModel(pub Option<Arc<Mutex<Model<DefaultBackend>>>>);
+
+impl SharedValueModel {
+    pub fn load() -> Self {
+        let model = ValueAgent::<DefaultBackend>::load_value_model(&default_device());
+        if model.is_none() {
+            println!("No value model found, the value based agent is disabled");
+        }
+        SharedValueModel(model.map(|model| Arc::new(Mutex::new(model))))
+    }
+}
+
+#[derive(Serialize)]
+pub struct OutcomeProbabilities {
+    pub black_wins: f32,
+    pub white_wins: f32,
+    pub draw: f32,
+}
+
+#[derive(Serialize)]
+pub struct AgentMoveResponse {
+    pub agent: String,
+    pub move_index: i8,
+    /// Predicted outcome after the chosen move, only set by agents that estimate it.
+    pub probabilities: Option<OutcomeProbabilities>,
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum NextMoveError {
+    UnknownAgent(String),
+    /// The value based agent was requested but no trained model was loaded.
+    ModelUnavailable,
+    /// The board or player sent by the client is not valid.
+    InvalidInput(OthelloError),
+}
+
+impl NextMoveError {
+    /// The error as sent to clients, with the same status on every endpoint.
+    pub fn to_response(&self) -> HttpResponse {
+        match self {
+            NextMoveError::UnknownAgent(agent) => {
+                error_response(HttpResponse::NotFound(), format!("Unknown agent: {}", agent))
+            }
+            NextMoveError::ModelUnavailable => {
+                error_response(
+                    HttpResponse::ServiceUnavailable(),
+                    "The value based agent has no trained model".to_string()
+                )
+            }
+            NextMoveError::InvalidInput(error) => {
+                error_response(HttpResponse::BadRequest(), error.to_string())
+            }
+        }
+    }
+}
+
+fn error_response(mut response: actix_web::HttpResponseBuilder, error: String) -> HttpResponse {
+    response.json(ErrorResponse { error })
+}
+
+///
+/// The move index the next move endpoints send for a move: the piece index of a placement, or
+/// `PASS_MOVE_INDEX` for a pass. Clients predate `NULL_MOVE_INDEX`, so a pass is not sent as
+/// 199.
+pub fn wire_move_index(played: Move) -> i8 {
+    match played {
+        Move::Place(position) => position.to_piece_index() as i8,
+        Move::Pass => PASS_MOVE_INDEX,
+    }
+}
+
+///
+/// Converts an agent action into the move index sent to clients. Agents return
+/// `NULL_MOVE_INDEX` when they have to pass.
+pub fn move_index_from_action(action: usize) -> i8 {
+    wire_move_index(Move::from_index(action).unwrap_or(Move::Pass))
+}
+
+///
+/// Picks a move for the side to move.
+///
+/// # Arguments
+///
+/// * `agent` - One of `rule_based`, `search_based`, `mcts` or `value_based`.
+/// * `board` - The current board.
+/// * `player` - The side to move.
+/// * `value_model` - Model used by the value based agent.
+///
+/// # Returns
+///
+/// * `Result<AgentMoveResponse, NextMoveError>` - The move, or why no move could be chosen.
+pub fn select_move(
+    agent: &str,
+    board: IBoard,
+    player: IPlayer,
+    value_model: &SharedValueModel
+) -> Result<AgentMoveResponse, NextMoveError> {
+    check_player(player).map_err(NextMoveError::InvalidInput)?;
+    let mut probabilities = None;
+    let action = match agent {
+        "rule_based" => RuleAgent::new(player, board).select_action(),
+        "search_based" => SearchAgent::new(player, board).select_action(),
+        "mcts" => MctsAgent::new(player, board).select_action(),
+        "value_based" => {
+            let Some(model) = value_model.0.as_ref() else {
+                return Err(NextMoveError::ModelUnavailable);
+            };
+            let legal_moves = Bitboard::from_board(board).legal_moves(player);
+            let possible_moves = indices_from_mask(legal_moves);
+            if possible_moves.is_empty() {
+                return Ok(AgentMoveResponse {
+                    agent: agent.to_string(),
+                    move_index: wire_move_index(Move::Pass),
+                    probabilities: None,
+                });
+            }
+            // the model is cheap to clone, the weights are shared
+            let model = model.lock().expect("Value model lock poisoned").clone();
+            let mut value_agent = ValueAgent::with_model(player, board, model, default_device());
+            value_agent.update_possible_move_indices(possible_moves);
+            let action = value_agent.select_action();
+            let (black_wins, white_wins, draw) = value_agent.get_outcome_probabilities();
+            probabilities = Some(OutcomeProbabilities { black_wins, white_wins, draw });
+            action
+        }
+        _ => {
+            return Err(NextMoveError::UnknownAgent(agent.to_string()));
+        }
+    };
+    Ok(AgentMoveResponse {
+        agent: agent.to_string(),
+        move_index: move_index_from_action(action),
+        probabilities,
+    })
+}
+
+async fn next_move_response(
+    agent: String,
+    board_str: String,
+    player: IPlayer,
+    value_model: web::Data<SharedValueModel>
+) -> HttpResponse {
+    let hash_map = create_code_char_hash(CODE_CHARS);
+    let board: IBoard = match try_board_from_string(&board_str, &hash_map) {
+        Ok(board) => board,
+        Err(error) => {
+            return NextMoveError::InvalidInput(error).to_response();
+        }
+    };
+    let value_model = value_model.get_ref().clone();
+    // model inference and search are cpu bound, keep them off the async workers
+    let result = web::block(move || select_move(&agent, board, player, &value_model)).await;
+    match result {
+        Ok(Ok(response)) => HttpResponse::Ok().json(response),
+        Ok(Err(error)) => error.to_response(),
+        Err(error) => error_response(HttpResponse::InternalServerError(), error.to_string()),
+    }
+}
+
+#[get("/next_move/value_based/{board_str}/{player}")]
+pub async fn next_move_value_based(
+    path: web::Path<(String, IPlayer)>,
+    value_model: web::Data<SharedValueModel>
+) -> HttpResponse {
+    let (board_str, player) = path.into_inner();
+    next_move_response("value_based".to_string(), board_str, player, value_model).await
+}
+
+#[get("/next_move/{agent}/{board_str}/{player}")]
+pub async fn next_move_for_agent(
+    path: web::Path<(String, String, IPlayer)>,
+    value_model: web::Data<SharedValueModel>
+) -> HttpResponse {
+    let (agent, board_str, player) = path.into_inner();
+    next_move_response(agent, board_str, player, value_model).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{ Arc, Mutex };
+
+    use othello_agent::{
+        backend::{ default_device, DefaultBackend },
+        error::OthelloError,
+        gameplay::constants::INITIAL_BOARD,
+        model::model::ModelConfig,
+    };
+
+    use othello_agent::gameplay::{ constants::NULL_MOVE_INDEX, game::Move, position::IPosition };
+
+    use super::{
+        move_index_from_action,
+        select_move,
+        wire_move_index,
+        NextMoveError,
+        SharedValueModel,
+        PASS_MOVE_INDEX,
+    };
+
+    #[test]
+    fn maps_moves_to_the_wire() {
+        assert_eq!(wire_move_index(Move::Pass), PASS_MOVE_INDEX);
+        let position = IPosition::position_from_piece_index(19).unwrap();
+        assert_eq!(wire_move_index(Move::Place(position)), 19);
+        assert_eq!(move_index_from_action(NULL_MOVE_INDEX), PASS_MOVE_INDEX);
+        assert_eq!(move_index_from_action(63), 63);
+    }
+
+    #[test]
+    fn selects_moves_for_known_agents() {
+        let no_model = SharedValueModel(None);
+        for agent in ["rule_based", "search_based", "mcts"] {
+            let response = select_move(agent, INITIAL_BOARD, 0, &no_model).ok().unwrap();
+            // the four opening moves: d3, c4, f5 and e6
+            assert!([19, 26, 37, 44].contains(&response.move_index));
+            assert!(response.probabilities.is_none());
+        }
+        let mut board = [[2; 8]; 8];
+        board[0][0] = 1;
+        let response = select_move("rule_based", board, 0, &no_model).ok().unwrap();
+        assert_eq!(response.move_index, PASS_MOVE_INDEX);
+    }
+
+    #[test]
+    fn rejects_unknown_or_unavailable_agents() {
+        let no_model = SharedValueModel(None);
+        let error = select_move("grandmaster", INITIAL_BOARD, 0, &no_model).err().unwrap();
+        assert_eq!(error, NextMoveError::UnknownAgent("grandmaster".to_string()));
+        assert_eq!(error.to_response().status(), 404);
+        let error = select_move("value_based", INITIAL_BOARD, 0, &no_model).err().unwrap();
+        assert_eq!(error.to_response().status(), 503);
+        let error = select_move("rule_based", INITIAL_BOARD, 2, &no_model).err().unwrap();
+        assert_eq!(error, NextMoveError::InvalidInput(OthelloError::InvalidPlayer(2)));
+        assert_eq!(error.to_response().status(), 400);
+    }
+
+    #[actix_web::test]
+    async fn rejects_malformed_boards() {
+        use actix_web::{ test, web, App };
+
+        use super::next_move_for_agent;
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(SharedValueModel(None)))
+                .service(next_move_for_agent)
+        ).await;
+        let request = test::TestRequest::get().uri("/next_move/rule_based/abc/0").to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 400);
+        let board = "---------h-yq---------";
+        let uri = format!("/next_move/rule_based/{}/0", board);
+        let request = test::TestRequest::get().uri(&uri).to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 200);
+    }
+
+    #[test]
+    fn value_based_agent_reports_probabilities() {
+        let model = ModelConfig::new(3).init::<DefaultBackend>(&default_device());
+        let value_model = SharedValueModel(Some(Arc::new(Mutex::new(model))));
+        let response = select_move("value_based", INITIAL_BOARD, 0, &value_model).ok().unwrap();
+        assert!([19, 26, 37, 44].contains(&response.move_index));
+        let probabilities = response.probabilities.unwrap();
+        let total = probabilities.black_wins + probabilities.white_wins + probabilities.draw;
+        assert!((total - 1.0).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,624 @@
+use std::{ collections::HashMap, sync::{ Arc, Mutex, RwLock } };
+
+use actix_web::{ get, post, web, HttpResponse };
+use othello_agent::{
+    error::{ check_player, OthelloError },
+    gameplay::{
+        constants::NULL_MOVE_INDEX,
+        game::{ IGame, IPlayer, Move, Ply },
+        position::IPosition,
+    },
+    simulate::history::GameHistory,
+};
+use serde::{ Deserialize, Serialize };
+use uuid::Uuid;
+
+use crate::{
+    next_move::{ select_move, NextMoveError, SharedValueModel, AGENT_NAMES },
+    storage::{ now, record_move, GameStore, StoredGame },
+};
+
+/// Seconds a finished game is kept in memory after its last request.
+const FINISHED_SESSION_TTL: u64 = 10 * 60;
+/// Seconds a running game is kept in memory after its last request.
+const IDLE_SESSION_TTL: u64 = 60 * 60;
+
+///
+/// A human playing a bot. The server owns the game, so clients only send their moves.
+pub struct GameSession {
+    game: IGame,
+    agent: String,
+    human_player: IPlayer,
+    history: GameHistory,
+    created_at: u64,
+    /// Seconds since the unix epoch of the last move or takeback
+    updated_at: u64,
+}
+
+pub type Sessions = Arc<RwLock<HashMap<Uuid, Arc<Mutex<GameSession>>>>>;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SessionError {
+    Agent(NextMoveError),
+    GameOver,
+    IllegalMove,
+    /// A pass was sent while the human has a legal move.
+    MustMove,
+    /// A takeback before the human placed a piece.
+    NothingToTakeBack,
+    /// The bot chose a move the game does not allow.
+    BotMove(OthelloError),
+}
+
+#[derive(Deserialize)]
+pub struct CreateGameRequest {
+    /// One of the agents served by `/next_move/{agent}`
+    pub agent: String,
+    /// Colour the human plays, black by default
+    pub human_player: Option<IPlayer>,
+}
+
+#[derive(Deserialize)]
+pub struct MoveRequest {
+    /// Piece index of the human's move, None to pass.
+    pub move_index: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GameSessionResponse {
+    pub game_id: Uuid,
+    pub agent: String,
+    pub human_player: IPlayer,
+    /// Board encoded with `string_from_board`
+    pub board: String,
+    pub turn: IPlayer,
+    /// Legal moves of the human, empty while the game is over.
+    pub legal_moves: Vec<usize>,
+    /// Disc counts of black and white
+    pub scores: [i16; 2],
+    pub game_over: bool,
+    /// None while the game is running and for a draw.
+    pub winner: Option<IPlayer>,
+    /// Moves the bot played in reply, in order.
+    pub bot_moves: Vec<usize>,
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+impl GameSession {
+    ///
+    /// Starts a game against `agent`. If the human plays white the bot makes the first move.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(GameSession, Vec<usize>), SessionError>` - The session and the bot's opening
+    ///   moves.
+    pub fn new(
+        agent: &str,
+        human_player: IPlayer,
+        value_model: &SharedValueModel
+    ) -> Result<(GameSession, Vec<usize>), SessionError> {
+        if !AGENT_NAMES.contains(&agent) {
+            return Err(SessionError::Agent(NextMoveError::UnknownAgent(agent.to_string())));
+        }
+        let mut session = GameSession {
+            game: IGame::new(),
+            agent: agent.to_string(),
+            human_player,
+            history: GameHistory::new(),
+            created_at: now(),
+            updated_at: now(),
+        };
+        let bot_moves = session.play_bot(value_model)?;
+        Ok((session, bot_moves))
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.game.is_over()
+    }
+
+    /// True once the game has been left alone for longer than it is kept in memory.
+    pub fn is_expired(&self, now: u64) -> bool {
+        let ttl = if self.is_over() { FINISHED_SESSION_TTL } else { IDLE_SESSION_TTL };
+        now.saturating_sub(self.updated_at) > ttl
+    }
+
+    ///
+    /// Plays the bot until it is the human's turn and the human has a move, or the game ends.
+    /// The human passes automatically when they have no move.
+    fn play_bot(&mut self, value_model: &SharedValueModel) -> Result<Vec<usize>, SessionError> {
+        let mut bot_moves = Vec::new();
+        while !self.is_over() {
+            if !self.game.player_has_move(self.game.turn) {
+                self.game.try_make_move(Move::Pass).map_err(SessionError::BotMove)?;
+                record_move(&mut self.history, &self.game, NULL_MOVE_INDEX);
+                continue;
+            }
+            if self.game.turn == self.human_player {
+                break;
+            }
+            let response = select_move(&self.agent, self.game.board, self.game.turn, value_model);
+            let move_index = response.map_err(SessionError::Agent)?.move_index as usize;
+            self.game.try_make_move_at_index(move_index).map_err(SessionError::BotMove)?;
+            record_move(&mut self.history, &self.game, move_index);
+            bot_moves.push(move_index);
+        }
+        Ok(bot_moves)
+    }
+
+    ///
+    /// Applies the human's move and lets the bot reply.
+    ///
+    /// # Arguments
+    ///
+    /// * `move_index` - Piece index of the move, None to pass.
+    /// * `value_model` - Model used if the bot is value based.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<usize>, SessionError>` - The bot's replies.
+    pub fn play_human(
+        &mut self,
+        move_index: Option<usize>,
+        value_model: &SharedValueModel
+    ) -> Result<Vec<usize>, SessionError> {
+        if self.is_over() {
+            return Err(SessionError::GameOver);
+        }
+        self.updated_at = now();
+        match move_index {
+            Some(move_index) => {
+                self.game
+                    .try_make_move_at_index(move_index)
+                    .map_err(|_| SessionError::IllegalMove)?;
+                record_move(&mut self.history, &self.game, move_index);
+            }
+            None => {
+                self.game.try_make_move(Move::Pass).map_err(|_| SessionError::MustMove)?;
+                record_move(&mut self.history, &self.game, NULL_MOVE_INDEX);
+            }
+        }
+        self.play_bot(value_model)
+    }
+
+    ///
+    /// Takes back the human's last placement and the bot replies after it, so the human can
+    /// play that move again. Passes in between are taken back too. Also works once the game is
+    /// over.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), SessionError>` - `NothingToTakeBack` if the human has not placed a piece.
+    pub fn take_back(&mut self) -> Result<(), SessionError> {
+        let is_human_placement = |ply: &Ply| {
+            ply.player == self.human_player && ply.played != Move::Pass
+        };
+        if !self.game.plies().iter().any(is_human_placement) {
+            return Err(SessionError::NothingToTakeBack);
+        }
+        self.updated_at = now();
+        loop {
+            let reached = self.game.plies().last().is_some_and(is_human_placement);
+            self.game.undo();
+            if reached {
+                break;
+            }
+        }
+        self.history.truncate(self.game.plies().len());
+        let [black_score, white_score] = self.game.scores();
+        self.history.set_scores(black_score, white_score);
+        Ok(())
+    }
+
+    pub fn winner(&self) -> Option<IPlayer> {
+        self.game.result().and_then(|result| result.winner)
+    }
+
+    /// The game as it is saved in the store.
+    pub fn to_stored(&self, game_id: Uuid) -> StoredGame {
+        let mut players = [self.agent.clone(), self.agent.clone()];
+        players[self.human_player as usize] = "human".to_string();
+        StoredGame {
+            game_id: game_id.to_string(),
+            players,
+            history: self.history.clone(),
+            finished: self.is_over(),
+            winner: self.winner(),
+            resigned: false,
+            created_at: self.created_at,
+            updated_at: now(),
+            rejoin_tokens: [None, None],
+        }
+    }
+
+    pub fn to_response(&self, game_id: Uuid, bot_moves: Vec<usize>) -> GameSessionResponse {
+        let legal_moves = if self.is_over() {
+            Vec::new()
+        } else {
+            self.game
+                .get_valid_moves(self.human_player)
+                .iter()
+                .map(IPosition::to_piece_index)
+                .collect()
+        };
+        GameSessionResponse {
+            game_id,
+            agent: self.agent.clone(),
+            human_player: self.human_player,
+            board: self.game.get_board_string(),
+            turn: self.game.turn,
+            legal_moves,
+            scores: self.game.scores(),
+            game_over: self.is_over(),
+            winner: self.winner(),
+            bot_moves,
+        }
+    }
+}
+
+fn error_response(error: SessionError) -> HttpResponse {
+    let (mut response, message) = match error {
+        SessionError::Agent(error) => {
+            return error.to_response();
+        }
+        SessionError::GameOver => (HttpResponse::Conflict(), "The game is over".to_string()),
+        SessionError::IllegalMove => {
+            (HttpResponse::UnprocessableEntity(), "That move is not legal".to_string())
+        }
+        SessionError::MustMove => {
+            (
+                HttpResponse::UnprocessableEntity(),
+                "You can only pass when you have no legal move".to_string(),
+            )
+        }
+        SessionError::NothingToTakeBack => {
+            (HttpResponse::Conflict(), "You have no move to take back".to_string())
+        }
+        SessionError::BotMove(error) => {
+            let message = format!("The bot played an illegal move: {}", error);
+            (HttpResponse::InternalServerError(), message)
+        }
+    };
+    response.json(ErrorResponse { error: message })
+}
+
+fn not_found(game_id: Uuid) -> HttpResponse {
+    let error = format!("Game {} does not exist", game_id);
+    HttpResponse::NotFound().json(ErrorResponse { error })
+}
+
+fn find_session(sessions: &Sessions, game_id: Uuid) -> Option<Arc<Mutex<GameSession>>> {
+    sessions.read().expect("Sessions lock poisoned").get(&game_id).cloned()
+}
+
+///
+/// Drops the games nobody has touched for a while, they stay in the store. A session that is
+/// locked is in use and kept.
+///
+/// # Arguments
+///
+/// * `sessions` - The sessions of the server.
+/// * `now` - Seconds since the unix epoch.
+pub fn remove_expired_sessions(sessions: &Sessions, now: u64) {
+    sessions
+        .write()
+        .expect("Sessions lock poisoned")
+        .retain(|_, session| {
+            session.try_lock().map_or(true, |session| !session.is_expired(now))
+        });
+}
+
+fn blocking_error(error: actix_web::error::BlockingError) -> HttpResponse {
+    HttpResponse::InternalServerError().json(ErrorResponse { error: error.to_string() })
+}
+
+#[post("/games")]
+pub async fn create_game(
+    body: web::Json<CreateGameRequest>,
+    sessions: web::Data<Sessions>,
+    value_model: web::Data<SharedValueModel>,
+    store: web::Data<GameStore>
+) -> HttpResponse {
+    let CreateGameRequest { agent, human_player } = body.into_inner();
+    let human_player = match check_player(human_player.unwrap_or(0)) {
+        Ok(human_player) => human_player,
+        Err(error) => {
+            return HttpResponse::BadRequest().json(ErrorResponse { error: error.to_string() });
+        }
+    };
+    let value_model = value_model.get_ref().clone();
+    let game_id = Uuid::new_v4();
+    let result = web::block(move || {
+        let (session, bot_moves) = GameSession::new(&agent, human_player, &value_model)?;
+        store.save_or_log(&session.to_stored(game_id));
+        Ok((session, bot_moves))
+    }).await;
+    match result {
+        Ok(Ok((session, bot_moves))) => {
+            let response = session.to_response(game_id, bot_moves);
+            // new games are the only thing that grows the map, so clean up before adding one
+            remove_expired_sessions(&sessions, now());
+            sessions
+                .write()
+                .expect("Sessions lock poisoned")
+                .insert(game_id, Arc::new(Mutex::new(session)));
+            HttpResponse::Created().json(response)
+        }
+        Ok(Err(error)) => error_response(error),
+        Err(error) => blocking_error(error),
+    }
+}
+
+#[get("/games/{game_id}")]
+pub async fn get_game(path: web::Path<Uuid>, sessions: web::Data<Sessions>) -> HttpResponse {
+    let game_id = path.into_inner();
+    let Some(session) = find_session(&sessions, game_id) else {
+        return not_found(game_id);
+    };
+    // a move holds the lock while the bot thinks, wait for it off the async workers
+    let result = web::block(move || {
+        let session = session.lock().expect("Session lock poisoned");
+        session.to_response(game_id, Vec::new())
+    }).await;
+    match result {
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(error) => blocking_error(error),
+    }
+}
+
+#[post("/games/{game_id}/moves")]
+pub async fn make_move(
+    path: web::Path<Uuid>,
+    body: web::Json<MoveRequest>,
+    sessions: web::Data<Sessions>,
+    value_model: web::Data<SharedValueModel>,
+    store: web::Data<GameStore>
+) -> HttpResponse {
+    let game_id = path.into_inner();
+    let Some(session) = find_session(&sessions, game_id) else {
+        return not_found(game_id);
+    };
+    let move_index = body.into_inner().move_index;
+    let value_model = value_model.get_ref().clone();
+    // the session stays locked while the bot thinks, so moves on one game are applied in order
+    let result = web::block(move || {
+        let mut session = session.lock().expect("Session lock poisoned");
+        let bot_moves = session.play_human(move_index, &value_model)?;
+        store.save_or_log(&session.to_stored(game_id));
+        Ok(session.to_response(game_id, bot_moves))
+    }).await;
+    match result {
+        Ok(Ok(response)) => HttpResponse::Ok().json(response),
+        Ok(Err(error)) => error_response(error),
+        Err(error) => blocking_error(error),
+    }
+}
+
+#[post("/games/{game_id}/takeback")]
+pub async fn take_back(
+    path: web::Path<Uuid>,
+    sessions: web::Data<Sessions>,
+    store: web::Data<GameStore>
+) -> HttpResponse {
+    let game_id = path.into_inner();
+    let Some(session) = find_session(&sessions, game_id) else {
+        return not_found(game_id);
+    };
+    // like get_game, the lock may be held by a move until the bot has replied
+    let result = web::block(move || {
+        let mut session = session.lock().expect("Session lock poisoned");
+        session.take_back()?;
+        store.save_or_log(&session.to_stored(game_id));
+        Ok(session.to_response(game_id, Vec::new()))
+    }).await;
+    match result {
+        Ok(Ok(response)) => HttpResponse::Ok().json(response),
+        Ok(Err(error)) => error_response(error),
+        Err(error) => blocking_error(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{ Arc, Mutex };
+
+    use othello_agent::error::OthelloError;
+    use uuid::Uuid;
+
+    use crate::next_move::{ NextMoveError, SharedValueModel };
+
+    use super::{
+        error_response,
+        find_session,
+        remove_expired_sessions,
+        GameSession,
+        SessionError,
+        Sessions,
+        FINISHED_SESSION_TTL,
+        IDLE_SESSION_TTL,
+    };
+
+    #[test]
+    fn bot_replies_to_human_moves() {
+        let no_model = SharedValueModel(None);
+        let (mut session, bot_moves) = GameSession::new("rule_based", 0, &no_model).unwrap();
+        assert!(bot_moves.is_empty());
+        assert_eq!(session.play_human(Some(0), &no_model), Err(SessionError::IllegalMove));
+        assert_eq!(session.play_human(None, &no_model), Err(SessionError::MustMove));
+        let bot_moves = session.play_human(Some(19), &no_model).unwrap();
+        assert_eq!(bot_moves.len(), 1);
+        let response = session.to_response(uuid::Uuid::new_v4(), bot_moves);
+        assert_eq!(response.turn, 0);
+        assert_eq!(response.scores.iter().sum::<i16>(), 6);
+        assert!(!response.legal_moves.is_empty());
+        assert!(!response.game_over);
+    }
+
+    #[test]
+    fn bot_opens_when_human_plays_white() {
+        let no_model = SharedValueModel(None);
+        let (session, bot_moves) = GameSession::new("search_based", 1, &no_model).unwrap();
+        assert_eq!(bot_moves.len(), 1);
+        assert!([19, 26, 37, 44].contains(&bot_moves[0]));
+        assert_eq!(session.to_response(uuid::Uuid::new_v4(), bot_moves).turn, 1);
+    }
+
+    #[test]
+    fn plays_to_the_end() {
+        let no_model = SharedValueModel(None);
+        let (mut session, _) = GameSession::new("rule_based", 0, &no_model).unwrap();
+        let game_id = uuid::Uuid::new_v4();
+        while !session.is_over() {
+            let response = session.to_response(uuid::Uuid::new_v4(), Vec::new());
+            session.play_human(response.legal_moves.first().copied(), &no_model).unwrap();
+        }
+        let response = session.to_response(uuid::Uuid::new_v4(), Vec::new());
+        assert!(response.game_over);
+        assert!(response.legal_moves.is_empty());
+        assert_eq!(session.play_human(None, &no_model), Err(SessionError::GameOver));
+
+        let stored = session.to_stored(game_id);
+        assert_eq!(stored.game_id, game_id.to_string());
+        assert_eq!(stored.players, ["human".to_string(), "rule_based".to_string()]);
+        assert!(stored.finished);
+        assert_eq!(stored.winner, session.winner());
+        let scores = [stored.history.agent0_score, stored.history.agent1_score];
+        assert_eq!(scores, response.scores);
+    }
+
+    #[test]
+    fn takes_back_the_human_move_and_the_reply() {
+        let no_model = SharedValueModel(None);
+        let (mut session, _) = GameSession::new("rule_based", 1, &no_model).unwrap();
+        assert_eq!(session.take_back(), Err(SessionError::NothingToTakeBack));
+        let board = session.game.board;
+        let move_index = session.to_response(uuid::Uuid::new_v4(), Vec::new()).legal_moves[0];
+        session.play_human(Some(move_index), &no_model).unwrap();
+        assert_eq!(session.history.total_moves, 3);
+
+        assert_eq!(session.take_back(), Ok(()));
+        assert_eq!(session.game.board, board);
+        assert_eq!(session.game.turn, 1);
+        // the bot's opening stays
+        assert_eq!(session.history.total_moves, 1);
+        assert_eq!(session.history.agent0_score + session.history.agent1_score, 5);
+        assert_eq!(session.take_back(), Err(SessionError::NothingToTakeBack));
+    }
+
+    #[test]
+    fn rejects_unknown_agents() {
+        let error = GameSession::new("grandmaster", 0, &SharedValueModel(None)).err().unwrap();
+        let unknown = NextMoveError::UnknownAgent("grandmaster".to_string());
+        // the same status as /next_move/{agent}
+        assert_eq!(error_response(error).status(), unknown.to_response().status());
+        let error = GameSession::new("grandmaster", 0, &SharedValueModel(None)).err().unwrap();
+        assert_eq!(error, SessionError::Agent(unknown));
+        // a broken bot is an error of the server, not of the human's move
+        let error = SessionError::BotMove(OthelloError::GameOver);
+        assert_eq!(error_response(error).status(), 500);
+    }
+
+    #[test]
+    fn removes_finished_and_idle_sessions() {
+        let no_model = SharedValueModel(None);
+        let sessions = Sessions::default();
+        let (running, _) = GameSession::new("rule_based", 0, &no_model).unwrap();
+        let (mut finished, _) = GameSession::new("rule_based", 0, &no_model).unwrap();
+        while !finished.is_over() {
+            let legal_moves = finished.to_response(Uuid::new_v4(), Vec::new()).legal_moves;
+            finished.play_human(legal_moves.first().copied(), &no_model).unwrap();
+        }
+        let started = running.updated_at;
+        let (running_id, finished_id) = (Uuid::new_v4(), Uuid::new_v4());
+        {
+            let mut map = sessions.write().unwrap();
+            map.insert(running_id, Arc::new(Mutex::new(running)));
+            map.insert(finished_id, Arc::new(Mutex::new(finished)));
+        }
+        remove_expired_sessions(&sessions, started);
+        assert_eq!(sessions.read().unwrap().len(), 2);
+        remove_expired_sessions(&sessions, started + FINISHED_SESSION_TTL + 1);
+        assert!(find_session(&sessions, finished_id).is_none());
+        // a session in use is never removed
+        let in_use = find_session(&sessions, running_id).unwrap();
+        let guard = in_use.lock().unwrap();
+        remove_expired_sessions(&sessions, started + IDLE_SESSION_TTL + 1);
+        assert!(find_session(&sessions, running_id).is_some());
+        drop(guard);
+        remove_expired_sessions(&sessions, started + IDLE_SESSION_TTL + 1);
+        assert!(sessions.read().unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn serves_games_over_http() {
+        use actix_web::{ test, web, App };
+
+        use crate::storage::GameStore;
+
+        use super::{ create_game, get_game, make_move, take_back, Sessions };
+
+        let store = web::Data::new(GameStore::open_in_memory().unwrap());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(Sessions::default()))
+                .app_data(store.clone())
+                .app_data(web::Data::new(SharedValueModel(None)))
+                .service(create_game)
+                .service(get_game)
+                .service(make_move)
+                .service(take_back)
+        ).await;
+        let request = test::TestRequest
+            ::post()
+            .uri("/games")
+            .set_json(serde_json::json!({ "agent": "rule_based" }))
+            .to_request();
+        let created: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+        let game_id = created["game_id"].as_str().unwrap().to_string();
+        assert_eq!(created["legal_moves"], serde_json::json!([19, 26, 37, 44]));
+
+        let request = test::TestRequest
+            ::post()
+            .uri(&format!("/games/{}/moves", game_id))
+            .set_json(serde_json::json!({ "move_index": 0 }))
+            .to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 422);
+
+        let request = test::TestRequest
+            ::post()
+            .uri(&format!("/games/{}/moves", game_id))
+            .set_json(serde_json::json!({ "move_index": 19 }))
+            .to_request();
+        let played: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(played["bot_moves"].as_array().unwrap().len(), 1);
+        assert_eq!(played["game_over"], false);
+
+        let request = test::TestRequest::get().uri(&format!("/games/{}", game_id)).to_request();
+        let fetched: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(fetched["board"], played["board"]);
+        // every change is saved
+        let stored = store.get(&game_id).unwrap().unwrap();
+        assert_eq!(stored.history.total_moves, 2);
+        assert_eq!(stored.history.move_history[0], 19);
+
+        let request = test::TestRequest
+            ::post()
+            .uri(&format!("/games/{}/takeback", game_id))
+            .to_request();
+        let taken_back: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(taken_back["board"], created["board"]);
+        assert_eq!(store.get(&game_id).unwrap().unwrap().history.total_moves, 0);
+        let request = test::TestRequest
+            ::post()
+            .uri(&format!("/games/{}/takeback", game_id))
+            .to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 409);
+
+        let missing = format!("/games/{}", uuid::Uuid::new_v4());
+        let request = test::TestRequest::get().uri(&missing).to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 404);
+    }
+}
@@ -0,0 +1,573 @@
+use std::{ sync::Mutex, time::{ SystemTime, UNIX_EPOCH } };
+
+use actix_web::{
+    get,
+    http::header::{ ContentDisposition, DispositionParam, DispositionType },
+    web,
+    HttpResponse,
+};
+use othello_agent::{
+    gameplay::{ game::{ IGame, IPlayer }, transcript::transcript_from_history },
+    simulate::history::GameHistory,
+};
+use rusqlite::{ params, Connection, OptionalExtension };
+use serde::{ Deserialize, Serialize };
+use tokio::sync::mpsc::{ self, UnboundedSender };
+
+/// Database used when `OTHELLO_DB_PATH` is not set.
+pub const DEFAULT_DB_PATH: &str = "othello_games.db";
+
+/// Number of games listed by `/history` when no limit is given.
+const DEFAULT_RECENT_LIMIT: usize = 20;
+const MAX_RECENT_LIMIT: usize = 100;
+
+///
+/// A game as it is kept in the store. Rooms and sessions save one after every change, so a game
+/// can be replayed or resumed after the server restarts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredGame {
+    pub game_id: String,
+    /// Who played black and white, `human` or the name of an agent
+    pub players: [String; 2],
+    /// Moves in order, `NULL_MOVE_INDEX` for a pass, with the board after each of them
+    pub history: GameHistory,
+    pub finished: bool,
+    /// None while the game is running and for a draw.
+    pub winner: Option<IPlayer>,
+    pub resigned: bool,
+    /// Seconds since the unix epoch
+    pub created_at: u64,
+    pub updated_at: u64,
+    /// Rejoin tokens of the seats reserved in a running game. They give the seats away, so they
+    /// are never sent to clients.
+    #[serde(skip)]
+    pub rejoin_tokens: [Option<String>; 2],
+}
+
+/// A stored game without its boards, returned when listing games.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameSummary {
+    pub game_id: String,
+    pub players: [String; 2],
+    pub total_moves: u16,
+    /// Disc counts of black and white
+    pub scores: [i16; 2],
+    pub finished: bool,
+    pub winner: Option<IPlayer>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+/// Seconds since the unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+///
+/// Appends a move to a history, with the board and disc counts after it. Once the game is over
+/// the scores are the final ones of `IGame::result`.
+///
+/// # Arguments
+///
+/// * `history` - The history of the game.
+/// * `game` - The game after the move was played.
+/// * `move_index` - Piece index of the move, `NULL_MOVE_INDEX` for a pass.
+pub fn record_move(history: &mut GameHistory, game: &IGame, move_index: usize) {
+    history.add_board(game.board, move_index, false);
+    let [black_score, white_score] = game.scores();
+    history.set_scores(black_score, white_score);
+}
+
+///
+/// Games kept in an embedded SQLite database. Every game is one row, the history is stored as
+/// json next to the columns used for listing.
+pub struct GameStore {
+    connection: Mutex<Connection>,
+}
+
+impl GameStore {
+    ///
+    /// Opens the database at `path`, creating it if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the database file.
+    ///
+    /// # Returns
+    ///
+    /// * `rusqlite::Result<GameStore>` - The store, or the error from opening the database.
+    pub fn open(path: &str) -> rusqlite::Result<GameStore> {
+        GameStore::from_connection(Connection::open(path)?)
+    }
+
+    /// A store that is lost when dropped, used in tests.
+    #[cfg(test)]
+    pub fn open_in_memory() -> rusqlite::Result<GameStore> {
+        GameStore::from_connection(Connection::open_in_memory()?)
+    }
+
+    /// Opens the database named by `OTHELLO_DB_PATH`, or `DEFAULT_DB_PATH`.
+    pub fn open_default() -> rusqlite::Result<GameStore> {
+        let path = std::env::var("OTHELLO_DB_PATH").unwrap_or(DEFAULT_DB_PATH.to_string());
+        GameStore::open(&path)
+    }
+
+    fn from_connection(connection: Connection) -> rusqlite::Result<GameStore> {
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS games (
+                game_id TEXT PRIMARY KEY,
+                black TEXT NOT NULL,
+                white TEXT NOT NULL,
+                history TEXT NOT NULL,
+                total_moves INTEGER NOT NULL,
+                black_score INTEGER NOT NULL,
+                white_score INTEGER NOT NULL,
+                finished INTEGER NOT NULL,
+                winner INTEGER,
+                resigned INTEGER NOT NULL,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL,
+                black_token TEXT,
+                white_token TEXT
+            );
+            CREATE INDEX IF NOT EXISTS games_updated_at ON games (updated_at);"
+        )?;
+        // databases from before the seats were stored get the token columns added
+        if connection.prepare("SELECT black_token FROM games").is_err() {
+            connection.execute_batch(
+                "ALTER TABLE games ADD COLUMN black_token TEXT;
+                ALTER TABLE games ADD COLUMN white_token TEXT;"
+            )?;
+        }
+        Ok(GameStore { connection: Mutex::new(connection) })
+    }
+
+    /// Inserts a game, or replaces the stored one with the same id.
+    pub fn save(&self, game: &StoredGame) -> rusqlite::Result<()> {
+        self.write(
+            "INSERT OR REPLACE INTO games
+            (game_id, black, white, history, total_moves, black_score, white_score, finished,
+                winner, resigned, created_at, updated_at, black_token, white_token)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            game
+        )?;
+        Ok(())
+    }
+
+    ///
+    /// Inserts a new game, leaving a stored game with the same id untouched.
+    ///
+    /// # Returns
+    ///
+    /// * `rusqlite::Result<bool>` - False if the id is already taken.
+    pub fn insert(&self, game: &StoredGame) -> rusqlite::Result<bool> {
+        let inserted = self.write(
+            "INSERT INTO games
+            (game_id, black, white, history, total_moves, black_score, white_score, finished,
+                winner, resigned, created_at, updated_at, black_token, white_token)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+            ON CONFLICT (game_id) DO NOTHING",
+            game
+        )?;
+        Ok(inserted == 1)
+    }
+
+    /// Runs an insert with the columns of a game as its parameters, returns the changed rows.
+    fn write(&self, statement: &str, game: &StoredGame) -> rusqlite::Result<usize> {
+        let history = serde_json::to_string(&game.history).expect("Histories always serialize");
+        let connection = self.connection.lock().expect("Game store lock poisoned");
+        connection.execute(
+            statement,
+            params![
+                game.game_id,
+                game.players[0],
+                game.players[1],
+                history,
+                game.history.total_moves,
+                game.history.agent0_score,
+                game.history.agent1_score,
+                game.finished,
+                game.winner,
+                game.resigned,
+                game.created_at,
+                game.updated_at,
+                game.rejoin_tokens[0],
+                game.rejoin_tokens[1]
+            ]
+        )
+    }
+
+    pub fn get(&self, game_id: &str) -> rusqlite::Result<Option<StoredGame>> {
+        let connection = self.connection.lock().expect("Game store lock poisoned");
+        connection
+            .query_row(
+                "SELECT game_id, black, white, history, finished, winner, resigned, created_at,
+                    updated_at, black_token, white_token
+                FROM games WHERE game_id = ?1",
+                params![game_id],
+                |row| {
+                    let history: String = row.get(3)?;
+                    let history = serde_json::from_str(&history).map_err(|error| {
+                        rusqlite::Error::FromSqlConversionFailure(
+                            3,
+                            rusqlite::types::Type::Text,
+                            Box::new(error)
+                        )
+                    })?;
+                    Ok(StoredGame {
+                        game_id: row.get(0)?,
+                        players: [row.get(1)?, row.get(2)?],
+                        history,
+                        finished: row.get(4)?,
+                        winner: row.get(5)?,
+                        resigned: row.get(6)?,
+                        created_at: row.get(7)?,
+                        updated_at: row.get(8)?,
+                        rejoin_tokens: [row.get(9)?, row.get(10)?],
+                    })
+                }
+            )
+            .optional()
+    }
+
+    /// The most recently updated games, newest first.
+    pub fn recent(&self, limit: usize) -> rusqlite::Result<Vec<GameSummary>> {
+        let connection = self.connection.lock().expect("Game store lock poisoned");
+        let mut statement = connection.prepare(
+            "SELECT game_id, black, white, total_moves, black_score, white_score, finished, winner,
+                created_at, updated_at
+            FROM games ORDER BY updated_at DESC, rowid DESC LIMIT ?1"
+        )?;
+        let summaries = statement.query_map(params![limit], |row| {
+            Ok(GameSummary {
+                game_id: row.get(0)?,
+                players: [row.get(1)?, row.get(2)?],
+                total_moves: row.get(3)?,
+                scores: [row.get(4)?, row.get(5)?],
+                finished: row.get(6)?,
+                winner: row.get(7)?,
+                created_at: row.get(8)?,
+                updated_at: row.get(9)?,
+            })
+        })?;
+        summaries.collect()
+    }
+
+    /// Saves a game, logging instead of failing so a broken database does not stop play.
+    pub fn save_or_log(&self, game: &StoredGame) {
+        if let Err(error) = self.save(game) {
+            println!("Failed to save game {}: {}", game.game_id, error);
+        }
+    }
+}
+
+/// Games waiting to be saved by the task of `spawn_saver`.
+pub type SaveSender = UnboundedSender<StoredGame>;
+
+///
+/// Saves the games sent to it one after another, off the async workers. Rooms send their game
+/// while they hold the lock of the games, so an older state never overwrites a newer one.
+///
+/// # Arguments
+///
+/// * `store` - The store the games are saved to.
+///
+/// # Returns
+///
+/// * `SaveSender` - Sender of the games to save.
+pub fn spawn_saver(store: web::Data<GameStore>) -> SaveSender {
+    let (tx, mut rx) = mpsc::unbounded_channel::<StoredGame>();
+    tokio::spawn(async move {
+        while let Some(game) = rx.recv().await {
+            let store = store.clone();
+            if let Err(error) = web::block(move || store.save_or_log(&game)).await {
+                println!("Failed to save game: {}", error);
+            }
+        }
+    });
+    tx
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+#[derive(Deserialize)]
+pub struct RecentQuery {
+    pub limit: Option<usize>,
+}
+
+fn storage_error(error: rusqlite::Error) -> HttpResponse {
+    HttpResponse::InternalServerError().json(ErrorResponse { error: error.to_string() })
+}
+
+fn blocking_error(error: actix_web::error::BlockingError) -> HttpResponse {
+    HttpResponse::InternalServerError().json(ErrorResponse { error: error.to_string() })
+}
+
+fn not_found(game_id: &str) -> HttpResponse {
+    let error = format!("Game {} was not found", game_id);
+    HttpResponse::NotFound().json(ErrorResponse { error })
+}
+
+/// Name of the downloaded transcript, with the characters of the id that are safe in a header.
+fn transcript_filename(game_id: &str) -> String {
+    let name: String = game_id
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .collect();
+    if name.is_empty() { "game.txt".to_string() } else { format!("{}.txt", name) }
+}
+
+/// Reads a game off the async workers, a failed read is already the error response.
+async fn load(
+    store: web::Data<GameStore>,
+    game_id: &str
+) -> Result<Option<StoredGame>, HttpResponse> {
+    let game_id = game_id.to_string();
+    match web::block(move || store.get(&game_id)).await {
+        Ok(Ok(game)) => Ok(game),
+        Ok(Err(error)) => Err(storage_error(error)),
+        Err(error) => Err(blocking_error(error)),
+    }
+}
+
+#[get("/history")]
+pub async fn recent_games(
+    query: web::Query<RecentQuery>,
+    store: web::Data<GameStore>
+) -> HttpResponse {
+    let limit = query.limit.unwrap_or(DEFAULT_RECENT_LIMIT).min(MAX_RECENT_LIMIT);
+    match web::block(move || store.recent(limit)).await {
+        Ok(Ok(summaries)) => HttpResponse::Ok().json(summaries),
+        Ok(Err(error)) => storage_error(error),
+        Err(error) => blocking_error(error),
+    }
+}
+
+#[get("/history/{game_id}")]
+pub async fn stored_game(path: web::Path<String>, store: web::Data<GameStore>) -> HttpResponse {
+    let game_id = path.into_inner();
+    match load(store, &game_id).await {
+        Ok(Some(game)) => HttpResponse::Ok().json(game),
+        Ok(None) => not_found(&game_id),
+        Err(response) => response,
+    }
+}
+
+#[get("/history/{game_id}/transcript")]
+pub async fn game_transcript(
+    path: web::Path<String>,
+    store: web::Data<GameStore>
+) -> HttpResponse {
+    let game_id = path.into_inner();
+    match load(store, &game_id).await {
+        Ok(Some(game)) => {
+            let transcript = match transcript_from_history(&game.history) {
+                Ok(transcript) => transcript,
+                Err(error) => {
+                    let error = format!("Game {} has a corrupt move: {}", game_id, error);
+                    return HttpResponse::InternalServerError().json(ErrorResponse { error });
+                }
+            };
+            let disposition = ContentDisposition {
+                disposition: DispositionType::Attachment,
+                parameters: vec![DispositionParam::Filename(transcript_filename(&game_id))],
+            };
+            HttpResponse::Ok()
+                .content_type("text/plain; charset=utf-8")
+                .insert_header(disposition)
+                .body(transcript)
+        }
+        Ok(None) => not_found(&game_id),
+        Err(response) => response,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use othello_agent::{
+        gameplay::{ game::IGame, position::IPosition },
+        simulate::history::GameHistory,
+    };
+
+    use super::{ record_move, GameStore, StoredGame };
+
+    fn stored_game(game_id: &str, moves: &[usize], updated_at: u64) -> StoredGame {
+        let mut game = IGame::new();
+        let mut history = GameHistory::new();
+        for &move_index in moves {
+            let position = IPosition::position_from_piece_index(move_index as i8).unwrap();
+            game.make_move_at_position(&position);
+            record_move(&mut history, &game, move_index);
+        }
+        StoredGame {
+            game_id: game_id.to_string(),
+            players: ["human".to_string(), "rule_based".to_string()],
+            history,
+            finished: false,
+            winner: None,
+            resigned: false,
+            created_at: 1,
+            updated_at,
+            rejoin_tokens: [None, None],
+        }
+    }
+
+    #[test]
+    fn saves_and_loads_games() {
+        let store = GameStore::open_in_memory().unwrap();
+        assert_eq!(store.get("missing").unwrap(), None);
+        let mut game = stored_game("first", &[19, 18], 10);
+        store.save(&game).unwrap();
+        assert_eq!(store.get("first").unwrap(), Some(game.clone()));
+        assert_eq!(game.history.agent0_score, 3);
+        assert_eq!(game.history.agent1_score, 3);
+
+        // saving again replaces the game
+        game.finished = true;
+        game.winner = Some(1);
+        game.updated_at = 20;
+        store.save(&game).unwrap();
+        assert_eq!(store.get("first").unwrap(), Some(game.clone()));
+
+        // inserting never replaces a stored game
+        assert!(!store.insert(&stored_game("first", &[], 30)).unwrap());
+        assert_eq!(store.get("first").unwrap(), Some(game));
+        assert!(store.insert(&stored_game("second", &[], 30)).unwrap());
+        assert!(store.get("second").unwrap().is_some());
+    }
+
+    #[test]
+    fn keeps_seat_tokens_from_clients() {
+        let store = GameStore::open_in_memory().unwrap();
+        let mut game = stored_game("seats", &[19], 10);
+        game.rejoin_tokens = [Some("black".to_string()), Some("white".to_string())];
+        store.save(&game).unwrap();
+        let loaded = store.get("seats").unwrap().unwrap();
+        assert_eq!(loaded.rejoin_tokens, game.rejoin_tokens);
+        assert!(!serde_json::to_string(&loaded).unwrap().contains("black\""));
+    }
+
+    #[test]
+    fn adds_seat_columns_to_old_databases() {
+        let connection = rusqlite::Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE games (
+                    game_id TEXT PRIMARY KEY,
+                    black TEXT NOT NULL,
+                    white TEXT NOT NULL,
+                    history TEXT NOT NULL,
+                    total_moves INTEGER NOT NULL,
+                    black_score INTEGER NOT NULL,
+                    white_score INTEGER NOT NULL,
+                    finished INTEGER NOT NULL,
+                    winner INTEGER,
+                    resigned INTEGER NOT NULL,
+                    created_at INTEGER NOT NULL,
+                    updated_at INTEGER NOT NULL
+                );"
+            )
+            .unwrap();
+        let store = GameStore::from_connection(connection).unwrap();
+        let game = stored_game("old", &[19], 10);
+        store.save(&game).unwrap();
+        assert_eq!(store.get("old").unwrap(), Some(game));
+    }
+
+    #[test]
+    fn lists_recent_games_first() {
+        let store = GameStore::open_in_memory().unwrap();
+        store.save(&stored_game("old", &[19], 10)).unwrap();
+        store.save(&stored_game("new", &[19, 18], 30)).unwrap();
+        store.save(&stored_game("middle", &[], 20)).unwrap();
+        let recent = store.recent(2).unwrap();
+        let ids: Vec<&str> = recent
+            .iter()
+            .map(|game| game.game_id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["new", "middle"]);
+        assert_eq!(recent[0].total_moves, 2);
+        assert_eq!(recent[0].scores, [3, 3]);
+    }
+
+    #[actix_web::test]
+    async fn saves_queued_games_in_order() {
+        use std::time::Duration;
+
+        use actix_web::{ rt::time::sleep, web };
+
+        use super::spawn_saver;
+
+        let store = web::Data::new(GameStore::open_in_memory().unwrap());
+        let saver = spawn_saver(store.clone());
+        saver.send(stored_game("queued", &[19], 10)).unwrap();
+        saver.send(stored_game("queued", &[19, 18], 20)).unwrap();
+        for _ in 0..100 {
+            if store.get("queued").unwrap().is_some_and(|game| game.updated_at == 20) {
+                break;
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+        let saved = store.get("queued").unwrap().unwrap();
+        assert_eq!(saved.history.move_history, vec![19, 18]);
+    }
+
+    #[actix_web::test]
+    async fn serves_stored_games_over_http() {
+        use actix_web::{ test, web, App };
+        use othello_agent::gameplay::constants::NULL_MOVE_INDEX;
+
+        use super::{ game_transcript, recent_games, stored_game as stored_game_handler };
+
+        let store = web::Data::new(GameStore::open_in_memory().unwrap());
+        store.save(&stored_game("abc", &[19, 18], 10)).unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(store.clone())
+                .service(recent_games)
+                .service(game_transcript)
+                .service(stored_game_handler)
+        ).await;
+
+        let request = test::TestRequest::get().uri("/history?limit=5").to_request();
+        let recent: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(recent[0]["game_id"], "abc");
+
+        let request = test::TestRequest::get().uri("/history/abc").to_request();
+        let game: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(game["history"]["move_history"], serde_json::json!([19, 18]));
+
+        let request = test::TestRequest::get().uri("/history/abc/transcript").to_request();
+        assert_eq!(test::call_and_read_body(&app, request).await, "d3c3");
+
+        // passes are written explicitly
+        let mut passed = stored_game("passed", &[19, 18], 20);
+        passed.history.move_history.push(NULL_MOVE_INDEX);
+        store.save(&passed).unwrap();
+        let request = test::TestRequest::get().uri("/history/passed/transcript").to_request();
+        assert_eq!(test::call_and_read_body(&app, request).await, "d3c3pa");
+
+        // a corrupt move is not written as a pass
+        let mut corrupt = stored_game("corrupt", &[19], 30);
+        corrupt.history.move_history.push(75);
+        store.save(&corrupt).unwrap();
+        let request = test::TestRequest::get().uri("/history/corrupt/transcript").to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 500);
+
+        // only safe characters of the id make it into the header
+        store.save(&stored_game("a\"b;c", &[19], 40)).unwrap();
+        let request = test::TestRequest::get().uri("/history/a%22b%3Bc/transcript").to_request();
+        let response = test::call_service(&app, request).await;
+        let disposition = response.headers().get("content-disposition").unwrap();
+        assert_eq!(disposition, "attachment; filename=\"abc.txt\"");
+
+        let request = test::TestRequest::get().uri("/history/missing").to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), 404);
+    }
+}